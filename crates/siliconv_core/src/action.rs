@@ -1,6 +1,9 @@
 //! Module containing action-related types for replays.
 
+use std::{fmt::Display, str::FromStr};
+
 /// A player button.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum PlayerButton {
     /// A jump. Equivalent to the button 1.
     Jump,
@@ -13,6 +16,7 @@ pub enum PlayerButton {
 /// A restart type.
 ///
 /// One of [`RestartType::Restart`], [`RestartType::RestartFull`] or [`RestartType::Death`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum RestartType {
     /// The [`RestartType::Restart`] type. Typically respawns at the last platformer checkpoint.
     Restart,
@@ -23,6 +27,7 @@ pub enum RestartType {
 }
 
 /// An action in the replay.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Action {
     /// An empty action. Doesn't do anything.
     Empty,
@@ -52,6 +57,7 @@ pub enum Action {
 }
 
 /// A time point at which to execute actions.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TimePoint {
     /// A frame time point.
     ///
@@ -61,6 +67,8 @@ pub enum TimePoint {
     /// - `playLayer->m_levelTime / tps`
     /// - custom frame counters based on update ticks (personally recommend this one the most :D)
     ///
+    /// The method a replay uses is stored as its [`FrameCountingMethod`].
+    ///
     /// If you're designing a new replay format, opt to use frames.
     Frame(u64),
 
@@ -88,6 +96,7 @@ pub enum TimePoint {
 /// Player position data for "frame-fixed" replays.
 ///
 /// When designing a new replay format, consider if you really need this functionality.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct PlayerPosition {
     /// The X position of the player.
     pub x: f64,
@@ -104,17 +113,21 @@ pub struct PlayerPosition {
 
 /// Position data for both players used for "frame-fixed" replays.
 ///
+/// Some formats only record the player who clicked, so either player may be missing.
+///
 /// When designing a new replay format, consider if you really need this functionality.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Position {
     /// Player 1 position data.
-    pub player1: PlayerPosition,
+    pub player1: Option<PlayerPosition>,
     /// Player 2 position data.
-    pub player2: PlayerPosition,
+    pub player2: Option<PlayerPosition>,
 }
 
 /// An action with a given time point.
 ///
 /// May contain optional player position data.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct TimedAction {
     /// The time point at which to perform this action.
     pub time: TimePoint,
@@ -123,3 +136,71 @@ pub struct TimedAction {
     /// The position at which both players are in this time point.
     pub position: Option<Position>,
 }
+
+/// How a bot counts the frames of [`TimePoint::Frame`] time points.
+///
+/// The methods agree on the length of a frame but not on which tick is frame 0, so the same
+/// input can be stored one frame apart by two bots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FrameCountingMethod {
+    /// The method isn't known. Frames are never translated from or to it.
+    #[default]
+    Unknown,
+    /// `playLayer->m_currentProgress`, which is incremented before a tick's inputs are
+    /// processed. The first tick is frame 1.
+    CurrentProgress,
+    /// `playLayer->m_levelTime / tps`, the number of finished ticks. The first tick is frame 0.
+    LevelTime,
+    /// A custom counter of update ticks. The first tick is frame 0.
+    TickCounter,
+}
+
+impl FrameCountingMethod {
+    /// The frame the first tick of a level is counted as, if known.
+    #[must_use]
+    pub const fn first_frame(self) -> Option<u64> {
+        match self {
+            Self::Unknown => None,
+            Self::CurrentProgress => Some(1),
+            Self::LevelTime | Self::TickCounter => Some(0),
+        }
+    }
+
+    /// The frame counted with `target` for the same tick as `frame` counted with this method.
+    ///
+    /// Frames are left as they are if either method is unknown. Frames before the target's
+    /// first frame are clamped to it.
+    #[must_use]
+    pub const fn translate(self, frame: u64, target: Self) -> u64 {
+        match (self.first_frame(), target.first_frame()) {
+            (Some(from), Some(to)) => frame.saturating_sub(from).saturating_add(to),
+            _ => frame,
+        }
+    }
+}
+
+impl Display for FrameCountingMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Unknown => "unknown",
+            Self::CurrentProgress => "progress",
+            Self::LevelTime => "level-time",
+            Self::TickCounter => "ticks",
+        })
+    }
+}
+
+impl FromStr for FrameCountingMethod {
+    type Err = String;
+
+    /// Parse a method as written by its [`Display`] implementation.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "unknown" => Self::Unknown,
+            "progress" => Self::CurrentProgress,
+            "level-time" => Self::LevelTime,
+            "ticks" => Self::TickCounter,
+            _ => return Err(format!("invalid frame counting method {s:?}")),
+        })
+    }
+}
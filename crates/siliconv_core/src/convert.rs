@@ -0,0 +1,756 @@
+//! Conversion between replay formats.
+//!
+//! Before a replay is written in another format, [`normalize`] removes or approximates
+//! everything the target can't store, based on its [`Capabilities`]. What was lost is
+//! collected in a [`LossReport`].
+
+use std::{collections::BTreeMap, fmt::Display};
+
+use crate::{
+    action::{Action, FrameCountingMethod, PlayerButton, RestartType, TimePoint, TimedAction},
+    format::{Capabilities, Format},
+    meta::{MetaEncodable, MetaMap},
+    replay::Replay,
+    timeline::{self, TimeKind, TpsTimeline},
+    xpos::{self, PositionTrack, SpeedModel},
+};
+
+/// Something a conversion dropped or approximated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Loss {
+    /// Positions removed from actions, or the position of one of their players.
+    PositionsDiscarded,
+    /// Rotations removed from positions.
+    RotationsDiscarded,
+    /// Velocities removed from positions.
+    VelocitiesDiscarded,
+    /// Positions rounded to `f32`.
+    PositionsRounded,
+    /// Bugpoint actions removed.
+    BugpointsRemoved,
+    /// Seeds removed from restart actions.
+    SeedsRemoved,
+    /// Restart actions removed.
+    RestartsRemoved,
+    /// Death actions removed.
+    DeathsRemoved,
+    /// Player 2 actions removed.
+    Player2Removed,
+    /// Platformer left and right actions removed.
+    PlatformerRemoved,
+    /// TPS changes removed, with the frames following them rescaled to the starting TPS.
+    TpsChangesBaked,
+    /// Timestamps converted to frames.
+    TimesRetimed,
+    /// X positions converted to frames.
+    XPosRetimed,
+    /// Frames converted to x positions.
+    FramesToXPos,
+    /// X positions whose frame may be wrong.
+    AmbiguousPlacements,
+    /// Frames moved to the target's frame counting method.
+    FramesTranslated,
+    /// Frames before the first frame of their counting method, moved to the target's first
+    /// frame.
+    FramesClamped,
+}
+
+impl Loss {
+    const fn description(self) -> &'static str {
+        match self {
+            Loss::PositionsDiscarded => "positions discarded",
+            Loss::RotationsDiscarded => "rotations discarded",
+            Loss::VelocitiesDiscarded => "velocities discarded",
+            Loss::PositionsRounded => "positions rounded",
+            Loss::BugpointsRemoved => "bugpoints removed",
+            Loss::SeedsRemoved => "restart seeds removed",
+            Loss::RestartsRemoved => "restarts removed",
+            Loss::DeathsRemoved => "deaths removed",
+            Loss::Player2Removed => "player 2 actions removed",
+            Loss::PlatformerRemoved => "platformer actions removed",
+            Loss::TpsChangesBaked => "tps changes baked",
+            Loss::TimesRetimed => "timestamps converted to frames",
+            Loss::XPosRetimed => "x positions converted to frames",
+            Loss::FramesToXPos => "frames converted to x positions",
+            Loss::AmbiguousPlacements => "ambiguous x position placements",
+            Loss::FramesTranslated => "frames translated to another counting method",
+            Loss::FramesClamped => "frames clamped to the first frame",
+        }
+    }
+}
+
+/// Everything a conversion dropped or approximated, with the number of affected actions.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LossReport {
+    losses: BTreeMap<Loss, usize>,
+}
+
+impl LossReport {
+    /// Create an empty report.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record that `count` actions were affected by a loss.
+    pub fn record(&mut self, loss: Loss, count: usize) {
+        if count > 0 {
+            *self.losses.entry(loss).or_default() += count;
+        }
+    }
+
+    /// The number of actions affected by a loss.
+    #[must_use]
+    pub fn count(&self, loss: Loss) -> usize {
+        self.losses.get(&loss).copied().unwrap_or_default()
+    }
+
+    /// Whether nothing was lost.
+    #[must_use]
+    pub fn is_lossless(&self) -> bool {
+        self.losses.is_empty()
+    }
+
+    /// Iterate over all losses and their counts.
+    pub fn iter(&self) -> impl Iterator<Item = (Loss, usize)> + '_ {
+        self.losses.iter().map(|(loss, count)| (*loss, *count))
+    }
+}
+
+impl Display for LossReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_lossless() {
+            return f.write_str("lossless");
+        }
+
+        for (i, (loss, count)) in self.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{count} {}", loss.description())?;
+        }
+        Ok(())
+    }
+}
+
+/// Settings of the normalisation passes.
+#[derive(Debug, Clone, Default)]
+pub struct ConvertOptions {
+    /// Model placing x position actions the player's positions don't reach.
+    pub speed_model: SpeedModel,
+    /// Positions of Player 1 to move actions between x positions and frames with. When
+    /// missing, the positions recorded on the replay's frame-timed actions are used.
+    ///
+    /// Formats timed by x position record no frames, so replays read from them only carry a
+    /// track when one is passed here, e.g. collected from another replay of the same attempt.
+    pub track: Option<PositionTrack>,
+}
+
+/// Prepare a replay to be written as `target` with the default options.
+///
+/// See [`normalize_with`].
+pub fn normalize(replay: &mut Replay, target: Format) -> LossReport {
+    normalize_with(replay, target, &ConvertOptions::default())
+}
+
+/// Prepare a replay to be written as `target`, removing what the format can't store.
+///
+/// Nothing is removed that the target supports, so converting to the plain text format is
+/// always lossless. Retimed actions are put back in order, and a TPS only known from the
+/// `delta` metadata field is written to `tps` as well.
+pub fn normalize_with(replay: &mut Replay, target: Format, options: &ConvertOptions) -> LossReport {
+    let capabilities = target.descriptor().capabilities;
+    let mut report = LossReport::new();
+    // Whether any action's time point changed, so they may be out of order.
+    let mut retimed = false;
+
+    if !capabilities.contains(Capabilities::XPOS_TIMING)
+        && replay
+            .actions
+            .iter()
+            .any(|timed| matches!(timed.time, TimePoint::XPos(_)))
+    {
+        let track = match &options.track {
+            Some(track) => track.clone(),
+            None => PositionTrack::from_replay(replay),
+        };
+        let placed = xpos::retime_xpos(replay, &options.speed_model, &track);
+        retimed |= !placed.placements.is_empty();
+        report.record(Loss::XPosRetimed, placed.placements.len());
+        report.record(Loss::AmbiguousPlacements, placed.ambiguous().count());
+    }
+
+    // Timestamps depend on TPS changes, so they are converted before those are baked.
+    if !capabilities.contains(Capabilities::TIME_TIMING)
+        && replay
+            .actions
+            .iter()
+            .any(|timed| matches!(timed.time, TimePoint::Time(_)))
+    {
+        let changed = timeline::retime(replay, TimeKind::Frame).retimed;
+        retimed |= !changed.is_empty();
+        report.record(
+            Loss::TimesRetimed,
+            changed
+                .iter()
+                .filter(|retimed| matches!(retimed.from, TimePoint::Time(_)))
+                .count(),
+        );
+    }
+
+    if !capabilities.contains(Capabilities::TPS_CHANGES) {
+        let baked = bake_tps_changes(replay);
+        retimed |= baked > 0;
+        report.record(Loss::TpsChangesBaked, baked);
+    }
+
+    let translated = translate_frames(replay, target.descriptor().frame_counting);
+    retimed |= translated.moved > 0 || translated.clamped > 0;
+    report.record(Loss::FramesTranslated, translated.moved);
+    report.record(Loss::FramesClamped, translated.clamped);
+
+    if !capabilities.contains(Capabilities::FRAME_TIMING)
+        && replay
+            .actions
+            .iter()
+            .any(|timed| matches!(timed.time, TimePoint::Frame(_)))
+    {
+        let track = match &options.track {
+            Some(track) => track.clone(),
+            None => PositionTrack::from_replay(replay),
+        };
+        let placed = xpos::frames_to_xpos(replay, &options.speed_model, &track);
+        retimed |= !placed.placements.is_empty();
+        report.record(Loss::FramesToXPos, placed.placements.len());
+        report.record(Loss::AmbiguousPlacements, placed.ambiguous().count());
+    }
+
+    if retimed {
+        sort_by_time(replay);
+    }
+    carry_tps(replay);
+
+    strip_positions(replay, capabilities, &mut report);
+
+    if !capabilities.contains(Capabilities::SEEDS) {
+        let mut removed = 0;
+        for timed in &mut replay.actions {
+            if let Action::Restart { seed, .. } = &mut timed.action {
+                removed += usize::from(seed.take().is_some());
+            }
+        }
+        report.record(Loss::SeedsRemoved, removed);
+    }
+
+    remove_unsupported_actions(replay, capabilities, &mut report);
+
+    report
+}
+
+/// Stably sort the actions by time, if they are all frames or all x positions.
+fn sort_by_time(replay: &mut Replay) {
+    let actions = &mut replay.actions;
+    let frame = |timed: &TimedAction| match timed.time {
+        TimePoint::Frame(frame) => Some(frame),
+        _ => None,
+    };
+
+    if actions.iter().all(|timed| frame(timed).is_some()) {
+        actions.sort_by_key(frame);
+    } else if let Some(positions) = actions
+        .iter()
+        .map(|timed| match timed.time {
+            TimePoint::XPos(x) => Some(x),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()
+    {
+        let mut keyed = positions
+            .into_iter()
+            .zip(actions.drain(..))
+            .collect::<Vec<_>>();
+        keyed.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        actions.extend(keyed.into_iter().map(|(_, timed)| timed));
+    }
+}
+
+/// Write the replay's TPS to the `tps` metadata field if it is only known from `delta`, so
+/// targets reading `tps` don't fall back to their default.
+fn carry_tps(replay: &mut Replay) {
+    if replay.meta.fields().contains_key("tps") {
+        return;
+    }
+    let Some(tps) = timeline::meta_tps(replay) else {
+        return;
+    };
+
+    let mut meta = MetaMap::from_meta(replay.meta.as_ref());
+    meta.insert("tps", tps.encode());
+    replay.meta = Box::new(meta);
+}
+
+/// Remove the actions the target can't store.
+fn remove_unsupported_actions(
+    replay: &mut Replay,
+    capabilities: Capabilities,
+    report: &mut LossReport,
+) {
+    let removals = [
+        (
+            Capabilities::BUGPOINTS,
+            Loss::BugpointsRemoved,
+            (|action| matches!(action, Action::Bugpoint)) as fn(&Action) -> bool,
+        ),
+        (Capabilities::RESTARTS, Loss::RestartsRemoved, |action| {
+            matches!(
+                action,
+                Action::Restart {
+                    restart_type: RestartType::Restart | RestartType::RestartFull,
+                    ..
+                }
+            )
+        }),
+        (Capabilities::DEATHS, Loss::DeathsRemoved, |action| {
+            matches!(
+                action,
+                Action::Restart {
+                    restart_type: RestartType::Death,
+                    ..
+                }
+            )
+        }),
+        (Capabilities::PLAYER2, Loss::Player2Removed, |action| {
+            matches!(action, Action::Player { player2: true, .. })
+        }),
+        (
+            Capabilities::PLATFORMER,
+            Loss::PlatformerRemoved,
+            |action| {
+                matches!(
+                    action,
+                    Action::Player {
+                        button: PlayerButton::Left | PlayerButton::Right,
+                        ..
+                    }
+                )
+            },
+        ),
+    ];
+
+    for (capability, loss, unsupported) in removals {
+        if capabilities.contains(capability) {
+            continue;
+        }
+
+        let before = replay.actions.len();
+        replay.actions.retain(|timed| !unsupported(&timed.action));
+        report.record(loss, before - replay.actions.len());
+    }
+}
+
+/// Remove the parts of positions the target can't store.
+#[allow(clippy::cast_possible_truncation)]
+fn strip_positions(replay: &mut Replay, capabilities: Capabilities, report: &mut LossReport) {
+    if !capabilities.contains(Capabilities::POSITIONS) {
+        let mut discarded = 0;
+        for timed in &mut replay.actions {
+            discarded += usize::from(timed.position.take().is_some());
+        }
+        report.record(Loss::PositionsDiscarded, discarded);
+        return;
+    }
+
+    let (mut discarded, mut rotations, mut velocities, mut rounded) = (0, 0, 0, 0);
+    for timed in &mut replay.actions {
+        let Some(mut position) = timed.position else {
+            continue;
+        };
+        let before = position;
+
+        if !capabilities.contains(Capabilities::IDLE_POSITIONS) {
+            match timed.action {
+                Action::Player { player2: false, .. } => position.player2 = None,
+                Action::Player { player2: true, .. } => position.player1 = None,
+                _ => (position.player1, position.player2) = (None, None),
+            }
+        }
+        if !capabilities.contains(Capabilities::PARTIAL_POSITIONS)
+            && (position.player1.is_none() || position.player2.is_none())
+        {
+            (position.player1, position.player2) = (None, None);
+        }
+        discarded += usize::from(position != before);
+
+        let (mut rotation, mut velocity, mut round) = (false, false, false);
+        for player in [&mut position.player1, &mut position.player2]
+            .into_iter()
+            .flatten()
+        {
+            if !capabilities.contains(Capabilities::ROTATIONS) {
+                rotation |= player.rotation.take().is_some();
+            }
+            if !capabilities.contains(Capabilities::X_VELOCITIES) {
+                velocity |= player.vel_x.take().is_some();
+            }
+            if !capabilities.contains(Capabilities::Y_VELOCITIES) {
+                velocity |= player.vel_y.take().is_some();
+            }
+            if !capabilities.contains(Capabilities::PRECISE_POSITIONS) {
+                let (x, y) = (f64::from(player.x as f32), f64::from(player.y as f32));
+                round |= (x, y) != (player.x, player.y);
+                (player.x, player.y) = (x, y);
+            }
+        }
+        rotations += usize::from(rotation);
+        velocities += usize::from(velocity);
+        rounded += usize::from(round);
+
+        timed.position =
+            (position.player1.is_some() || position.player2.is_some()).then_some(position);
+    }
+
+    report.record(Loss::PositionsDiscarded, discarded);
+    report.record(Loss::RotationsDiscarded, rotations);
+    report.record(Loss::VelocitiesDiscarded, velocities);
+    report.record(Loss::PositionsRounded, rounded);
+}
+
+/// The number of frames moved by [`translate_frames`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TranslatedFrames {
+    /// Frames moved to another number for the same tick.
+    pub moved: usize,
+    /// Frames before the replay's first frame, moved to the target's first frame. They may
+    /// now share a frame with the actions of the first tick.
+    pub clamped: usize,
+}
+
+/// Move every frame to the same tick counted with `method`, and switch the replay to it.
+///
+/// Nothing changes if either the replay's or the target method is unknown.
+pub fn translate_frames(replay: &mut Replay, method: FrameCountingMethod) -> TranslatedFrames {
+    let mut translated = TranslatedFrames::default();
+    let (Some(first_frame), Some(_)) = (replay.frame_counting.first_frame(), method.first_frame())
+    else {
+        return translated;
+    };
+
+    for timed in &mut replay.actions {
+        if let TimePoint::Frame(frame) = timed.time {
+            let moved = replay.frame_counting.translate(frame, method);
+            if frame < first_frame {
+                translated.clamped += 1;
+            } else if moved != frame {
+                translated.moved += 1;
+            }
+            timed.time = TimePoint::Frame(moved);
+        }
+    }
+    replay.frame_counting = method;
+
+    translated
+}
+
+/// Remove TPS changes, rescaling every frame as if the whole replay ran at the starting TPS.
+/// Returns the number of removed TPS changes.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+fn bake_tps_changes(replay: &mut Replay) -> usize {
+    let timeline = TpsTimeline::from_replay(replay);
+    let before = replay.actions.len();
+    replay
+        .actions
+        .retain(|timed| !matches!(timed.action, Action::TPS { .. }));
+    let baked = before - replay.actions.len();
+
+    if !timeline.is_constant() {
+        let tps = timeline.initial_tps();
+        for timed in &mut replay.actions {
+            if let TimePoint::Frame(frame) = timed.time {
+                let time = timeline.frame_to_time(frame as f64);
+                timed.time = TimePoint::Frame((time * tps).round() as u64);
+            }
+        }
+    }
+
+    baked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        action::{FrameCountingMethod, PlayerPosition, Position, TimedAction},
+        version::GameVersion,
+    };
+
+    fn timed(frame: u64, action: Action) -> TimedAction {
+        TimedAction {
+            time: TimePoint::Frame(frame),
+            action,
+            position: None,
+        }
+    }
+
+    fn click(button: PlayerButton, player2: bool) -> Action {
+        Action::Player {
+            button,
+            hold: true,
+            player2,
+        }
+    }
+
+    fn replay(actions: Vec<TimedAction>) -> Replay {
+        let mut meta = MetaMap::new();
+        meta.insert("tps", "240");
+
+        Replay {
+            meta: Box::new(meta),
+            actions,
+            format: Format::PlainText,
+            game_version: GameVersion::new(22, 74),
+            frame_counting: FrameCountingMethod::Unknown,
+        }
+    }
+
+    #[test]
+    fn test_normalize_removes_unsupported_actions() {
+        let mut replay = replay(vec![
+            timed(10, click(PlayerButton::Jump, false)),
+            timed(20, click(PlayerButton::Left, false)),
+            timed(30, click(PlayerButton::Jump, true)),
+            timed(
+                40,
+                Action::Restart {
+                    restart_type: RestartType::Death,
+                    seed: Some(7),
+                },
+            ),
+            timed(50, Action::Bugpoint),
+        ]);
+
+        let report = normalize(&mut replay.to_generic(), Format::PlainText);
+        assert!(report.is_lossless());
+
+        let report = normalize(&mut replay, Format::Rush);
+        assert_eq!(
+            replay.actions,
+            vec![
+                timed(10, click(PlayerButton::Jump, false)),
+                timed(30, click(PlayerButton::Jump, true)),
+            ]
+        );
+        assert_eq!(report.count(Loss::PlatformerRemoved), 1);
+        assert_eq!(report.count(Loss::Player2Removed), 0);
+        assert_eq!(
+            report.to_string(),
+            "1 bugpoints removed, 1 restart seeds removed, 1 deaths removed, \
+             1 platformer actions removed"
+        );
+    }
+
+    #[test]
+    fn test_normalize_bakes_tps_changes() {
+        let mut replay = replay(vec![
+            timed(100, click(PlayerButton::Jump, false)),
+            timed(120, Action::TPS { tps: 480.0 }),
+            timed(160, click(PlayerButton::Jump, false)),
+            timed(200, Action::TPS { tps: 240.0 }),
+            timed(210, click(PlayerButton::Jump, false)),
+            TimedAction {
+                time: TimePoint::Time(1.0),
+                action: click(PlayerButton::Jump, false),
+                position: None,
+            },
+        ]);
+
+        let report = normalize(&mut replay, Format::GDR1);
+        assert_eq!(report.count(Loss::TpsChangesBaked), 2);
+        assert_eq!(report.count(Loss::TimesRetimed), 1);
+        assert_eq!(
+            replay
+                .actions
+                .iter()
+                .map(|timed| timed.time)
+                .collect::<Vec<_>>(),
+            vec![
+                TimePoint::Frame(100),
+                TimePoint::Frame(140),
+                TimePoint::Frame(170),
+                TimePoint::Frame(240),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_normalize_translates_frame_counting() {
+        let mut replay = replay(vec![
+            timed(0, click(PlayerButton::Jump, false)),
+            timed(1, click(PlayerButton::Jump, false)),
+            timed(10, click(PlayerButton::Jump, false)),
+        ]);
+        replay.frame_counting = FrameCountingMethod::CurrentProgress;
+
+        let report = normalize(&mut replay.to_generic(), Format::PlainText);
+        assert!(report.is_lossless());
+
+        let report = normalize(&mut replay, Format::Slc3);
+        assert_eq!(report.count(Loss::FramesTranslated), 2);
+        assert_eq!(report.count(Loss::FramesClamped), 1);
+        assert_eq!(replay.frame_counting, FrameCountingMethod::TickCounter);
+        assert_eq!(
+            replay
+                .actions
+                .iter()
+                .map(|timed| timed.time)
+                .collect::<Vec<_>>(),
+            vec![
+                TimePoint::Frame(0),
+                TimePoint::Frame(0),
+                TimePoint::Frame(9),
+            ]
+        );
+
+        normalize(&mut replay, Format::GDR2);
+        assert_eq!(replay.actions[2].time, TimePoint::Frame(10));
+    }
+
+    #[test]
+    fn test_normalize_strips_position_parts() {
+        let player = |x: f64| PlayerPosition {
+            x: x + 0.1,
+            y: 105.0,
+            rotation: Some(90.0),
+            vel_x: Some(3.0),
+            vel_y: Some(-1.5),
+        };
+        let both = Position {
+            player1: Some(player(10.0)),
+            player2: Some(player(20.0)),
+        };
+        let mut replay = replay(vec![
+            TimedAction {
+                position: Some(both),
+                ..timed(10, click(PlayerButton::Jump, true))
+            },
+            TimedAction {
+                position: Some(both),
+                ..timed(20, Action::Empty)
+            },
+        ]);
+
+        let report = normalize(&mut replay.to_generic(), Format::GDR2);
+        assert!(report.is_lossless());
+
+        let mut mhr = replay.to_generic();
+        let report = normalize(&mut mhr, Format::MHR);
+        assert_eq!(report.count(Loss::PositionsDiscarded), 2);
+        assert_eq!(report.count(Loss::VelocitiesDiscarded), 1);
+        assert_eq!(report.count(Loss::PositionsRounded), 1);
+        let position = mhr.actions[0].position.unwrap();
+        assert_eq!(position.player1, None);
+        let player2 = position.player2.unwrap();
+        assert_eq!(
+            (player2.x, player2.rotation, player2.vel_x, player2.vel_y),
+            (f64::from(20.1_f32), Some(90.0), None, Some(-1.5))
+        );
+        assert_eq!(mhr.actions[1].position, None);
+
+        let report = normalize(&mut replay, Format::XDBot);
+        assert_eq!(report.count(Loss::PositionsDiscarded), 0);
+        assert_eq!(report.count(Loss::RotationsDiscarded), 2);
+        assert_eq!(report.count(Loss::VelocitiesDiscarded), 2);
+        assert_eq!(report.count(Loss::PositionsRounded), 0);
+    }
+
+    #[test]
+    fn test_normalize_keeps_deaths_without_restarts() {
+        let restart = |restart_type| Action::Restart {
+            restart_type,
+            seed: Some(3),
+        };
+        let mut replay = replay(vec![
+            timed(10, restart(RestartType::Death)),
+            timed(20, restart(RestartType::Restart)),
+            timed(30, restart(RestartType::RestartFull)),
+        ]);
+
+        let report = normalize(&mut replay, Format::GDR2);
+        assert_eq!(report.count(Loss::RestartsRemoved), 2);
+        assert_eq!(report.count(Loss::DeathsRemoved), 0);
+        assert_eq!(replay.actions, vec![timed(10, restart(RestartType::Death))]);
+    }
+
+    #[test]
+    fn test_normalize_places_frames_at_xpos() {
+        let mut replay = replay(vec![
+            timed(120, click(PlayerButton::Jump, false)),
+            timed(240, click(PlayerButton::Jump, false)),
+        ]);
+        replay.frame_counting = FrameCountingMethod::LevelTime;
+
+        let report = normalize(&mut replay, Format::ZBotXpos);
+        assert_eq!(report.count(Loss::FramesToXPos), 2);
+        assert!(
+            replay
+                .actions
+                .iter()
+                .all(|timed| matches!(timed.time, TimePoint::XPos(_)))
+        );
+    }
+
+    #[test]
+    fn test_normalize_sorts_retimed_actions() {
+        let mut retimed = replay(vec![
+            timed(100, click(PlayerButton::Jump, false)),
+            TimedAction {
+                time: TimePoint::Time(0.25),
+                action: click(PlayerButton::Jump, true),
+                position: None,
+            },
+            timed(150, click(PlayerButton::Jump, false)),
+        ]);
+
+        normalize(&mut retimed, Format::Slc3);
+        assert_eq!(
+            retimed
+                .actions
+                .iter()
+                .map(|timed| timed.time)
+                .collect::<Vec<_>>(),
+            vec![
+                TimePoint::Frame(60),
+                TimePoint::Frame(100),
+                TimePoint::Frame(150),
+            ]
+        );
+
+        // Actions that weren't retimed are written in their original order.
+        let mut unsorted = replay(vec![
+            timed(100, click(PlayerButton::Jump, false)),
+            timed(50, click(PlayerButton::Jump, true)),
+        ]);
+        assert!(normalize(&mut unsorted, Format::PlainText).is_lossless());
+        assert_eq!(unsorted.actions[0].time, TimePoint::Frame(100));
+    }
+
+    #[test]
+    fn test_normalize_carries_tps_from_delta() {
+        let mut meta = MetaMap::new();
+        meta.insert("delta", "0.016666666666666666");
+        let mut replay = Replay {
+            meta: Box::new(meta),
+            ..replay(Vec::new())
+        };
+
+        assert!(normalize(&mut replay, Format::Slc3).is_lossless());
+        let tps = MetaMap::from_meta(replay.meta.as_ref())
+            .get("tps")
+            .and_then(|tps| tps.parse::<f64>().ok())
+            .unwrap();
+        assert!((tps - 60.0).abs() < 1e-9);
+    }
+}
@@ -2,6 +2,8 @@
 
 use thiserror::Error;
 
+use crate::format::Format;
+
 #[derive(Debug, Error)]
 /// Errors that can occur during replay reading or writing.
 pub enum ReplayError {
@@ -13,6 +15,10 @@ pub enum ReplayError {
     /// Failed to write replay to destination.
     WriteError(String),
 
+    #[error("Unsupported format: {0}")]
+    /// No reader or writer is available for the format.
+    UnsupportedFormat(Format),
+
     /// Generic IO error.
     #[error("IO error: {0}")]
     IOError(#[from] std::io::Error),
@@ -1,5 +1,9 @@
 //! Module containing replay formats.
 
+use std::{fmt::Display, ops::BitOr};
+
+use crate::{action::FrameCountingMethod, version::GameVersion};
+
 /// A replay format.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Format {
@@ -85,3 +89,568 @@ pub enum Format {
     /// ``TCBot`` .tcm format.
     TCBot,
 }
+
+impl Format {
+    /// Every replay format.
+    pub const ALL: &'static [Format] = &[
+        Format::PlainText,
+        Format::OmegaBot,
+        Format::OmegaBot2,
+        Format::OmegaBot3,
+        Format::MHRJson,
+        Format::MHR,
+        Format::TASBot,
+        Format::ZBotXpos,
+        Format::ZBotFrame,
+        Format::ReplayBot,
+        Format::EchoOld,
+        Format::EchoNewJson,
+        Format::EchoNewBinary,
+        Format::YBot1,
+        Format::XBot,
+        Format::Rush,
+        Format::KDBot,
+        Format::GDR1,
+        Format::GDR1Json,
+        Format::GDR2,
+        Format::XDBot,
+        Format::ReplayEngineV1,
+        Format::ReplayEngineV2,
+        Format::ReplayEngineV3,
+        Format::Slc1,
+        Format::Slc2,
+        Format::Slc3,
+        Format::UVBot,
+        Format::TCBot,
+    ];
+
+    /// The descriptor of this format.
+    #[must_use]
+    pub const fn descriptor(self) -> FormatDescriptor {
+        const V21: (GameVersion, GameVersion) =
+            (GameVersion::new(21, 0), GameVersion::new(21, 130));
+        const V22: (GameVersion, GameVersion) = (GameVersion::new(22, 0), GameVersion::new(22, 74));
+
+        let (name, extensions, (min_game_version, max_game_version)): (
+            &'static str,
+            &'static [&'static str],
+            (GameVersion, GameVersion),
+        ) = match self {
+            Format::PlainText => (
+                "Plain text",
+                &["txt"],
+                (GameVersion::universal(), GameVersion::universal()),
+            ),
+            Format::OmegaBot => ("OmegaBot", &["replay"], V21),
+            Format::OmegaBot2 => ("OmegaBot 2", &["replay"], V21),
+            Format::OmegaBot3 => ("OmegaBot 3", &["replay"], V21),
+            Format::MHRJson => ("Mega Hack Replay JSON", &["mhr.json"], V21),
+            Format::MHR => ("Mega Hack Replay", &["mhr"], V21),
+            Format::TASBot => ("TASBot", &["json"], V21),
+            Format::ZBotXpos => ("zBot", &["zbot"], V21),
+            Format::ZBotFrame => ("zBot frame", &["zbf"], V21),
+            Format::ReplayBot => ("ReplayBot", &["replay"], V21),
+            Format::EchoOld => ("Echo (old)", &["echo"], V21),
+            Format::EchoNewJson => ("Echo JSON", &["echo"], V21),
+            Format::EchoNewBinary => ("Echo binary", &["echo"], V21),
+            Format::YBot1 => ("yBot 1", &[], V21),
+            Format::XBot => ("xBot", &["xbot"], V21),
+            Format::Rush => ("Rush", &["rsh"], V21),
+            Format::KDBot => ("KDBot", &["kd"], V21),
+            Format::GDR1 => ("GDR", &["gdr"], V22),
+            Format::GDR1Json => ("GDR JSON", &["gdr.json"], V22),
+            Format::GDR2 => ("GDR2", &["gdr2"], V22),
+            Format::XDBot => ("xdBot", &["xd"], V22),
+            Format::ReplayEngineV1 => ("ReplayEngine 1", &["re"], V22),
+            Format::ReplayEngineV2 => ("ReplayEngine 2", &["re2"], V22),
+            Format::ReplayEngineV3 => ("ReplayEngine 3", &["re3"], V22),
+            Format::Slc1 => ("Silicate 1", &["slc"], V22),
+            Format::Slc2 => ("Silicate 2", &["slc"], V22),
+            Format::Slc3 => ("Silicate 3", &["slc"], V22),
+            Format::UVBot => ("UVBot", &["uv"], V22),
+            Format::TCBot => ("TCBot", &["tcm"], V22),
+        };
+
+        FormatDescriptor {
+            name,
+            extensions,
+            min_game_version,
+            max_game_version,
+            capabilities: self.capabilities(),
+            frame_counting: self.frame_counting(),
+        }
+    }
+
+    /// What this format can store.
+    const fn capabilities(self) -> Capabilities {
+        match self {
+            Format::PlainText => Capabilities::ALL,
+            Format::OmegaBot | Format::ZBotXpos => XPOS_ONLY_CLICKS,
+            Format::OmegaBot2 | Format::ReplayBot | Format::XBot => XPOS_CLICKS,
+            Format::OmegaBot3
+            | Format::TASBot
+            | Format::ZBotFrame
+            | Format::YBot1
+            | Format::Rush
+            | Format::KDBot => CLICKS,
+            Format::MHR | Format::EchoNewBinary => CLICKS.union(CLICKER_POSITIONS),
+            Format::MHRJson | Format::EchoOld | Format::EchoNewJson => CLICKS
+                .union(CLICKER_POSITIONS)
+                .union(Capabilities::PRECISE_POSITIONS),
+            Format::GDR1 | Format::GDR1Json => INPUTS.union(FULL_POSITIONS),
+            // GDR2 only stores deaths, not regular restarts.
+            Format::GDR2 => INPUTS
+                .union(FULL_POSITIONS)
+                .union(Capabilities::TPS_CHANGES)
+                .union(Capabilities::DEATHS)
+                .union(Capabilities::SEEDS),
+            // xdBot stores the x and y of both players, or nothing.
+            Format::XDBot => INPUTS
+                .union(Capabilities::POSITIONS)
+                .union(Capabilities::PRECISE_POSITIONS)
+                .union(Capabilities::IDLE_POSITIONS),
+            Format::ReplayEngineV1 => CLICKS.union(RE_POSITIONS),
+            Format::ReplayEngineV2 => INPUTS.union(RE_POSITIONS),
+            // ReplayEngine 3 stores both players at once, with their x velocities.
+            Format::ReplayEngineV3 => INPUTS
+                .union(RE_POSITIONS.difference(Capabilities::PARTIAL_POSITIONS))
+                .union(Capabilities::X_VELOCITIES),
+            Format::Slc1 | Format::UVBot => INPUTS,
+            Format::Slc2 => INPUTS
+                .union(Capabilities::TPS_CHANGES)
+                .union(Capabilities::RESTARTS)
+                .union(Capabilities::DEATHS),
+            Format::Slc3 => INPUTS
+                .union(Capabilities::TPS_CHANGES)
+                .union(Capabilities::RESTARTS)
+                .union(Capabilities::DEATHS)
+                .union(Capabilities::SEEDS),
+            Format::TCBot => Capabilities::ALL
+                .difference(FULL_POSITIONS)
+                .difference(Capabilities::XPOS_TIMING)
+                .difference(Capabilities::TIME_TIMING),
+        }
+    }
+
+    /// How this format's frames are counted.
+    const fn frame_counting(self) -> FrameCountingMethod {
+        // Geode based 2.2 bots read the play layer's progress, 2.1 bots its level time.
+        match self {
+            Format::PlainText => FrameCountingMethod::Unknown,
+            Format::GDR1
+            | Format::GDR1Json
+            | Format::GDR2
+            | Format::XDBot
+            | Format::ReplayEngineV1
+            | Format::ReplayEngineV2
+            | Format::ReplayEngineV3 => FrameCountingMethod::CurrentProgress,
+            Format::Slc1 | Format::Slc2 | Format::Slc3 | Format::UVBot | Format::TCBot => {
+                FrameCountingMethod::TickCounter
+            }
+            _ => FrameCountingMethod::LevelTime,
+        }
+    }
+
+    /// Features of this format that `target` can't store.
+    ///
+    /// An empty result means converting to `target` doesn't lose anything by design.
+    #[must_use]
+    pub const fn lost_capabilities(self, target: Format) -> Capabilities {
+        self.descriptor()
+            .capabilities
+            .difference(target.descriptor().capabilities)
+    }
+
+    /// Whether a file name ends with one of this format's extensions.
+    #[must_use]
+    pub fn matches_file_name(self, file_name: &str) -> bool {
+        let file_name = file_name.to_ascii_lowercase();
+        self.descriptor().extensions.iter().any(|extension| {
+            file_name
+                .strip_suffix(extension)
+                .is_some_and(|stem| stem.len() > 1 && stem.ends_with('.'))
+        })
+    }
+}
+
+impl Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.descriptor().name)
+    }
+}
+
+/// Jump clicks of both players timed by frame.
+const CLICKS: Capabilities = Capabilities::PLAYER2.union(Capabilities::FRAME_TIMING);
+/// Platformer inputs of both players timed by frame.
+const INPUTS: Capabilities = CLICKS.union(Capabilities::PLATFORMER);
+/// Jump clicks timed by frame or x position.
+const XPOS_CLICKS: Capabilities = CLICKS.union(Capabilities::XPOS_TIMING);
+/// Jump clicks timed by x position only.
+const XPOS_ONLY_CLICKS: Capabilities = Capabilities::PLAYER2.union(Capabilities::XPOS_TIMING);
+/// Every part of a position.
+const FULL_POSITIONS: Capabilities = Capabilities::POSITIONS
+    .union(Capabilities::ROTATIONS)
+    .union(Capabilities::X_VELOCITIES)
+    .union(Capabilities::Y_VELOCITIES)
+    .union(Capabilities::PRECISE_POSITIONS)
+    .union(Capabilities::IDLE_POSITIONS)
+    .union(Capabilities::PARTIAL_POSITIONS);
+/// `f32` positions of the clicking player, as stored by Mega Hack and Echo.
+const CLICKER_POSITIONS: Capabilities = Capabilities::POSITIONS
+    .union(Capabilities::ROTATIONS)
+    .union(Capabilities::Y_VELOCITIES)
+    .union(Capabilities::PARTIAL_POSITIONS);
+/// `f32` positions of either player without x velocities, as stored by ``ReplayEngine``.
+const RE_POSITIONS: Capabilities = Capabilities::POSITIONS
+    .union(Capabilities::ROTATIONS)
+    .union(Capabilities::Y_VELOCITIES)
+    .union(Capabilities::IDLE_POSITIONS)
+    .union(Capabilities::PARTIAL_POSITIONS);
+
+/// Static information about a replay format.
+#[derive(Debug, Clone, Copy)]
+pub struct FormatDescriptor {
+    /// Human readable name of the format.
+    pub name: &'static str,
+    /// File extensions, without the leading dot.
+    ///
+    /// Multi-part extensions such as `mhr.json` are listed as a whole.
+    pub extensions: &'static [&'static str],
+    /// Oldest game version the format targets.
+    pub min_game_version: GameVersion,
+    /// Newest game version the format targets.
+    pub max_game_version: GameVersion,
+    /// What the format can store.
+    pub capabilities: Capabilities,
+    /// How the format's frames are counted. Unknown for formats that store the method.
+    pub frame_counting: FrameCountingMethod,
+}
+
+impl FormatDescriptor {
+    /// Whether a replay for `version` fits this format.
+    ///
+    /// Universal versions fit every format, and formats targeting a universal version take any
+    /// replay.
+    #[must_use]
+    pub const fn supports_game_version(&self, version: GameVersion) -> bool {
+        const fn key(version: GameVersion) -> u64 {
+            (version.major as u64) << 32 | version.minor as u64
+        }
+
+        version.is_universal()
+            || self.min_game_version.is_universal()
+            || (key(self.min_game_version) <= key(version)
+                && key(version) <= key(self.max_game_version))
+    }
+}
+
+/// A set of replay features a format can store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Capabilities(u32);
+
+impl Capabilities {
+    /// X and y positions of players alongside actions.
+    pub const POSITIONS: Self = Self(1);
+    /// Actions timed by x position.
+    pub const XPOS_TIMING: Self = Self(1 << 1);
+    /// TPS changes in the middle of a replay.
+    pub const TPS_CHANGES: Self = Self(1 << 2);
+    /// Player 2 actions.
+    pub const PLAYER2: Self = Self(1 << 3);
+    /// Platformer left and right actions.
+    pub const PLATFORMER: Self = Self(1 << 4);
+    /// Bugpoints.
+    pub const BUGPOINTS: Self = Self(1 << 5);
+    /// Seeds on restarts.
+    pub const SEEDS: Self = Self(1 << 6);
+    /// Restarts, other than deaths.
+    pub const RESTARTS: Self = Self(1 << 7);
+    /// Actions timed in seconds.
+    pub const TIME_TIMING: Self = Self(1 << 8);
+    /// Actions timed by frame.
+    pub const FRAME_TIMING: Self = Self(1 << 9);
+    /// Deaths.
+    pub const DEATHS: Self = Self(1 << 10);
+    /// Player rotations in positions.
+    pub const ROTATIONS: Self = Self(1 << 11);
+    /// Player x velocities in positions.
+    pub const X_VELOCITIES: Self = Self(1 << 12);
+    /// Player y velocities in positions.
+    pub const Y_VELOCITIES: Self = Self(1 << 13);
+    /// Positions as `f64`. Without it, they are rounded to `f32`.
+    pub const PRECISE_POSITIONS: Self = Self(1 << 14);
+    /// Positions of players that aren't clicking, including on actions that aren't clicks.
+    pub const IDLE_POSITIONS: Self = Self(1 << 15);
+    /// Positions of only one of the players.
+    pub const PARTIAL_POSITIONS: Self = Self(1 << 16);
+
+    /// No capabilities.
+    pub const NONE: Self = Self(0);
+    /// Every capability.
+    pub const ALL: Self = Self((1 << 17) - 1);
+
+    const NAMES: [(Self, &'static str); 17] = [
+        (Self::POSITIONS, "positions"),
+        (Self::ROTATIONS, "rotations"),
+        (Self::X_VELOCITIES, "x velocities"),
+        (Self::Y_VELOCITIES, "y velocities"),
+        (Self::PRECISE_POSITIONS, "precise positions"),
+        (Self::IDLE_POSITIONS, "idle player positions"),
+        (Self::PARTIAL_POSITIONS, "partial positions"),
+        (Self::FRAME_TIMING, "frame timing"),
+        (Self::XPOS_TIMING, "x position timing"),
+        (Self::TIME_TIMING, "time timing"),
+        (Self::TPS_CHANGES, "tps changes"),
+        (Self::PLAYER2, "player 2"),
+        (Self::PLATFORMER, "platformer inputs"),
+        (Self::BUGPOINTS, "bugpoints"),
+        (Self::SEEDS, "seeds"),
+        (Self::DEATHS, "deaths"),
+        (Self::RESTARTS, "restarts"),
+    ];
+
+    /// Whether every capability in `other` is also in `self`.
+    #[must_use]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether the set is empty.
+    #[must_use]
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Capabilities in either set.
+    #[must_use]
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// Capabilities in `self` but not in `other`.
+    #[must_use]
+    pub const fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    /// Names of the capabilities in the set.
+    pub fn names(self) -> impl Iterator<Item = &'static str> {
+        Self::NAMES
+            .into_iter()
+            .filter(move |(capability, _)| self.contains(*capability))
+            .map(|(_, name)| name)
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        self.union(rhs)
+    }
+}
+
+impl Display for Capabilities {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return f.write_str("none");
+        }
+
+        for (i, name) in self.names().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            f.write_str(name)?;
+        }
+        Ok(())
+    }
+}
+
+/// How confident a sniffer is that some data is in a given format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Confidence {
+    /// Only weak hints match, such as the file extension or a plausible record size.
+    Low,
+    /// The data has the structure of the format, but nothing unique to it.
+    Medium,
+    /// The data has the format's magic bytes or distinctive keys.
+    High,
+}
+
+/// A format some data could be in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Candidate {
+    /// The candidate format.
+    pub format: Format,
+    /// How confident the detector is in this format.
+    pub confidence: Confidence,
+    /// Whether the file name has one of the format's extensions.
+    pub extension_match: bool,
+}
+
+/// Inspects the contents of a whole file, returning the format it looks like, if any.
+pub type Sniffer = fn(&[u8]) -> Option<(Format, Confidence)>;
+
+/// Detects replay formats from file contents and names.
+///
+/// Sniffers are supplied by the crates implementing the formats.
+#[derive(Debug, Clone, Default)]
+pub struct Detector {
+    sniffers: Vec<Sniffer>,
+}
+
+impl Detector {
+    /// Create a detector without any sniffers, which only goes by file extensions.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a sniffer to the detector.
+    #[must_use]
+    pub fn with_sniffer(mut self, sniffer: Sniffer) -> Self {
+        self.sniffers.push(sniffer);
+        self
+    }
+
+    /// Find all formats the data could be in, best candidates first.
+    ///
+    /// Contents always outrank the file name, which only breaks ties. Formats that only match
+    /// by extension are included with [`Confidence::Low`].
+    #[must_use]
+    pub fn detect(&self, data: &[u8], file_name: Option<&str>) -> Vec<Candidate> {
+        let mut candidates: Vec<Candidate> = Vec::new();
+
+        for sniffer in &self.sniffers {
+            let Some((format, confidence)) = sniffer(data) else {
+                continue;
+            };
+
+            match candidates.iter_mut().find(|c| c.format == format) {
+                Some(candidate) => candidate.confidence = candidate.confidence.max(confidence),
+                None => candidates.push(Candidate {
+                    format,
+                    confidence,
+                    extension_match: false,
+                }),
+            }
+        }
+
+        if let Some(file_name) = file_name {
+            for format in Format::ALL
+                .iter()
+                .copied()
+                .filter(|format| format.matches_file_name(file_name))
+            {
+                match candidates.iter_mut().find(|c| c.format == format) {
+                    Some(candidate) => candidate.extension_match = true,
+                    None => candidates.push(Candidate {
+                        format,
+                        confidence: Confidence::Low,
+                        extension_match: true,
+                    }),
+                }
+            }
+        }
+
+        candidates.sort_by(|a, b| {
+            b.confidence
+                .cmp(&a.confidence)
+                .then(b.extension_match.cmp(&a.extension_match))
+        });
+        candidates
+    }
+
+    /// The most likely format of the data, if any.
+    #[must_use]
+    pub fn best(&self, data: &[u8], file_name: Option<&str>) -> Option<Format> {
+        self.detect(data, file_name)
+            .first()
+            .map(|candidate| candidate.format)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_descriptors() {
+        for format in Format::ALL {
+            let descriptor = format.descriptor();
+            assert!(!descriptor.name.is_empty());
+            assert!(descriptor.supports_game_version(descriptor.min_game_version));
+            assert!(descriptor.supports_game_version(descriptor.max_game_version));
+        }
+
+        let gdr = Format::GDR1.descriptor();
+        assert!(gdr.supports_game_version(GameVersion::new(22, 74)));
+        assert!(!gdr.supports_game_version(GameVersion::new(21, 130)));
+        assert!(gdr.supports_game_version(GameVersion::universal()));
+        assert!(
+            Format::PlainText
+                .descriptor()
+                .supports_game_version(GameVersion::new(19, 0))
+        );
+
+        assert!(Format::GDR1.lost_capabilities(Format::GDR2).is_empty());
+        assert_eq!(
+            Format::GDR2.lost_capabilities(Format::MHR),
+            Capabilities::X_VELOCITIES
+                | Capabilities::PRECISE_POSITIONS
+                | Capabilities::IDLE_POSITIONS
+                | Capabilities::PLATFORMER
+                | Capabilities::TPS_CHANGES
+                | Capabilities::DEATHS
+                | Capabilities::SEEDS
+        );
+        assert_eq!(
+            Format::GDR2.lost_capabilities(Format::MHR).to_string(),
+            "x velocities, precise positions, idle player positions, tps changes, \
+             platformer inputs, seeds, deaths"
+        );
+        assert_eq!(Format::TCBot.to_string(), "TCBot");
+    }
+
+    #[test]
+    fn test_format_matches_file_name() {
+        assert!(Format::GDR2.matches_file_name("level.GDR2"));
+        assert!(Format::MHRJson.matches_file_name("dir/level.mhr.json"));
+        assert!(Format::TASBot.matches_file_name("level.mhr.json"));
+        assert!(!Format::GDR1.matches_file_name("level.gdr2"));
+        assert!(!Format::GDR1.matches_file_name(".gdr"));
+        assert!(!Format::YBot1.matches_file_name("level"));
+    }
+
+    #[test]
+    fn test_detector_ranks_content_over_extension() {
+        let detector = Detector::new()
+            .with_sniffer(|data| {
+                data.starts_with(b"RPLY")
+                    .then_some((Format::ReplayBot, Confidence::High))
+            })
+            .with_sniffer(|data| {
+                (data.len() % 5 == 0).then_some((Format::OmegaBot2, Confidence::Medium))
+            });
+
+        let candidates = detector.detect(b"RPLY\x02", Some("level.replay"));
+        assert_eq!(
+            candidates
+                .iter()
+                .map(|c| (c.format, c.confidence, c.extension_match))
+                .collect::<Vec<_>>(),
+            vec![
+                (Format::ReplayBot, Confidence::High, true),
+                (Format::OmegaBot2, Confidence::Medium, true),
+                (Format::OmegaBot, Confidence::Low, true),
+                (Format::OmegaBot3, Confidence::Low, true),
+            ]
+        );
+
+        assert_eq!(
+            detector.best(b"1234", Some("level.replay")),
+            Some(Format::OmegaBot)
+        );
+        assert_eq!(detector.best(b"1234", None), None);
+    }
+}
@@ -1,8 +1,13 @@
 //! Core crate for Siliconv - a multi-format converter and replay editor.
 
 pub mod action;
+pub mod convert;
 pub mod error;
 pub mod format;
 pub mod meta;
+pub mod registry;
 pub mod replay;
+pub mod resample;
+pub mod timeline;
 pub mod version;
+pub mod xpos;
@@ -1,6 +1,6 @@
 //! Metadata handling for different replay formats.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 /// A metadata field.
 pub struct MetaField<'a> {
@@ -49,9 +49,7 @@ impl<'a> MetaField<'a> {
 /// Generic metadata trait for different replays.
 pub trait Meta {
     /// Fetch all fields in a metadata object.
-    fn fields(&self) -> HashMap<String, MetaField<'_>>
-    where
-        Self: Sized;
+    fn fields(&self) -> HashMap<String, MetaField<'_>>;
 
     /// Create a metadata object from fields.
     fn from_fields(fields: HashMap<String, MetaField<'_>>) -> Self
@@ -59,6 +57,77 @@ pub trait Meta {
         Self: Sized;
 }
 
+/// Metadata stored as encoded strings, keyed by field name.
+///
+/// Holds the fields of any other metadata object without knowing its type,
+/// which is useful for formats that carry arbitrary metadata.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetaMap {
+    fields: BTreeMap<String, String>,
+}
+
+impl MetaMap {
+    /// Create an empty metadata map.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Copy the encoded fields of another metadata object.
+    #[must_use]
+    pub fn from_meta(meta: &dyn Meta) -> Self {
+        Self::from_fields(meta.fields())
+    }
+
+    /// Insert an encoded field, returning the previous value if there was one.
+    pub fn insert(&mut self, key: impl Into<String>, value: impl Into<String>) -> Option<String> {
+        self.fields.insert(key.into(), value.into())
+    }
+
+    /// Get an encoded field.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields.get(key).map(String::as_str)
+    }
+
+    /// Iterate over all encoded fields, sorted by key.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    /// The number of fields.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Whether there are no fields.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+}
+
+impl Meta for MetaMap {
+    fn fields(&self) -> HashMap<String, MetaField<'_>> {
+        self.fields
+            .iter()
+            .map(|(key, value)| (key.clone(), MetaField::new(|| value.clone())))
+            .collect()
+    }
+
+    fn from_fields(fields: HashMap<String, MetaField<'_>>) -> Self {
+        Self {
+            fields: fields
+                .iter()
+                .map(|(key, field)| (key.clone(), field.encode()))
+                .collect(),
+        }
+    }
+}
+
 // Implementations for common types
 
 macro_rules! impl_meta_for_num {
@@ -93,6 +162,18 @@ impl MetaDecodable for String {
     }
 }
 
+impl MetaEncodable for bool {
+    fn encode(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl MetaDecodable for bool {
+    fn decode(encoded: String) -> Option<Self> {
+        encoded.parse::<bool>().ok()
+    }
+}
+
 impl MetaEncodable for () {
     fn encode(&self) -> String {
         String::new()
@@ -203,4 +284,21 @@ mod tests {
         let reconstructed_meta = TestMeta::from_fields(original_meta.fields());
         assert!(reconstructed_meta.tps.eq(&75.0));
     }
+
+    #[test]
+    fn test_meta_map() {
+        let original_meta = OtherTestMeta {
+            tps: 60.0,
+            seed: 42,
+        };
+        let map = MetaMap::from_meta(&original_meta);
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get("tps"), Some("60"));
+        assert_eq!(map.get("seed"), Some("42"));
+
+        let reconstructed_meta = OtherTestMeta::from_fields(map.fields());
+        assert!(reconstructed_meta.tps.eq(&60.0));
+        assert_eq!(reconstructed_meta.seed, 42);
+    }
 }
@@ -0,0 +1,224 @@
+//! Runtime lookup of replay readers and writers by format.
+//!
+//! [`ReplaySerializable`] is generic over its reader and writer, so it can't be used as a trait
+//! object. [`FormatHandler`] is its object-safe counterpart, and a [`Registry`] maps every
+//! [`Format`] to a boxed handler.
+
+use std::{
+    collections::HashMap,
+    io::{Read, Seek, Write},
+    marker::PhantomData,
+};
+
+use crate::{
+    convert::{self, ConvertOptions, LossReport},
+    error::ReplayError,
+    format::Format,
+    replay::{Replay, ReplaySerializable},
+};
+
+/// A reader that can also seek, usable as a trait object.
+pub trait ReadSeek: Read + Seek {}
+
+impl<T: Read + Seek + ?Sized> ReadSeek for T {}
+
+/// Object-safe reader and writer for one or more formats.
+pub trait FormatHandler: Send + Sync {
+    /// Read a replay in `format`, one of the formats this handler was registered for.
+    ///
+    /// # Errors
+    /// If reading from the reader fails.
+    fn read(&self, reader: &mut dyn ReadSeek, format: Format) -> Result<Replay, ReplayError>;
+
+    /// Write a replay in `format`, one of the formats this handler was registered for.
+    ///
+    /// # Errors
+    /// If writing to the writer fails.
+    fn write(
+        &self,
+        replay: &Replay,
+        format: Format,
+        writer: &mut dyn Write,
+    ) -> Result<(), ReplayError>;
+}
+
+/// A [`FormatHandler`] backed by a [`ReplaySerializable`] type.
+pub struct SerializableHandler<T>(PhantomData<fn() -> T>);
+
+impl<T> SerializableHandler<T> {
+    /// Create a handler for `T`.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<T> Default for SerializableHandler<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: ReplaySerializable> FormatHandler for SerializableHandler<T> {
+    fn read(&self, mut reader: &mut dyn ReadSeek, format: Format) -> Result<Replay, ReplayError> {
+        T::read_as(&mut reader, format).map(T::into_replay)
+    }
+
+    fn write(
+        &self,
+        replay: &Replay,
+        format: Format,
+        mut writer: &mut dyn Write,
+    ) -> Result<(), ReplayError> {
+        T::write_as(replay, format, &mut writer)
+    }
+}
+
+/// Readers and writers for replay formats, picked at runtime.
+#[derive(Default)]
+pub struct Registry {
+    handlers: HashMap<Format, Box<dyn FormatHandler>>,
+}
+
+impl Registry {
+    /// Create an empty registry.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `T` as the handler for each of `formats`.
+    #[must_use]
+    pub fn with<T: ReplaySerializable + 'static>(mut self, formats: &[Format]) -> Self {
+        for &format in formats {
+            self.register(format, Box::new(SerializableHandler::<T>::new()));
+        }
+        self
+    }
+
+    /// Register a handler for a format, returning the one it replaces.
+    pub fn register(
+        &mut self,
+        format: Format,
+        handler: Box<dyn FormatHandler>,
+    ) -> Option<Box<dyn FormatHandler>> {
+        self.handlers.insert(format, handler)
+    }
+
+    /// The handler for a format, if one is registered.
+    #[must_use]
+    pub fn handler(&self, format: Format) -> Option<&dyn FormatHandler> {
+        self.handlers.get(&format).map(AsRef::as_ref)
+    }
+
+    /// Whether a handler is registered for a format.
+    #[must_use]
+    pub fn supports(&self, format: Format) -> bool {
+        self.handlers.contains_key(&format)
+    }
+
+    /// All formats with a registered handler, in no particular order.
+    pub fn formats(&self) -> impl Iterator<Item = Format> + '_ {
+        self.handlers.keys().copied()
+    }
+
+    /// Read a replay in the given format.
+    ///
+    /// # Errors
+    /// If no handler is registered for the format, reading fails, or the data is in another
+    /// version of the format, such as Silicate 3 data read as Silicate 2.
+    pub fn read<R: Read + Seek>(
+        &self,
+        format: Format,
+        reader: &mut R,
+    ) -> Result<Replay, ReplayError> {
+        let replay = self
+            .handler(format)
+            .ok_or(ReplayError::UnsupportedFormat(format))?
+            .read(reader, format)?;
+
+        if replay.format != format {
+            return Err(ReplayError::ReadError(format!(
+                "expected a {format} replay, found {}",
+                replay.format
+            )));
+        }
+        Ok(replay)
+    }
+
+    /// Write a replay in the given format.
+    ///
+    /// `format` picks the version written by formats that have several, whatever the replay's
+    /// own format is.
+    ///
+    /// # Errors
+    /// If no handler is registered for the format, or writing fails.
+    pub fn write<W: Write>(
+        &self,
+        format: Format,
+        replay: &Replay,
+        writer: &mut W,
+    ) -> Result<(), ReplayError> {
+        self.handler(format)
+            .ok_or(ReplayError::UnsupportedFormat(format))?
+            .write(replay, format, writer)
+    }
+
+    /// Convert a replay from one format to another with the default options.
+    ///
+    /// # Errors
+    /// If either format has no handler, or reading or writing fails.
+    pub fn convert<R: Read + Seek, W: Write>(
+        &self,
+        from: Format,
+        reader: &mut R,
+        to: Format,
+        writer: &mut W,
+    ) -> Result<LossReport, ReplayError> {
+        self.convert_with(from, reader, to, writer, &ConvertOptions::default())
+    }
+
+    /// Convert a replay from one format to another.
+    ///
+    /// The replay is [normalized](convert::normalize_with) for the target format between
+    /// reading and writing. The returned report lists everything the conversion lost.
+    ///
+    /// # Errors
+    /// If either format has no handler, or reading or writing fails.
+    pub fn convert_with<R: Read + Seek, W: Write>(
+        &self,
+        from: Format,
+        reader: &mut R,
+        to: Format,
+        writer: &mut W,
+        options: &ConvertOptions,
+    ) -> Result<LossReport, ReplayError> {
+        if !self.supports(to) {
+            return Err(ReplayError::UnsupportedFormat(to));
+        }
+
+        let mut replay = self.read(from, reader)?;
+        let report = convert::normalize_with(&mut replay, to, options);
+        self.write(to, &replay, writer)?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_registry_rejects_unregistered_formats() {
+        let registry = Registry::new();
+        assert!(!registry.supports(Format::GDR2));
+
+        let result = registry.read(Format::GDR2, &mut Cursor::new(Vec::new()));
+        assert!(matches!(
+            result,
+            Err(ReplayError::UnsupportedFormat(Format::GDR2))
+        ));
+    }
+}
@@ -3,7 +3,11 @@
 use std::io::{Read, Seek, Write};
 
 use crate::{
-    action::TimedAction, error::ReplayError, format::Format, meta::Meta, version::GameVersion,
+    action::{FrameCountingMethod, TimedAction},
+    error::ReplayError,
+    format::Format,
+    meta::{Meta, MetaMap},
+    version::GameVersion,
 };
 
 /// A replay.
@@ -16,6 +20,24 @@ pub struct Replay {
     pub format: Format,
     /// The game version this replay is designed for.
     pub game_version: GameVersion,
+    /// How the replay's frame time points are counted.
+    pub frame_counting: FrameCountingMethod,
+}
+
+impl Replay {
+    /// Copy the replay, snapshotting its metadata into a [`MetaMap`].
+    ///
+    /// The fields are kept as is, but the concrete metadata type is not.
+    #[must_use]
+    pub fn to_generic(&self) -> Self {
+        Self {
+            meta: Box::new(MetaMap::from_meta(self.meta.as_ref())),
+            actions: self.actions.clone(),
+            format: self.format,
+            game_version: self.game_version,
+            frame_counting: self.frame_counting,
+        }
+    }
 }
 
 /// Trait for types that are:
@@ -36,9 +58,39 @@ pub trait ReplaySerializable {
     where
         Self: Sized;
 
+    /// Read an instance from a reader, as one of the formats this type handles.
+    ///
+    /// Types handling several formats that can't always be told apart by their content
+    /// override this to use `format`. By default the format is detected from the content.
+    ///
+    /// # Errors
+    /// If reading from the reader fails.
+    fn read_as<R: Read + Seek>(reader: &mut R, format: Format) -> Result<Self, ReplayError>
+    where
+        Self: Sized,
+    {
+        let _ = format;
+        Self::read(reader)
+    }
+
     /// Write an instance to a writer.
     ///
     /// # Errors
     /// If writing to the writer fails.
     fn write<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError>;
+
+    /// Write a generic replay to a writer, as one of the formats this type handles.
+    ///
+    /// Unlike [`write`](Self::write), this borrows the replay instead of taking ownership of
+    /// it. `format` picks the version written by types handling several formats.
+    ///
+    /// # Errors
+    /// If writing to the writer fails.
+    fn write_as<W: Write>(
+        replay: &Replay,
+        format: Format,
+        writer: &mut W,
+    ) -> Result<(), ReplayError>
+    where
+        Self: Sized;
 }
@@ -0,0 +1,290 @@
+//! Resampling of replays to another TPS.
+//!
+//! Every frame is moved to the frame at the same time at the new TPS, following the replay's
+//! [`TpsTimeline`]. Lowering the TPS can round a press and its release onto the same frame,
+//! which a [`CollapsePolicy`] resolves.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    action::{Action, PlayerButton, TimePoint},
+    meta::{MetaEncodable, MetaMap},
+    replay::Replay,
+    timeline::TpsTimeline,
+};
+
+/// How to resolve a press and release landing on the same frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum CollapsePolicy {
+    /// Move the release one frame later, keeping a one frame hold.
+    #[default]
+    KeepHold,
+    /// Remove both the press and the release.
+    Drop,
+    /// Move the press one frame earlier. If that would run into the previous release of the
+    /// button, the release is moved one frame later instead.
+    Shift,
+}
+
+/// A press and release that landed on the same frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Collapse {
+    /// The frame both landed on.
+    pub frame: u64,
+    /// The pressed button.
+    pub button: PlayerButton,
+    /// Whether the button is player 2's.
+    pub player2: bool,
+}
+
+/// The outcome of resampling a replay.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResampleReport {
+    /// Every collapsed press and release, resolved with the policy.
+    pub collapses: Vec<Collapse>,
+}
+
+/// Resample a replay to start at `tps`.
+///
+/// Every rate is scaled by the same factor, so TPS changes keep their relative speed. The
+/// `tps` metadata field, and `delta` if present, are updated. Only frame time points move.
+///
+/// Invalid rates leave the replay untouched.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+pub fn resample(replay: &mut Replay, tps: f64, policy: CollapsePolicy) -> ResampleReport {
+    let timeline = TpsTimeline::from_replay(replay);
+    let factor = tps / timeline.initial_tps();
+    if !factor.is_finite() || factor <= 0.0 {
+        return ResampleReport::default();
+    }
+
+    let scaled = timeline.scaled(factor);
+    for timed in &mut replay.actions {
+        if let TimePoint::Frame(frame) = timed.time {
+            let time = timeline.frame_to_time(frame as f64);
+            timed.time = TimePoint::Frame(scaled.time_to_frame(time).round() as u64);
+        }
+        if let Action::TPS { tps } = &mut timed.action {
+            *tps *= factor;
+        }
+    }
+
+    let mut meta = MetaMap::from_meta(replay.meta.as_ref());
+    meta.insert("tps", tps.encode());
+    if meta.get("delta").is_some() {
+        meta.insert("delta", (1.0 / tps).encode());
+    }
+    replay.meta = Box::new(meta);
+
+    let report = resolve_collapses(replay, policy);
+
+    if replay
+        .actions
+        .iter()
+        .all(|timed| matches!(timed.time, TimePoint::Frame(_)))
+    {
+        replay.actions.sort_by_key(|timed| match timed.time {
+            TimePoint::Frame(frame) => Some(frame),
+            _ => None,
+        });
+    }
+
+    report
+}
+
+fn resolve_collapses(replay: &mut Replay, policy: CollapsePolicy) -> ResampleReport {
+    let mut report = ResampleReport::default();
+    let mut dropped = HashSet::new();
+
+    // Per button, the index of the press that hasn't been released and the last release frame.
+    let mut pressed: HashMap<(PlayerButton, bool), usize> = HashMap::new();
+    let mut released: HashMap<(PlayerButton, bool), u64> = HashMap::new();
+
+    for index in 0..replay.actions.len() {
+        let timed = replay.actions[index];
+        let (
+            TimePoint::Frame(frame),
+            Action::Player {
+                button,
+                hold,
+                player2,
+            },
+        ) = (timed.time, timed.action)
+        else {
+            continue;
+        };
+        let key = (button, player2);
+
+        if hold {
+            pressed.insert(key, index);
+            continue;
+        }
+
+        let press = pressed.remove(&key);
+        let previous_release = released.insert(key, frame);
+        let Some(press) = press.filter(|&press| replay.actions[press].time == timed.time) else {
+            continue;
+        };
+
+        report.collapses.push(Collapse {
+            frame,
+            button,
+            player2,
+        });
+
+        let shift_press = frame > 0 && previous_release.is_none_or(|release| release + 1 < frame);
+        match policy {
+            CollapsePolicy::Drop => {
+                dropped.insert(press);
+                dropped.insert(index);
+            }
+            CollapsePolicy::Shift if shift_press => {
+                replay.actions[press].time = TimePoint::Frame(frame - 1);
+            }
+            CollapsePolicy::KeepHold | CollapsePolicy::Shift => {
+                replay.actions[index].time = TimePoint::Frame(frame + 1);
+                released.insert(key, frame + 1);
+            }
+        }
+    }
+
+    let mut index = 0;
+    replay.actions.retain(|_| {
+        index += 1;
+        !dropped.contains(&(index - 1))
+    });
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        action::{FrameCountingMethod, TimedAction},
+        format::Format,
+        version::GameVersion,
+    };
+
+    fn timed(frame: u64, action: Action) -> TimedAction {
+        TimedAction {
+            time: TimePoint::Frame(frame),
+            action,
+            position: None,
+        }
+    }
+
+    fn click(hold: bool) -> Action {
+        Action::Player {
+            button: PlayerButton::Jump,
+            hold,
+            player2: false,
+        }
+    }
+
+    fn replay(actions: Vec<TimedAction>) -> Replay {
+        let mut meta = MetaMap::new();
+        meta.insert("tps", "240");
+
+        Replay {
+            meta: Box::new(meta),
+            actions,
+            format: Format::PlainText,
+            game_version: GameVersion::new(22, 74),
+            frame_counting: FrameCountingMethod::Unknown,
+        }
+    }
+
+    fn frames(replay: &Replay) -> Vec<(u64, Action)> {
+        replay
+            .actions
+            .iter()
+            .map(|timed| match timed.time {
+                TimePoint::Frame(frame) => (frame, timed.action),
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_resample_scales_frames_and_tps() {
+        let mut replay = replay(vec![
+            timed(100, click(true)),
+            timed(120, click(false)),
+            timed(200, Action::TPS { tps: 480.0 }),
+            timed(300, click(true)),
+        ]);
+
+        let report = resample(&mut replay, 480.0, CollapsePolicy::KeepHold);
+        assert!(report.collapses.is_empty());
+        assert_eq!(
+            frames(&replay),
+            vec![
+                (200, click(true)),
+                (240, click(false)),
+                (400, Action::TPS { tps: 960.0 }),
+                (600, click(true)),
+            ]
+        );
+
+        let meta = MetaMap::from_meta(replay.meta.as_ref());
+        assert_eq!(meta.get("tps"), Some("480"));
+        assert_eq!(meta.get("delta"), None);
+    }
+
+    #[test]
+    fn test_resample_collapse_policies() {
+        // At 60 TPS, 100 and 101 both land on frame 25.
+        let actions = vec![
+            timed(80, click(true)),
+            timed(96, click(false)),
+            timed(100, click(true)),
+            timed(101, click(false)),
+            timed(200, click(true)),
+        ];
+
+        let mut keep = replay(actions.clone());
+        let report = resample(&mut keep, 60.0, CollapsePolicy::KeepHold);
+        assert_eq!(
+            report.collapses,
+            vec![Collapse {
+                frame: 25,
+                button: PlayerButton::Jump,
+                player2: false,
+            }]
+        );
+        assert_eq!(
+            frames(&keep),
+            vec![
+                (20, click(true)),
+                (24, click(false)),
+                (25, click(true)),
+                (26, click(false)),
+                (50, click(true)),
+            ]
+        );
+
+        let mut drop = replay(actions.clone());
+        resample(&mut drop, 60.0, CollapsePolicy::Drop);
+        assert_eq!(
+            frames(&drop),
+            vec![(20, click(true)), (24, click(false)), (50, click(true))]
+        );
+
+        // The previous release is on frame 24, so the press can't move back.
+        let mut shift = replay(actions.clone());
+        resample(&mut shift, 60.0, CollapsePolicy::Shift);
+        assert_eq!(frames(&shift), frames(&keep));
+
+        let mut shift = replay(actions[2..].to_vec());
+        resample(&mut shift, 60.0, CollapsePolicy::Shift);
+        assert_eq!(
+            frames(&shift),
+            vec![(24, click(true)), (25, click(false)), (50, click(true))]
+        );
+    }
+}
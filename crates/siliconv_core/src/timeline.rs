@@ -0,0 +1,402 @@
+//! Tick rate timelines and conversion between time point kinds.
+//!
+//! A replay runs at the TPS in its metadata until an [`Action::TPS`] changes it. A
+//! [`TpsTimeline`] keeps those changes as piecewise segments, mapping frames to seconds and
+//! back without accumulating error.
+
+use crate::{
+    action::{Action, TimePoint},
+    replay::Replay,
+};
+
+/// Tick rate assumed for replays whose metadata doesn't have one.
+pub const DEFAULT_TPS: f64 = 240.0;
+
+/// The TPS stored in a replay's metadata, under the shared `tps` key.
+///
+/// Formats storing a frame delta instead, such as zBot, have it under the `delta` key.
+#[must_use]
+pub fn meta_tps(replay: &Replay) -> Option<f64> {
+    let fields = replay.meta.fields();
+    let decode = |key: &str| {
+        fields
+            .get(key)
+            .and_then(|field| field.decode::<f64>(field.encode()))
+    };
+
+    decode("tps")
+        .or_else(|| decode("delta").map(|delta| 1.0 / delta))
+        .filter(|tps| valid_tps(*tps))
+}
+
+fn valid_tps(tps: f64) -> bool {
+    tps.is_finite() && tps > 0.0
+}
+
+/// A stretch of the replay running at a single TPS.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Segment {
+    /// First frame of the segment.
+    frame: f64,
+    /// Seconds since the start of the replay at that frame.
+    time: f64,
+    tps: f64,
+}
+
+/// The TPS of a replay over time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TpsTimeline {
+    /// Segments sorted by frame. The first one always starts at frame 0.
+    segments: Vec<Segment>,
+}
+
+impl TpsTimeline {
+    /// Create a timeline running at a constant TPS.
+    ///
+    /// Invalid rates fall back to [`DEFAULT_TPS`].
+    #[must_use]
+    pub fn new(tps: f64) -> Self {
+        Self {
+            segments: vec![Segment {
+                frame: 0.0,
+                time: 0.0,
+                tps: if valid_tps(tps) { tps } else { DEFAULT_TPS },
+            }],
+        }
+    }
+
+    /// Build the timeline of a replay from its metadata TPS and its TPS actions.
+    ///
+    /// TPS actions timed by x position can't be placed and are ignored.
+    #[must_use]
+    pub fn from_replay(replay: &Replay) -> Self {
+        let mut timeline = Self::new(meta_tps(replay).unwrap_or(DEFAULT_TPS));
+
+        for timed in &replay.actions {
+            let Action::TPS { tps } = timed.action else {
+                continue;
+            };
+
+            #[allow(clippy::cast_precision_loss)]
+            let frame = match timed.time {
+                TimePoint::Frame(frame) => frame as f64,
+                TimePoint::Time(time) => timeline.time_to_frame(time),
+                TimePoint::XPos(_) => continue,
+            };
+            timeline.add_change(frame, tps);
+        }
+
+        timeline
+    }
+
+    /// Change the TPS from `frame` onwards, until the next change.
+    ///
+    /// A change at an existing change's frame replaces it. Invalid rates are ignored.
+    pub fn add_change(&mut self, frame: f64, tps: f64) {
+        if !valid_tps(tps) || !frame.is_finite() || frame < 0.0 {
+            return;
+        }
+
+        let index = self
+            .segments
+            .partition_point(|segment| segment.frame <= frame);
+        let previous = &mut self.segments[index - 1];
+        if previous.frame.eq(&frame) {
+            previous.tps = tps;
+        } else {
+            self.segments.insert(
+                index,
+                Segment {
+                    frame,
+                    time: 0.0,
+                    tps,
+                },
+            );
+        }
+
+        for i in 1..self.segments.len() {
+            let previous = self.segments[i - 1];
+            let segment = &mut self.segments[i];
+            segment.time = previous.time + (segment.frame - previous.frame) / previous.tps;
+        }
+    }
+
+    /// The same timeline with every rate multiplied by `factor`.
+    ///
+    /// Times stay where they are, so frames are multiplied by `factor` as well.
+    #[must_use]
+    pub fn scaled(&self, factor: f64) -> Self {
+        Self {
+            segments: self
+                .segments
+                .iter()
+                .map(|segment| Segment {
+                    frame: segment.frame * factor,
+                    time: segment.time,
+                    tps: segment.tps * factor,
+                })
+                .collect(),
+        }
+    }
+
+    /// The TPS the replay starts with.
+    #[must_use]
+    pub fn initial_tps(&self) -> f64 {
+        self.segments[0].tps
+    }
+
+    /// Whether the TPS never changes.
+    #[must_use]
+    pub fn is_constant(&self) -> bool {
+        self.segments.len() == 1
+    }
+
+    fn segment_at_frame(&self, frame: f64) -> &Segment {
+        let index = self
+            .segments
+            .partition_point(|segment| segment.frame <= frame);
+        &self.segments[index.saturating_sub(1)]
+    }
+
+    fn segment_at_time(&self, time: f64) -> &Segment {
+        let index = self
+            .segments
+            .partition_point(|segment| segment.time <= time);
+        &self.segments[index.saturating_sub(1)]
+    }
+
+    /// The TPS in effect on a frame.
+    #[must_use]
+    pub fn tps_at_frame(&self, frame: f64) -> f64 {
+        self.segment_at_frame(frame).tps
+    }
+
+    /// Seconds since the start of the replay at a frame.
+    #[must_use]
+    pub fn frame_to_time(&self, frame: f64) -> f64 {
+        let segment = self.segment_at_frame(frame);
+        segment.time + (frame - segment.frame) / segment.tps
+    }
+
+    /// The frame, possibly fractional, at a number of seconds since the start of the replay.
+    #[must_use]
+    pub fn time_to_frame(&self, time: f64) -> f64 {
+        let segment = self.segment_at_time(time);
+        segment.frame + (time - segment.time) * segment.tps
+    }
+}
+
+/// A kind of [`TimePoint`], without its value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimeKind {
+    /// [`TimePoint::Frame`].
+    Frame,
+    /// [`TimePoint::XPos`].
+    XPos,
+    /// [`TimePoint::Time`].
+    Time,
+}
+
+impl TimeKind {
+    /// The kind of a time point.
+    #[must_use]
+    pub const fn of(time: TimePoint) -> Self {
+        match time {
+            TimePoint::Frame(_) => Self::Frame,
+            TimePoint::XPos(_) => Self::XPos,
+            TimePoint::Time(_) => Self::Time,
+        }
+    }
+}
+
+/// An action whose time point was converted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Retimed {
+    /// Index of the action in the replay.
+    pub index: usize,
+    /// The original time point.
+    pub from: TimePoint,
+    /// The converted time point.
+    pub to: TimePoint,
+    /// How far the converted time point is from the original one, in seconds.
+    pub error: f64,
+}
+
+/// The outcome of converting a replay's time points to one kind.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetimeReport {
+    /// Every converted action.
+    pub retimed: Vec<Retimed>,
+    /// Indices of actions that couldn't be converted and were left as they were.
+    pub unconvertible: Vec<usize>,
+}
+
+impl RetimeReport {
+    /// The largest error of any converted action, in seconds.
+    #[must_use]
+    pub fn max_error(&self) -> f64 {
+        self.retimed
+            .iter()
+            .map(|retimed| retimed.error)
+            .fold(0.0, f64::max)
+    }
+
+    /// Converted actions that didn't land exactly on their original time.
+    pub fn rounded(&self) -> impl Iterator<Item = &Retimed> {
+        self.retimed.iter().filter(|retimed| retimed.error > 0.0)
+    }
+}
+
+/// Convert every action of a replay to time points of one kind.
+///
+/// Frames and times convert through the replay's [`TpsTimeline`]. Frames are rounded to the
+/// nearest one, which is reported as an error. Converting to x positions uses Player 1's
+/// recorded position, so actions without one can't be converted. Converting from x positions
+/// needs the player's path and isn't done here.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+pub fn retime(replay: &mut Replay, kind: TimeKind) -> RetimeReport {
+    let timeline = TpsTimeline::from_replay(replay);
+    let mut report = RetimeReport::default();
+
+    for (index, timed) in replay.actions.iter_mut().enumerate() {
+        let from = timed.time;
+        if TimeKind::of(from) == kind {
+            continue;
+        }
+
+        let time = match from {
+            TimePoint::Frame(frame) => Some(timeline.frame_to_time(frame as f64)),
+            TimePoint::Time(time) => Some(time),
+            TimePoint::XPos(_) => None,
+        };
+
+        let converted = match (kind, time) {
+            (TimeKind::Frame, Some(time)) => {
+                let frame = timeline.time_to_frame(time).round().max(0.0);
+                let error = (timeline.frame_to_time(frame) - time).abs();
+                Some((TimePoint::Frame(frame as u64), error))
+            }
+            (TimeKind::Time, Some(time)) => Some((TimePoint::Time(time), 0.0)),
+            (TimeKind::XPos, _) => timed
+                .position
+                .and_then(|position| position.player1)
+                .map(|player1| (TimePoint::XPos(player1.x), 0.0)),
+            (_, None) => None,
+        };
+
+        match converted {
+            Some((to, error)) => {
+                timed.time = to;
+                report.retimed.push(Retimed {
+                    index,
+                    from,
+                    to,
+                    error,
+                });
+            }
+            None => report.unconvertible.push(index),
+        }
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        action::{FrameCountingMethod, PlayerButton, TimedAction},
+        format::Format,
+        meta::MetaMap,
+        version::GameVersion,
+    };
+
+    fn timed(time: TimePoint, action: Action) -> TimedAction {
+        TimedAction {
+            time,
+            action,
+            position: None,
+        }
+    }
+
+    fn click() -> Action {
+        Action::Player {
+            button: PlayerButton::Jump,
+            hold: true,
+            player2: false,
+        }
+    }
+
+    fn replay(actions: Vec<TimedAction>) -> Replay {
+        let mut meta = MetaMap::new();
+        meta.insert("tps", "240");
+
+        Replay {
+            meta: Box::new(meta),
+            actions,
+            format: Format::PlainText,
+            game_version: GameVersion::new(22, 74),
+            frame_counting: FrameCountingMethod::Unknown,
+        }
+    }
+
+    #[test]
+    fn test_timeline_piecewise() {
+        let timeline = TpsTimeline::from_replay(&replay(vec![
+            timed(TimePoint::Frame(240), Action::TPS { tps: 480.0 }),
+            timed(TimePoint::Time(2.0), Action::TPS { tps: 60.0 }),
+        ]));
+
+        assert!(timeline.initial_tps().eq(&240.0));
+        assert!(timeline.tps_at_frame(239.0).eq(&240.0));
+        assert!(timeline.tps_at_frame(240.0).eq(&480.0));
+
+        // 1s at 240 TPS, then 1s at 480 TPS, then 60 TPS.
+        for (frame, time) in [
+            (120.0, 0.5),
+            (240.0, 1.0),
+            (480.0, 1.5),
+            (720.0, 2.0),
+            (780.0, 3.0),
+        ] {
+            assert!(timeline.frame_to_time(frame).eq(&time), "frame {frame}");
+            assert!(timeline.time_to_frame(time).eq(&frame), "time {time}");
+        }
+    }
+
+    #[test]
+    fn test_retime_reports_rounding() {
+        let mut replay = replay(vec![
+            timed(TimePoint::Time(0.5), click()),
+            timed(TimePoint::Time(0.501), click()),
+            timed(TimePoint::Frame(360), click()),
+            timed(TimePoint::XPos(10.0), click()),
+        ]);
+
+        let report = retime(&mut replay, TimeKind::Frame);
+        assert_eq!(
+            replay.actions.iter().map(|t| t.time).collect::<Vec<_>>(),
+            vec![
+                TimePoint::Frame(120),
+                TimePoint::Frame(120),
+                TimePoint::Frame(360),
+                TimePoint::XPos(10.0),
+            ]
+        );
+        assert_eq!(report.retimed.len(), 2);
+        assert_eq!(
+            report.rounded().map(|r| r.index).collect::<Vec<_>>(),
+            vec![1]
+        );
+        assert!((report.max_error() - 0.001).abs() < 1e-9);
+        assert_eq!(report.unconvertible, vec![3]);
+
+        let report = retime(&mut replay, TimeKind::Time);
+        assert_eq!(replay.actions[2].time, TimePoint::Time(1.5));
+        assert!(report.max_error().eq(&0.0));
+    }
+}
@@ -1,5 +1,7 @@
 //! Game version related structs.
 
+use std::{fmt::Display, str::FromStr};
+
 /// A game version.
 #[derive(Debug, Clone, Copy)]
 pub struct GameVersion {
@@ -24,7 +26,7 @@ pub struct GameVersion {
 impl GameVersion {
     /// Create a new game version.
     #[must_use]
-    pub fn new(major: u32, minor: u32) -> Self {
+    pub const fn new(major: u32, minor: u32) -> Self {
         Self { major, minor }
     }
 
@@ -36,11 +38,47 @@ impl GameVersion {
 
     /// Create a universal game version.
     #[must_use]
-    pub fn universal() -> Self {
+    pub const fn universal() -> Self {
         Self { major: 0, minor: 0 }
     }
 }
 
+impl Display for GameVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major / 10, self.major % 10)?;
+
+        let minor = format!("{:03}", self.minor);
+        write!(f, "{}", minor.trim_end_matches('0'))
+    }
+}
+
+impl FromStr for GameVersion {
+    type Err = String;
+
+    /// Parse a game version such as "2.113" or "2.2074".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid game version: {s}");
+
+        let (whole, fraction) = s.trim().split_once('.').unwrap_or((s.trim(), ""));
+        if !fraction.bytes().all(|b| b.is_ascii_digit()) || fraction.len() > 4 {
+            return Err(invalid());
+        }
+
+        let whole = whole.parse::<u32>().map_err(|_| invalid())?;
+        let (first, rest) = fraction.split_at(fraction.len().min(1));
+
+        let major = whole
+            .checked_mul(10)
+            .and_then(|major| major.checked_add(first.parse::<u32>().unwrap_or(0)))
+            .ok_or_else(invalid)?;
+        let minor = format!("{rest:0<3}")
+            .parse::<u32>()
+            .map_err(|_| invalid())?;
+
+        Ok(Self { major, minor })
+    }
+}
+
 impl PartialEq for GameVersion {
     fn eq(&self, other: &Self) -> bool {
         self.is_universal() || other.is_universal() || self.major == other.major
@@ -86,4 +124,24 @@ mod tests {
         assert_eq!(universal, v1);
         assert_eq!(universal, v2);
     }
+
+    #[test]
+    fn test_game_version_parse() {
+        let cases = [
+            ("1.9", 19, 0),
+            ("2.113", 21, 130),
+            ("2.2074", 22, 74),
+            ("2.206", 22, 60),
+        ];
+
+        for (s, major, minor) in cases {
+            let version: GameVersion = s.parse().unwrap();
+            assert_eq!((version.major, version.minor), (major, minor));
+            assert_eq!(version.to_string(), s);
+        }
+
+        assert!("2.2a".parse::<GameVersion>().is_err());
+        assert!("two".parse::<GameVersion>().is_err());
+        assert!("4294967295.2".parse::<GameVersion>().is_err());
+    }
 }
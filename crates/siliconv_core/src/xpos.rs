@@ -0,0 +1,612 @@
+//! Retiming of x position actions to frames, and back.
+//!
+//! An x position action fires on the first frame Player 1 is at or past its position. When
+//! positions of Player 1 are known on some frames, the crossing frame is found on that
+//! [`PositionTrack`]. Otherwise a [`SpeedModel`] estimates when the player gets there,
+//! assuming a constant speed between speed portals, starting from the last known position
+//! before it. Frames are turned into x positions the same way, for formats that can't store
+//! frames.
+//!
+//! Formats timed by x position don't record the frames of their actions, so a track can't be
+//! collected from them. Their actions are placed by the model, unless a track is supplied.
+
+use std::collections::HashMap;
+
+use crate::{action::TimePoint, replay::Replay, timeline::TpsTimeline};
+
+/// A speed portal setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Speed {
+    /// 0.5x speed.
+    Slow,
+    /// 1x speed.
+    Normal,
+    /// 2x speed.
+    Fast,
+    /// 3x speed.
+    Faster,
+    /// 4x speed.
+    Fastest,
+}
+
+impl Speed {
+    /// Horizontal speed of the player, in units per second.
+    #[must_use]
+    pub const fn units_per_second(self) -> f64 {
+        match self {
+            Speed::Slow => 251.16,
+            Speed::Normal => 311.58,
+            Speed::Fast => 387.42,
+            Speed::Faster => 468.0,
+            Speed::Fastest => 576.0,
+        }
+    }
+}
+
+/// A game mode portal setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameMode {
+    /// Cube.
+    Cube,
+    /// Ship.
+    Ship,
+    /// Ball.
+    Ball,
+    /// UFO.
+    Ufo,
+    /// Wave.
+    Wave,
+    /// Robot.
+    Robot,
+    /// Spider.
+    Spider,
+    /// Swing.
+    Swing,
+}
+
+/// A point of the level from which the player moves at a new speed or in a new game mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Portal {
+    /// X position of the portal.
+    pub x: f64,
+    /// Speed after the portal.
+    pub speed: Speed,
+    /// Game mode after the portal.
+    pub mode: GameMode,
+}
+
+/// Constant speed model of Player 1's movement through a level.
+#[derive(Debug, Clone)]
+pub struct SpeedModel {
+    start: Portal,
+    /// Portals after the start, sorted by x position.
+    portals: Vec<Portal>,
+    /// Speed multipliers of game modes, 1 if missing.
+    mode_factors: HashMap<GameMode, f64>,
+    /// Distance from a portal within which placements are flagged as ambiguous.
+    portal_tolerance: f64,
+}
+
+impl Default for SpeedModel {
+    fn default() -> Self {
+        Self::new(0.0, Speed::Normal, GameMode::Cube)
+    }
+}
+
+impl SpeedModel {
+    /// Create a model for a player starting at `x` with a speed and game mode.
+    #[must_use]
+    pub fn new(x: f64, speed: Speed, mode: GameMode) -> Self {
+        Self {
+            start: Portal { x, speed, mode },
+            portals: Vec::new(),
+            mode_factors: HashMap::new(),
+            portal_tolerance: 1.0,
+        }
+    }
+
+    /// Add a portal. Portals before the start are ignored.
+    #[must_use]
+    pub fn with_portal(mut self, portal: Portal) -> Self {
+        if portal.x > self.start.x {
+            let index = self.portals.partition_point(|p| p.x <= portal.x);
+            self.portals.insert(index, portal);
+        }
+        self
+    }
+
+    /// Multiply the speed of the player in a game mode.
+    #[must_use]
+    pub fn with_mode_factor(mut self, mode: GameMode, factor: f64) -> Self {
+        self.mode_factors.insert(mode, factor);
+        self
+    }
+
+    /// Set how close to a portal, in units, a placement has to be to be flagged as ambiguous.
+    #[must_use]
+    pub fn with_portal_tolerance(mut self, tolerance: f64) -> Self {
+        self.portal_tolerance = tolerance;
+        self
+    }
+
+    fn units_per_second(&self, portal: &Portal) -> f64 {
+        portal.speed.units_per_second() * self.mode_factors.get(&portal.mode).unwrap_or(&1.0)
+    }
+
+    /// Seconds the player takes from the start to `x`, or [`None`] if `x` is before the start.
+    #[must_use]
+    pub fn time_at(&self, x: f64) -> Option<f64> {
+        if x < self.start.x {
+            return None;
+        }
+
+        let mut time = 0.0;
+        let mut section = &self.start;
+        for portal in self.portals.iter().take_while(|portal| portal.x < x) {
+            time += (portal.x - section.x) / self.units_per_second(section);
+            section = portal;
+        }
+
+        Some(time + (x - section.x) / self.units_per_second(section))
+    }
+
+    /// Where the player is after `time` seconds from the start.
+    #[must_use]
+    pub fn x_at(&self, time: f64) -> f64 {
+        let mut remaining = time;
+        let mut section = &self.start;
+        for portal in &self.portals {
+            let duration = (portal.x - section.x) / self.units_per_second(section);
+            if remaining < duration {
+                break;
+            }
+
+            remaining -= duration;
+            section = portal;
+        }
+
+        section.x + remaining * self.units_per_second(section)
+    }
+
+    /// Whether `x` is within the portal tolerance of a portal.
+    #[must_use]
+    pub fn near_portal(&self, x: f64) -> bool {
+        self.portals
+            .iter()
+            .any(|portal| (portal.x - x).abs() <= self.portal_tolerance)
+    }
+}
+
+/// Known x positions of Player 1, by frame.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PositionTrack {
+    /// Sorted by frame, at most one position per frame.
+    points: Vec<(u64, f64)>,
+}
+
+impl PositionTrack {
+    /// Create an empty track.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collect the positions recorded on the frame-timed actions of a replay.
+    ///
+    /// Actions timed by x position or in seconds are ignored, so this is empty for replays in
+    /// formats timed by x position.
+    #[must_use]
+    pub fn from_replay(replay: &Replay) -> Self {
+        let mut track = Self::new();
+        for timed in &replay.actions {
+            if let (TimePoint::Frame(frame), Some(player1)) = (
+                timed.time,
+                timed.position.and_then(|position| position.player1),
+            ) {
+                track.insert(frame, player1.x);
+            }
+        }
+        track
+    }
+
+    /// Record Player 1's x position on a frame, replacing any previous one.
+    pub fn insert(&mut self, frame: u64, x: f64) {
+        match self.points.binary_search_by_key(&frame, |(f, _)| *f) {
+            Ok(index) => self.points[index].1 = x,
+            Err(index) => self.points.insert(index, (frame, x)),
+        }
+    }
+
+    /// Whether no positions are known.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Where the player is on `frame`, if the track covers it.
+    ///
+    /// Between known frames the player is assumed to move in a straight line.
+    #[allow(clippy::cast_precision_loss)]
+    fn x_at(&self, frame: u64) -> Option<f64> {
+        let index = self.points.partition_point(|(f, _)| *f < frame);
+        let &(f1, x1) = self.points.get(index)?;
+        if f1 == frame {
+            return Some(x1);
+        }
+
+        let &(f0, x0) = self.points.get(index.checked_sub(1)?)?;
+        Some(x0 + (x1 - x0) * (frame - f0) as f64 / (f1 - f0) as f64)
+    }
+
+    /// The last known position that hasn't reached `x`.
+    fn last_before_x(&self, x: f64) -> Option<(u64, f64)> {
+        self.points.iter().rev().find(|(_, px)| *px < x).copied()
+    }
+
+    /// The last known position before `frame`.
+    fn last_before_frame(&self, frame: u64) -> Option<(u64, f64)> {
+        let index = self.points.partition_point(|(f, _)| *f < frame);
+        index.checked_sub(1).map(|index| self.points[index])
+    }
+
+    /// Frames on which the player first reaches `x`, once for every time it moves past it.
+    ///
+    /// The player starts at its first known position, so it reaches that position on its
+    /// frame. Between known frames it is assumed to move in a straight line.
+    #[allow(
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss,
+        clippy::cast_precision_loss,
+        clippy::float_cmp
+    )]
+    fn crossings(&self, x: f64) -> Vec<u64> {
+        let start = self
+            .points
+            .first()
+            .filter(|(_, x0)| *x0 == x)
+            .map(|(f0, _)| *f0);
+
+        start
+            .into_iter()
+            .chain(
+                self.points
+                    .windows(2)
+                    .filter(|pair| pair[0].1 < x && x <= pair[1].1)
+                    .map(|pair| {
+                        let ((f0, x0), (f1, x1)) = (pair[0], pair[1]);
+                        let frame = f0 as f64 + (x - x0) / (x1 - x0) * (f1 - f0) as f64;
+                        (frame - 1e-9).ceil() as u64
+                    }),
+            )
+            .collect()
+    }
+}
+
+/// Where the frame of a placed action came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PlacementSource {
+    /// The player's position track.
+    Track,
+    /// The constant speed model.
+    Model,
+}
+
+/// Why the frame of a placed action may be wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Ambiguity {
+    /// The player moves past the position more than once. The first crossing was used.
+    MultipleCrossings,
+    /// The position track doesn't cover the action, so the model was used.
+    OutsideTrack,
+    /// The position is close to a portal of the model.
+    NearPortal,
+    /// The position is before the start of the model, so the action was put on frame 0.
+    BeforeStart,
+}
+
+/// An x position action placed on a frame, or a frame action placed at an x position.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placement {
+    /// Index of the action in the replay.
+    pub index: usize,
+    /// The x position of the action.
+    pub x: f64,
+    /// The frame of the action.
+    pub frame: u64,
+    /// Where the placed frame or x position came from.
+    pub source: PlacementSource,
+    /// Why the placement may be wrong, if it may be.
+    pub ambiguity: Option<Ambiguity>,
+}
+
+/// The outcome of retiming x position or frame actions.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct XPosReport {
+    /// Every placed action.
+    pub placements: Vec<Placement>,
+}
+
+impl XPosReport {
+    /// Placements that may be wrong.
+    pub fn ambiguous(&self) -> impl Iterator<Item = &Placement> {
+        self.placements
+            .iter()
+            .filter(|placement| placement.ambiguity.is_some())
+    }
+}
+
+/// Place every x position action of a replay on a frame.
+///
+/// The track is used where it reaches an action's position, the model everywhere else,
+/// starting from the last known position before the action. Model times are turned into
+/// frames with the replay's [`TpsTimeline`].
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss,
+    clippy::cast_precision_loss
+)]
+pub fn retime_xpos(replay: &mut Replay, model: &SpeedModel, track: &PositionTrack) -> XPosReport {
+    let timeline = TpsTimeline::from_replay(replay);
+    let mut report = XPosReport::default();
+
+    for (index, timed) in replay.actions.iter_mut().enumerate() {
+        let TimePoint::XPos(x) = timed.time else {
+            continue;
+        };
+
+        let crossings = track.crossings(x);
+        let (frame, source, ambiguity) = if let Some(&frame) = crossings.first() {
+            let ambiguity = (crossings.len() > 1).then_some(Ambiguity::MultipleCrossings);
+            (frame, PlacementSource::Track, ambiguity)
+        } else if let Some(time) = track
+            .last_before_x(x)
+            .and_then(|(f0, x0)| {
+                Some(timeline.frame_to_time(f0 as f64) + model.time_at(x)? - model.time_at(x0)?)
+            })
+            .or_else(|| model.time_at(x))
+        {
+            let frame = (timeline.time_to_frame(time) - 1e-9).ceil().max(0.0) as u64;
+            let ambiguity = if track.is_empty() {
+                model.near_portal(x).then_some(Ambiguity::NearPortal)
+            } else {
+                Some(Ambiguity::OutsideTrack)
+            };
+            (frame, PlacementSource::Model, ambiguity)
+        } else {
+            (0, PlacementSource::Model, Some(Ambiguity::BeforeStart))
+        };
+
+        timed.time = TimePoint::Frame(frame);
+        report.placements.push(Placement {
+            index,
+            x,
+            frame,
+            source,
+            ambiguity,
+        });
+    }
+
+    report
+}
+
+/// Place every frame action of a replay at an x position.
+///
+/// The track is used where it covers an action's frame, the model everywhere else, starting
+/// from the last known position before the action. Frames are turned into model times with
+/// the replay's [`TpsTimeline`].
+#[allow(clippy::cast_precision_loss)]
+pub fn frames_to_xpos(
+    replay: &mut Replay,
+    model: &SpeedModel,
+    track: &PositionTrack,
+) -> XPosReport {
+    let timeline = TpsTimeline::from_replay(replay);
+    let mut report = XPosReport::default();
+
+    for (index, timed) in replay.actions.iter_mut().enumerate() {
+        let TimePoint::Frame(frame) = timed.time else {
+            continue;
+        };
+
+        let (x, source, ambiguity) = if let Some(x) = track.x_at(frame) {
+            (x, PlacementSource::Track, None)
+        } else {
+            let time = timeline.frame_to_time(frame as f64);
+            let x = track
+                .last_before_frame(frame)
+                .and_then(|(f0, x0)| {
+                    let since = time - timeline.frame_to_time(f0 as f64);
+                    Some(model.x_at(model.time_at(x0)? + since))
+                })
+                .unwrap_or_else(|| model.x_at(time));
+            let ambiguity = if track.is_empty() {
+                model.near_portal(x).then_some(Ambiguity::NearPortal)
+            } else {
+                Some(Ambiguity::OutsideTrack)
+            };
+            (x, PlacementSource::Model, ambiguity)
+        };
+
+        timed.time = TimePoint::XPos(x);
+        report.placements.push(Placement {
+            index,
+            x,
+            frame,
+            source,
+            ambiguity,
+        });
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        action::{
+            Action, FrameCountingMethod, PlayerButton, PlayerPosition, Position, TimedAction,
+        },
+        format::Format,
+        meta::MetaMap,
+        version::GameVersion,
+    };
+
+    fn click(time: TimePoint, x: Option<f64>) -> TimedAction {
+        let player = |x| PlayerPosition {
+            x,
+            y: 0.0,
+            rotation: None,
+            vel_x: None,
+            vel_y: None,
+        };
+
+        TimedAction {
+            time,
+            action: Action::Player {
+                button: PlayerButton::Jump,
+                hold: true,
+                player2: false,
+            },
+            position: x.map(|x| Position {
+                player1: Some(player(x)),
+                player2: Some(player(x)),
+            }),
+        }
+    }
+
+    fn replay(actions: Vec<TimedAction>) -> Replay {
+        let mut meta = MetaMap::new();
+        meta.insert("tps", "240");
+
+        Replay {
+            meta: Box::new(meta),
+            actions,
+            format: Format::ZBotXpos,
+            game_version: GameVersion::new(21, 130),
+            frame_counting: FrameCountingMethod::LevelTime,
+        }
+    }
+
+    #[test]
+    fn test_retime_xpos_with_model() {
+        let model = SpeedModel::default().with_portal(Portal {
+            x: 311.58,
+            speed: Speed::Fast,
+            mode: GameMode::Ship,
+        });
+
+        let mut replay = replay(vec![
+            click(TimePoint::XPos(155.79), None),
+            click(TimePoint::XPos(311.58 + 387.42), None),
+            click(TimePoint::XPos(311.0), None),
+            click(TimePoint::XPos(-5.0), None),
+        ]);
+        let report = retime_xpos(&mut replay, &model, &PositionTrack::new());
+
+        assert_eq!(
+            report
+                .placements
+                .iter()
+                .map(|p| (p.frame, p.source, p.ambiguity))
+                .collect::<Vec<_>>(),
+            vec![
+                (120, PlacementSource::Model, None),
+                (480, PlacementSource::Model, None),
+                (240, PlacementSource::Model, Some(Ambiguity::NearPortal)),
+                (0, PlacementSource::Model, Some(Ambiguity::BeforeStart)),
+            ]
+        );
+        assert_eq!(replay.actions[1].time, TimePoint::Frame(480));
+    }
+
+    #[test]
+    fn test_retime_xpos_with_track() {
+        let mut replay = replay(vec![
+            click(TimePoint::Frame(0), Some(0.0)),
+            click(TimePoint::Frame(10), Some(10.0)),
+            click(TimePoint::Frame(20), Some(20.0)),
+            click(TimePoint::Frame(30), Some(5.0)),
+            click(TimePoint::Frame(40), Some(30.0)),
+            click(TimePoint::XPos(4.5), None),
+            click(TimePoint::XPos(25.0), None),
+            click(TimePoint::XPos(100.0), None),
+            click(TimePoint::XPos(15.0), None),
+            click(TimePoint::XPos(0.0), None),
+        ]);
+
+        let track = PositionTrack::from_replay(&replay);
+        let report = retime_xpos(&mut replay, &SpeedModel::default(), &track);
+
+        assert_eq!(
+            report
+                .placements
+                .iter()
+                .map(|p| (p.index, p.frame, p.source, p.ambiguity))
+                .collect::<Vec<_>>(),
+            vec![
+                (5, 5, PlacementSource::Track, None),
+                (6, 38, PlacementSource::Track, None),
+                (7, 94, PlacementSource::Model, Some(Ambiguity::OutsideTrack)),
+                (
+                    8,
+                    15,
+                    PlacementSource::Track,
+                    Some(Ambiguity::MultipleCrossings)
+                ),
+                (9, 0, PlacementSource::Track, None),
+            ]
+        );
+        assert_eq!(report.ambiguous().count(), 2);
+    }
+
+    #[test]
+    fn test_frames_to_xpos() {
+        let model = SpeedModel::default().with_portal(Portal {
+            x: 311.58,
+            speed: Speed::Fast,
+            mode: GameMode::Ship,
+        });
+
+        let mut replay = replay(vec![
+            click(TimePoint::Frame(120), None),
+            click(TimePoint::Frame(480), None),
+            click(TimePoint::Frame(240), None),
+        ]);
+        let report = frames_to_xpos(&mut replay, &model, &PositionTrack::new());
+
+        let placed = report
+            .placements
+            .iter()
+            .map(|p| ((p.x * 100.0).round() / 100.0, p.ambiguity))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            placed,
+            vec![
+                (155.79, None),
+                (699.0, None),
+                (311.58, Some(Ambiguity::NearPortal)),
+            ]
+        );
+        assert!(matches!(replay.actions[0].time, TimePoint::XPos(_)));
+
+        let mut replay = self::replay(vec![
+            click(TimePoint::Frame(0), Some(0.0)),
+            click(TimePoint::Frame(10), Some(20.0)),
+            click(TimePoint::Frame(5), None),
+            click(TimePoint::Frame(20), None),
+        ]);
+        let track = PositionTrack::from_replay(&replay);
+        let report = frames_to_xpos(&mut replay, &SpeedModel::default(), &track);
+        assert_eq!(
+            report.placements[2..]
+                .iter()
+                .map(|p| (p.x.round(), p.source, p.ambiguity))
+                .collect::<Vec<_>>(),
+            vec![
+                (10.0, PlacementSource::Track, None),
+                (33.0, PlacementSource::Model, Some(Ambiguity::OutsideTrack)),
+            ]
+        );
+    }
+}
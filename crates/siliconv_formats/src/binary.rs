@@ -0,0 +1,113 @@
+//! Little-endian binary helpers shared by the binary replay formats.
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+macro_rules! impl_read_write {
+    ($(($read:ident, $write:ident, $t:ty)),*) => {
+        /// Extension trait for reading little-endian primitives.
+        pub(crate) trait ReadBytes: Read {
+            $(
+                fn $read(&mut self) -> io::Result<$t> {
+                    let mut buf = [0u8; size_of::<$t>()];
+                    self.read_exact(&mut buf)?;
+                    Ok(<$t>::from_le_bytes(buf))
+                }
+            )*
+
+            /// Read an unsigned LEB128 varint.
+            fn read_varint(&mut self) -> io::Result<u64> {
+                let mut value = 0u64;
+                for shift in (0..64).step_by(7) {
+                    let byte = self.read_u8()?;
+                    value |= u64::from(byte & 0x7F) << shift;
+                    if byte & 0x80 == 0 {
+                        return Ok(value);
+                    }
+                }
+
+                Err(io::Error::new(io::ErrorKind::InvalidData, "varint is too long"))
+            }
+
+            /// Read a varint length-prefixed byte buffer.
+            fn read_bytes(&mut self) -> io::Result<Vec<u8>> {
+                let len = usize::try_from(self.read_varint()?)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                let mut buf = Vec::new();
+                self.take(len as u64).read_to_end(&mut buf)?;
+                if buf.len() != len {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                Ok(buf)
+            }
+
+            /// Read a varint length-prefixed UTF-8 string.
+            fn read_string(&mut self) -> io::Result<String> {
+                String::from_utf8(self.read_bytes()?)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            }
+        }
+
+        /// Extension trait for writing little-endian primitives.
+        pub(crate) trait WriteBytes: Write {
+            $(
+                fn $write(&mut self, value: $t) -> io::Result<()> {
+                    self.write_all(&value.to_le_bytes())
+                }
+            )*
+
+            /// Write an unsigned LEB128 varint.
+            fn write_varint(&mut self, mut value: u64) -> io::Result<()> {
+                loop {
+                    #[allow(clippy::cast_possible_truncation)]
+                    let byte = (value & 0x7F) as u8;
+                    value >>= 7;
+                    if value == 0 {
+                        return self.write_u8(byte);
+                    }
+                    self.write_u8(byte | 0x80)?;
+                }
+            }
+
+            /// Write a varint length-prefixed byte buffer.
+            fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+                self.write_varint(bytes.len() as u64)?;
+                self.write_all(bytes)
+            }
+
+            /// Write a varint length-prefixed UTF-8 string.
+            fn write_string(&mut self, s: &str) -> io::Result<()> {
+                self.write_bytes(s.as_bytes())
+            }
+        }
+    };
+}
+
+impl_read_write!(
+    (read_u8, write_u8, u8),
+    (read_i16, write_i16, i16),
+    (read_u32, write_u32, u32),
+    (read_i32, write_i32, i32),
+    (read_u64, write_u64, u64),
+    (read_f32, write_f32, f32),
+    (read_f64, write_f64, f64)
+);
+
+impl<R: Read> ReadBytes for R {}
+impl<W: Write> WriteBytes for W {}
+
+/// Returns the number of bytes left between the current position and the end of the stream.
+pub(crate) fn remaining<S: Seek + ?Sized>(stream: &mut S) -> io::Result<u64> {
+    let current = stream.stream_position()?;
+    let end = stream.seek(SeekFrom::End(0))?;
+    stream.seek(SeekFrom::Start(current))?;
+    Ok(end - current)
+}
+
+/// Reads up to `len` bytes from the current position, then seeks back to it.
+pub(crate) fn peek<R: Read + Seek>(reader: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let start = reader.stream_position()?;
+    let mut buf = Vec::with_capacity(len);
+    (&mut *reader).take(len as u64).read_to_end(&mut buf)?;
+    reader.seek(SeekFrom::Start(start))?;
+    Ok(buf)
+}
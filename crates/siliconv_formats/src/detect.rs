@@ -0,0 +1,224 @@
+//! Format detection using the sniffers of every format in this crate.
+
+use siliconv_core::format::Detector;
+
+use crate::{
+    echo, gdr, gdr2, kdbot, mhr, omegabot, plaintext, replaybot, replayengine, rush, silicate,
+    tasbot, tcbot, uvbot, xbot, xdbot, ybot, zbot,
+};
+
+/// A detector knowing about every format in this crate.
+#[must_use]
+pub fn detector() -> Detector {
+    Detector::new()
+        .with_sniffer(plaintext::sniff)
+        .with_sniffer(omegabot::sniff)
+        .with_sniffer(mhr::sniff)
+        .with_sniffer(tasbot::sniff)
+        .with_sniffer(zbot::sniff)
+        .with_sniffer(replaybot::sniff)
+        .with_sniffer(echo::sniff)
+        .with_sniffer(ybot::sniff)
+        .with_sniffer(xbot::sniff)
+        .with_sniffer(rush::sniff)
+        .with_sniffer(kdbot::sniff)
+        .with_sniffer(gdr::sniff)
+        .with_sniffer(gdr2::sniff)
+        .with_sniffer(xdbot::sniff)
+        .with_sniffer(replayengine::sniff)
+        .with_sniffer(silicate::sniff)
+        .with_sniffer(uvbot::sniff)
+        .with_sniffer(tcbot::sniff)
+}
+
+/// Whether JSON data contains `key` as a quoted string, without parsing it.
+pub(crate) fn json_contains_key(data: &[u8], key: &str) -> bool {
+    data.windows(key.len() + 2).any(|window| {
+        window[0] == b'"'
+            && window[key.len() + 1] == b'"'
+            && &window[1..=key.len()] == key.as_bytes()
+    })
+}
+
+/// Whether a frame rate read from a headerless file is believable.
+pub(crate) fn plausible_fps(fps: f64) -> bool {
+    fps.is_finite() && (1.0..=100_000.0).contains(&fps)
+}
+
+/// Whether the locations starting every `stride`-byte record are frames rather than `f32` x
+/// positions, or [`None`] if there are no records.
+///
+/// Read as an `f32`, any frame below 2^23 is zero or subnormal, which an x position never
+/// is, so records where every location looks like that hold frames.
+pub(crate) fn locations_are_frames(records: &[u8], stride: usize) -> Option<bool> {
+    let mut locations = records
+        .chunks_exact(stride)
+        .map(|record| u32::from_le_bytes([record[0], record[1], record[2], record[3]]))
+        .peekable();
+
+    locations.peek()?;
+    Some(locations.all(|bits| bits < 0x0080_0000))
+}
+
+/// Reads the little-endian `u32` at `offset`, if there is one.
+pub(crate) fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+    data.get(offset..offset + 4)?
+        .try_into()
+        .ok()
+        .map(u32::from_le_bytes)
+}
+
+/// Reads the little-endian `f32` at `offset`, if there is one.
+pub(crate) fn f32_at(data: &[u8], offset: usize) -> Option<f32> {
+    u32_at(data, offset).map(f32::from_bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use siliconv_core::{
+        action::{Action, PlayerButton, TimePoint, TimedAction},
+        format::{Confidence, Format},
+        registry::Registry,
+        replay::{Replay, ReplaySerializable},
+        version::GameVersion,
+    };
+
+    use super::*;
+
+    fn replay(format: Format, time: TimePoint) -> Replay {
+        #[derive(siliconv_macros::Meta)]
+        struct TpsMeta {
+            tps: f64,
+        }
+
+        Replay {
+            meta: Box::new(TpsMeta { tps: 240.0 }),
+            actions: vec![TimedAction {
+                time,
+                action: Action::Player {
+                    button: PlayerButton::Jump,
+                    hold: true,
+                    player2: false,
+                },
+                position: None,
+            }],
+            format,
+            game_version: GameVersion::new(22, 0),
+            frame_counting: format.descriptor().frame_counting,
+        }
+    }
+
+    fn written<T: ReplaySerializable>(format: Format, time: TimePoint) -> Vec<u8> {
+        let mut buf = Vec::new();
+        T::write_as(&replay(format, time), format, &mut buf).unwrap();
+        buf
+    }
+
+    fn written_with(registry: &Registry, format: Format, time: TimePoint) -> Vec<u8> {
+        let mut buf = Vec::new();
+        registry
+            .write(format, &replay(format, time), &mut buf)
+            .unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_detector_resolves_shared_extensions() {
+        let detector = detector();
+
+        let replaybot =
+            written::<replaybot::ReplayBotReplay>(Format::ReplayBot, TimePoint::Frame(5));
+        let omegabot = written::<omegabot::OmegaBotReplay>(Format::OmegaBot2, TimePoint::Frame(5));
+        assert_eq!(
+            detector.best(&replaybot, Some("a.replay")),
+            Some(Format::ReplayBot)
+        );
+        assert_eq!(
+            detector.best(&omegabot, Some("a.replay")),
+            Some(Format::OmegaBot2)
+        );
+
+        for format in [Format::EchoOld, Format::EchoNewJson, Format::EchoNewBinary] {
+            let echo = written::<echo::EchoReplay>(format, TimePoint::Frame(5));
+            assert_eq!(detector.best(&echo, Some("a.echo")), Some(format));
+        }
+    }
+
+    #[test]
+    fn test_detector_without_extension() {
+        let detector = detector();
+        let registry = crate::registry::registry();
+
+        for (format, data) in [
+            (
+                Format::YBot1,
+                written::<ybot::YBotReplay>(Format::YBot1, TimePoint::Frame(5)),
+            ),
+            (
+                Format::GDR1,
+                written::<gdr::GdrReplay>(Format::GDR1, TimePoint::Frame(5)),
+            ),
+            (
+                Format::GDR1Json,
+                written::<gdr::GdrReplay>(Format::GDR1Json, TimePoint::Frame(5)),
+            ),
+            (
+                Format::GDR2,
+                written::<gdr2::Gdr2Replay>(Format::GDR2, TimePoint::Frame(5)),
+            ),
+            (
+                Format::Slc3,
+                written::<silicate::SilicateReplay>(Format::Slc3, TimePoint::Frame(5)),
+            ),
+            (
+                Format::TCBot,
+                written::<tcbot::TcBotReplay>(Format::TCBot, TimePoint::Frame(5)),
+            ),
+            (
+                Format::PlainText,
+                written::<plaintext::PlainTextReplay>(Format::PlainText, TimePoint::Frame(5)),
+            ),
+            (
+                Format::XBot,
+                written::<xbot::XBotReplay>(Format::XBot, TimePoint::Frame(5)),
+            ),
+        ] {
+            let candidates = detector.detect(&data, None);
+            assert_eq!(candidates[0].format, format);
+            assert_eq!(candidates[0].confidence, Confidence::High);
+
+            // The first candidate must also be readable as that format.
+            assert!(
+                registry.read(format, &mut Cursor::new(data)).is_ok(),
+                "{format:?} is detected but can't be read"
+            );
+        }
+    }
+
+    #[test]
+    fn test_detector_finds_every_written_format() {
+        let detector = detector();
+        let registry = crate::registry::registry();
+
+        for &format in Format::ALL {
+            let time = match format {
+                Format::OmegaBot | Format::ZBotXpos => TimePoint::XPos(120.25),
+                _ => TimePoint::Frame(5),
+            };
+            let data = written_with(&registry, format, time);
+            let file_name = format
+                .descriptor()
+                .extensions
+                .first()
+                .map(|extension| format!("a.{extension}"));
+
+            assert_eq!(
+                detector.best(&data, file_name.as_deref()),
+                Some(format),
+                "{format:?} is detected as something else"
+            );
+        }
+    }
+}
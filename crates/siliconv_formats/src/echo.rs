@@ -0,0 +1,793 @@
+//! Echo replays.
+//!
+//! Old and new Echo replays share the .echo extension. Both JSON flavours store the same
+//! data under different keys (`"FPS"`, `"Echo Replay"`, ... in old replays):
+//!
+//! ```json
+//! { "fps": 240.0, "starting_frame": 0, "starting_x": 0.0, "starting_y": 105.0,
+//!   "inputs": [{ "frame": 10, "holding": true, "player_2": false, "x_position": 1.0, ... }],
+//!   "practice_fixes": [{ "frame": 5, "player_1": { ... }, "player_2": { ... } }] }
+//! ```
+//!
+//! The binary flavour stores the same data little-endian:
+//!
+//! ```text
+//! "MYEC" version:u8(=1) fps:f64 starting_frame:u32 starting_x:f32 starting_y:f32
+//! count:u32 { frame:u32 flags:u8 [state] }*
+//! count:u32 { frame:u32 state state }*
+//! state: x:f32 y:f32 rotation:f32 y_vel:f64
+//! ```
+//!
+//! Input flags are bit 0 (holding), bit 1 (player 2), bit 2 (has state), bit 3 (state has no
+//! rotation) and bit 4 (state has no y velocity). Inputs only carry the state of the clicking
+//! player, practice fixes carry full states of both, with a missing rotation or y velocity
+//! written as 0.
+//!
+//! Input frames count from the start of the level. `starting_frame` is where the recording
+//! began, so it is kept in the meta and not added to the frames.
+
+use std::io::{Read, Seek, Write};
+
+use serde_json::{Map, Value};
+use siliconv_core::{
+    action::{
+        Action, FrameCountingMethod, PlayerButton, PlayerPosition, Position, TimePoint, TimedAction,
+    },
+    error::ReplayError,
+    format::{Confidence, Format},
+    meta::Meta,
+    replay::{Replay, ReplaySerializable},
+    version::GameVersion,
+};
+use siliconv_macros::Meta;
+
+use crate::{
+    binary::{ReadBytes, WriteBytes},
+    detect,
+};
+
+const MAGIC: &[u8; 4] = b"MYEC";
+const VERSION: u8 = 1;
+
+const HOLDING: u8 = 0b0_0001;
+const PLAYER_2: u8 = 0b0_0010;
+const HAS_STATE: u8 = 0b0_0100;
+const NO_ROTATION: u8 = 0b0_1000;
+const NO_Y_VELOCITY: u8 = 0b1_0000;
+
+#[derive(Meta)]
+pub struct EchoMeta {
+    #[meta(default = 240.0)]
+    pub tps: f64,
+    pub starting_frame: u32,
+    pub starting_x: f32,
+    pub starting_y: f32,
+    /// Practice mode fixes as a JSON array, using the new JSON keys.
+    pub practice_fixes: String,
+}
+
+/// An Echo replay format version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EchoVersion {
+    /// Old Echo JSON replay.
+    Old,
+    /// New Echo JSON replay.
+    NewJson,
+    /// New Echo binary replay.
+    NewBinary,
+}
+
+impl EchoVersion {
+    /// The replay format corresponding to this version.
+    #[must_use]
+    pub const fn format(self) -> Format {
+        match self {
+            Self::Old => Format::EchoOld,
+            Self::NewJson => Format::EchoNewJson,
+            Self::NewBinary => Format::EchoNewBinary,
+        }
+    }
+
+    /// The Echo version of a replay format, if it is an Echo format.
+    #[must_use]
+    pub const fn from_format(format: Format) -> Option<Self> {
+        match format {
+            Format::EchoOld => Some(Self::Old),
+            Format::EchoNewJson => Some(Self::NewJson),
+            Format::EchoNewBinary => Some(Self::NewBinary),
+            _ => None,
+        }
+    }
+
+    /// Picks the version of an .echo file from its contents.
+    ///
+    /// Returns [`None`] if the data doesn't look like an Echo replay.
+    #[must_use]
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(MAGIC) {
+            Some(Self::NewBinary)
+        } else if detect::json_contains_key(data, Keys::OLD.inputs) {
+            Some(Self::Old)
+        } else if detect::json_contains_key(data, Keys::NEW.inputs) {
+            Some(Self::NewJson)
+        } else {
+            None
+        }
+    }
+}
+
+/// JSON keys of an Echo flavour.
+struct Keys {
+    fps: &'static str,
+    starting_frame: &'static str,
+    starting_x: &'static str,
+    starting_y: &'static str,
+    inputs: &'static str,
+    practice_fixes: &'static str,
+    frame: &'static str,
+    holding: &'static str,
+    player_2: &'static str,
+    players: [&'static str; 2],
+    x: &'static str,
+    y: &'static str,
+    rotation: &'static str,
+    y_vel: &'static str,
+}
+
+impl Keys {
+    const OLD: Keys = Keys {
+        fps: "FPS",
+        starting_frame: "Starting Frame",
+        starting_x: "Starting X",
+        starting_y: "Starting Y",
+        inputs: "Echo Replay",
+        practice_fixes: "Practice Fixes",
+        frame: "Frame",
+        holding: "Hold",
+        player_2: "Player 2",
+        players: ["Player 1", "Player 2"],
+        x: "X Position",
+        y: "Y Position",
+        rotation: "Rotation",
+        y_vel: "Y Velocity",
+    };
+
+    const NEW: Keys = Keys {
+        fps: "fps",
+        starting_frame: "starting_frame",
+        starting_x: "starting_x",
+        starting_y: "starting_y",
+        inputs: "inputs",
+        practice_fixes: "practice_fixes",
+        frame: "frame",
+        holding: "holding",
+        player_2: "player_2",
+        players: ["player_1", "player_2"],
+        x: "x_position",
+        y: "y_position",
+        rotation: "rotation",
+        y_vel: "y_velocity",
+    };
+
+    fn read_state(&self, value: &Value) -> Option<PlayerPosition> {
+        Some(PlayerPosition {
+            x: value[self.x].as_f64()?,
+            y: value[self.y].as_f64()?,
+            rotation: value[self.rotation].as_f64(),
+            vel_x: None,
+            vel_y: value[self.y_vel].as_f64(),
+        })
+    }
+
+    fn write_state(&self, state: &PlayerPosition, object: &mut Map<String, Value>) {
+        object.insert(self.x.to_string(), state.x.into());
+        object.insert(self.y.to_string(), state.y.into());
+        if let Some(rotation) = state.rotation {
+            object.insert(self.rotation.to_string(), rotation.into());
+        }
+        if let Some(y_vel) = state.vel_y {
+            object.insert(self.y_vel.to_string(), y_vel.into());
+        }
+    }
+
+    fn read_frame(&self, value: &Value) -> Result<u32, ReplayError> {
+        value[self.frame]
+            .as_u64()
+            .and_then(|frame| u32::try_from(frame).ok())
+            .ok_or_else(|| ReplayError::ReadError("echo record has an invalid frame".to_string()))
+    }
+}
+
+fn read_state<R: Read>(reader: &mut R) -> Result<PlayerPosition, ReplayError> {
+    Ok(PlayerPosition {
+        x: f64::from(reader.read_f32()?),
+        y: f64::from(reader.read_f32()?),
+        rotation: Some(f64::from(reader.read_f32()?)),
+        vel_x: None,
+        vel_y: Some(reader.read_f64()?),
+    })
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn write_state<W: Write>(writer: &mut W, state: &PlayerPosition) -> Result<(), ReplayError> {
+    writer.write_f32(state.x as f32)?;
+    writer.write_f32(state.y as f32)?;
+    writer.write_f32(state.rotation.unwrap_or_default() as f32)?;
+    writer.write_f64(state.vel_y.unwrap_or_default())?;
+    Ok(())
+}
+
+fn count(len: usize) -> Result<u32, ReplayError> {
+    u32::try_from(len).map_err(|_| ReplayError::WriteError("too many echo records".to_string()))
+}
+
+/// A single Echo input.
+struct Input {
+    frame: u32,
+    holding: bool,
+    player2: bool,
+    state: Option<PlayerPosition>,
+}
+
+impl Input {
+    fn into_action(self) -> TimedAction {
+        // Echo only records the clicking player.
+        let position = self.state.map(|state| {
+            if self.player2 {
+                Position {
+                    player1: None,
+                    player2: Some(state),
+                }
+            } else {
+                Position {
+                    player1: Some(state),
+                    player2: None,
+                }
+            }
+        });
+
+        TimedAction {
+            time: TimePoint::Frame(u64::from(self.frame)),
+            action: Action::Player {
+                button: PlayerButton::Jump,
+                hold: self.holding,
+                player2: self.player2,
+            },
+            position,
+        }
+    }
+
+    fn from_action(timed: &TimedAction) -> Result<Option<Self>, ReplayError> {
+        let (holding, player2) = match timed.action {
+            Action::Empty => return Ok(None),
+            Action::Player {
+                button: PlayerButton::Jump,
+                hold,
+                player2,
+            } => (hold, player2),
+            _ => {
+                return Err(ReplayError::WriteError(format!(
+                    "echo does not support {:?} actions",
+                    timed.action
+                )));
+            }
+        };
+
+        let TimePoint::Frame(frame) = timed.time else {
+            return Err(ReplayError::WriteError(
+                "echo only supports frame time points".to_string(),
+            ));
+        };
+        let frame = u32::try_from(frame)
+            .map_err(|_| ReplayError::WriteError(format!("frame {frame} is too large for echo")))?;
+
+        Ok(Some(Input {
+            frame,
+            holding,
+            player2,
+            state: timed.position.and_then(|position| {
+                if player2 {
+                    position.player2
+                } else {
+                    position.player1
+                }
+            }),
+        }))
+    }
+
+    fn read_binary<R: Read>(reader: &mut R) -> Result<Self, ReplayError> {
+        let frame = reader.read_u32()?;
+        let flags = reader.read_u8()?;
+        let state = if flags & HAS_STATE != 0 {
+            let mut state = read_state(reader)?;
+            if flags & NO_ROTATION != 0 {
+                state.rotation = None;
+            }
+            if flags & NO_Y_VELOCITY != 0 {
+                state.vel_y = None;
+            }
+            Some(state)
+        } else {
+            None
+        };
+
+        Ok(Input {
+            frame,
+            holding: flags & HOLDING != 0,
+            player2: flags & PLAYER_2 != 0,
+            state,
+        })
+    }
+
+    fn write_binary<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+        writer.write_u32(self.frame)?;
+        let mut flags = 0;
+        if self.holding {
+            flags |= HOLDING;
+        }
+        if self.player2 {
+            flags |= PLAYER_2;
+        }
+        if let Some(state) = &self.state {
+            flags |= HAS_STATE;
+            if state.rotation.is_none() {
+                flags |= NO_ROTATION;
+            }
+            if state.vel_y.is_none() {
+                flags |= NO_Y_VELOCITY;
+            }
+        }
+
+        writer.write_u8(flags)?;
+        if let Some(state) = &self.state {
+            write_state(writer, state)?;
+        }
+
+        Ok(())
+    }
+
+    fn read_json(keys: &Keys, value: &Value) -> Result<Self, ReplayError> {
+        Ok(Input {
+            frame: keys.read_frame(value)?,
+            holding: value[keys.holding].as_bool().unwrap_or_default(),
+            player2: value[keys.player_2].as_bool().unwrap_or_default(),
+            state: keys.read_state(value),
+        })
+    }
+
+    fn write_json(&self, keys: &Keys) -> Value {
+        let mut object = Map::new();
+        object.insert(keys.frame.to_string(), self.frame.into());
+        object.insert(keys.holding.to_string(), self.holding.into());
+        object.insert(keys.player_2.to_string(), self.player2.into());
+        if let Some(state) = &self.state {
+            keys.write_state(state, &mut object);
+        }
+
+        Value::Object(object)
+    }
+}
+
+/// A practice mode fix, restoring both players when respawning at a checkpoint.
+struct PracticeFix {
+    frame: u32,
+    players: [PlayerPosition; 2],
+}
+
+impl PracticeFix {
+    fn read_binary<R: Read>(reader: &mut R) -> Result<Self, ReplayError> {
+        Ok(PracticeFix {
+            frame: reader.read_u32()?,
+            players: [read_state(reader)?, read_state(reader)?],
+        })
+    }
+
+    fn write_binary<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+        writer.write_u32(self.frame)?;
+        for state in &self.players {
+            write_state(writer, state)?;
+        }
+
+        Ok(())
+    }
+
+    fn read_json(keys: &Keys, value: &Value) -> Result<Self, ReplayError> {
+        let state = |player: &str| {
+            keys.read_state(&value[player]).ok_or_else(|| {
+                ReplayError::ReadError("echo practice fix is missing a player".to_string())
+            })
+        };
+
+        Ok(PracticeFix {
+            frame: keys.read_frame(value)?,
+            players: [state(keys.players[0])?, state(keys.players[1])?],
+        })
+    }
+
+    fn write_json(&self, keys: &Keys) -> Value {
+        let mut object = Map::new();
+        object.insert(keys.frame.to_string(), self.frame.into());
+        for (key, state) in keys.players.iter().zip(&self.players) {
+            let mut player = Map::new();
+            keys.write_state(state, &mut player);
+            object.insert((*key).to_string(), Value::Object(player));
+        }
+
+        Value::Object(object)
+    }
+}
+
+/// Sniffs an .echo file, telling the old and new flavours apart by their contents.
+#[must_use]
+pub fn sniff(data: &[u8]) -> Option<(Format, Confidence)> {
+    let version = EchoVersion::detect(data)?;
+    // The keys of new JSON replays aren't unique to Echo.
+    let confidence = if version == EchoVersion::NewJson {
+        Confidence::Medium
+    } else {
+        Confidence::High
+    };
+
+    Some((version.format(), confidence))
+}
+
+pub struct EchoReplay {
+    inner: Replay,
+    version: EchoVersion,
+}
+
+impl EchoReplay {
+    /// Set the Echo version to write.
+    #[must_use]
+    pub fn with_version(mut self, version: EchoVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// The Echo version this replay will be written as.
+    #[must_use]
+    pub fn version(&self) -> EchoVersion {
+        self.version
+    }
+
+    fn read_binary<R: Read>(
+        reader: &mut R,
+    ) -> Result<(EchoMeta, Vec<Input>, Vec<PracticeFix>), ReplayError> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(ReplayError::ReadError("missing echo header".to_string()));
+        }
+
+        let version = reader.read_u8()?;
+        if version != VERSION {
+            return Err(ReplayError::ReadError(format!(
+                "unsupported echo version {version}"
+            )));
+        }
+
+        let meta = EchoMeta {
+            tps: reader.read_f64()?,
+            starting_frame: reader.read_u32()?,
+            starting_x: reader.read_f32()?,
+            starting_y: reader.read_f32()?,
+            practice_fixes: String::new(),
+        };
+
+        let count = reader.read_u32()?;
+        let inputs = (0..count)
+            .map(|_| Input::read_binary(reader))
+            .collect::<Result<_, _>>()?;
+
+        let count = reader.read_u32()?;
+        let fixes = (0..count)
+            .map(|_| PracticeFix::read_binary(reader))
+            .collect::<Result<_, _>>()?;
+
+        Ok((meta, inputs, fixes))
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn read_json(
+        keys: &Keys,
+        data: &[u8],
+    ) -> Result<(EchoMeta, Vec<Input>, Vec<PracticeFix>), ReplayError> {
+        let document: Value = serde_json::from_slice(data)
+            .map_err(|e| ReplayError::ReadError(format!("failed to read echo replay: {e}")))?;
+
+        let meta = EchoMeta {
+            tps: document[keys.fps].as_f64().unwrap_or(240.0),
+            starting_frame: document[keys.starting_frame]
+                .as_u64()
+                .and_then(|frame| u32::try_from(frame).ok())
+                .unwrap_or_default(),
+            starting_x: document[keys.starting_x].as_f64().unwrap_or_default() as f32,
+            starting_y: document[keys.starting_y].as_f64().unwrap_or_default() as f32,
+            practice_fixes: String::new(),
+        };
+
+        let inputs = document[keys.inputs]
+            .as_array()
+            .ok_or_else(|| ReplayError::ReadError("echo replay is missing its inputs".to_string()))?
+            .iter()
+            .map(|input| Input::read_json(keys, input))
+            .collect::<Result<_, _>>()?;
+
+        let fixes = document[keys.practice_fixes]
+            .as_array()
+            .map(|fixes| {
+                fixes
+                    .iter()
+                    .map(|fix| PracticeFix::read_json(keys, fix))
+                    .collect::<Result<_, _>>()
+            })
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok((meta, inputs, fixes))
+    }
+
+    fn write_json<W: Write>(
+        writer: &mut W,
+        keys: &Keys,
+        meta: &EchoMeta,
+        inputs: &[Input],
+        fixes: &[PracticeFix],
+    ) -> Result<(), ReplayError> {
+        let mut document = Map::new();
+        document.insert(keys.fps.to_string(), meta.tps.into());
+        document.insert(keys.starting_frame.to_string(), meta.starting_frame.into());
+        document.insert(keys.starting_x.to_string(), meta.starting_x.into());
+        document.insert(keys.starting_y.to_string(), meta.starting_y.into());
+        document.insert(
+            keys.inputs.to_string(),
+            inputs.iter().map(|input| input.write_json(keys)).collect(),
+        );
+        document.insert(
+            keys.practice_fixes.to_string(),
+            fixes.iter().map(|fix| fix.write_json(keys)).collect(),
+        );
+
+        serde_json::to_writer(writer, &document)
+            .map_err(|e| ReplayError::WriteError(format!("failed to write echo replay: {e}")))
+    }
+
+    fn write_binary<W: Write>(
+        writer: &mut W,
+        meta: &EchoMeta,
+        inputs: &[Input],
+        fixes: &[PracticeFix],
+    ) -> Result<(), ReplayError> {
+        writer.write_all(MAGIC)?;
+        writer.write_u8(VERSION)?;
+        writer.write_f64(meta.tps)?;
+        writer.write_u32(meta.starting_frame)?;
+        writer.write_f32(meta.starting_x)?;
+        writer.write_f32(meta.starting_y)?;
+
+        writer.write_u32(count(inputs.len())?)?;
+        for input in inputs {
+            input.write_binary(writer)?;
+        }
+
+        writer.write_u32(count(fixes.len())?)?;
+        for fix in fixes {
+            fix.write_binary(writer)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write a replay as the given version.
+    fn write_version<W: Write>(
+        replay: &Replay,
+        version: EchoVersion,
+        writer: &mut W,
+    ) -> Result<(), ReplayError> {
+        let meta = EchoMeta::from_fields(replay.meta.fields());
+
+        let inputs = replay
+            .actions
+            .iter()
+            .filter_map(|timed| Input::from_action(timed).transpose())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let fixes = if meta.practice_fixes.is_empty() {
+            Vec::new()
+        } else {
+            let fixes: Value = serde_json::from_str(&meta.practice_fixes).map_err(|e| {
+                ReplayError::WriteError(format!("invalid echo practice fixes: {e}"))
+            })?;
+
+            fixes
+                .as_array()
+                .map(|fixes| {
+                    fixes
+                        .iter()
+                        .map(|fix| PracticeFix::read_json(&Keys::NEW, fix))
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .transpose()
+                .map_err(|_| ReplayError::WriteError("invalid echo practice fixes".to_string()))?
+                .unwrap_or_default()
+        };
+
+        match version {
+            EchoVersion::Old => Self::write_json(writer, &Keys::OLD, &meta, &inputs, &fixes),
+            EchoVersion::NewJson => Self::write_json(writer, &Keys::NEW, &meta, &inputs, &fixes),
+            EchoVersion::NewBinary => Self::write_binary(writer, &meta, &inputs, &fixes),
+        }
+    }
+}
+
+impl ReplaySerializable for EchoReplay {
+    fn new(replay: Replay) -> Self {
+        let version = EchoVersion::from_format(replay.format).unwrap_or(EchoVersion::NewBinary);
+        EchoReplay {
+            inner: replay,
+            version,
+        }
+    }
+
+    fn into_replay(self) -> Replay {
+        self.inner
+    }
+
+    fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, ReplayError>
+    where
+        Self: Sized,
+    {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        let version = EchoVersion::detect(&data)
+            .ok_or_else(|| ReplayError::ReadError("not an echo replay".to_string()))?;
+        let (mut meta, inputs, fixes) = match version {
+            EchoVersion::Old => Self::read_json(&Keys::OLD, &data)?,
+            EchoVersion::NewJson => Self::read_json(&Keys::NEW, &data)?,
+            EchoVersion::NewBinary => Self::read_binary(&mut data.as_slice())?,
+        };
+
+        if !fixes.is_empty() {
+            let fixes = fixes
+                .iter()
+                .map(|fix| fix.write_json(&Keys::NEW))
+                .collect::<Value>();
+            meta.practice_fixes = fixes.to_string();
+        }
+
+        Ok(EchoReplay {
+            inner: Replay {
+                meta: Box::new(meta),
+                actions: inputs.into_iter().map(Input::into_action).collect(),
+                format: version.format(),
+                game_version: GameVersion::new(21, 130),
+                frame_counting: FrameCountingMethod::LevelTime,
+            },
+            version,
+        })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+        Self::write_version(&self.inner, self.version, writer)
+    }
+
+    fn write_as<W: Write>(
+        replay: &Replay,
+        format: Format,
+        writer: &mut W,
+    ) -> Result<(), ReplayError>
+    where
+        Self: Sized,
+    {
+        Self::write_version(
+            replay,
+            EchoVersion::from_format(format).unwrap_or(EchoVersion::NewBinary),
+            writer,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn state(x: f64, y: f64) -> PlayerPosition {
+        PlayerPosition {
+            x,
+            y,
+            rotation: Some(90.0),
+            vel_x: None,
+            vel_y: Some(-1.5),
+        }
+    }
+
+    #[test]
+    fn test_echo_versions_roundtrip() {
+        let actions = vec![
+            TimedAction {
+                time: TimePoint::Frame(10),
+                action: Action::Player {
+                    button: PlayerButton::Jump,
+                    hold: true,
+                    player2: false,
+                },
+                position: Some(Position {
+                    player1: Some(state(30.5, 105.0)),
+                    player2: None,
+                }),
+            },
+            TimedAction {
+                time: TimePoint::Frame(12),
+                action: Action::Player {
+                    button: PlayerButton::Jump,
+                    hold: true,
+                    player2: true,
+                },
+                position: Some(Position {
+                    player1: None,
+                    player2: Some(PlayerPosition {
+                        rotation: None,
+                        ..state(31.0, 225.0)
+                    }),
+                }),
+            },
+            TimedAction {
+                time: TimePoint::Frame(14),
+                action: Action::Player {
+                    button: PlayerButton::Jump,
+                    hold: false,
+                    player2: true,
+                },
+                position: None,
+            },
+        ];
+        let practice_fixes = r#"[{"frame":5,"player_1":{"rotation":90.0,"x_position":12.0,"y_position":15.0,"y_velocity":-1.5},"player_2":{"rotation":90.0,"x_position":12.0,"y_position":45.0,"y_velocity":-1.5}}]"#;
+
+        for version in [
+            EchoVersion::Old,
+            EchoVersion::NewJson,
+            EchoVersion::NewBinary,
+        ] {
+            let replay = EchoReplay::new(Replay {
+                meta: Box::new(EchoMeta {
+                    tps: 360.0,
+                    starting_frame: 4,
+                    starting_x: 12.0,
+                    starting_y: 15.0,
+                    practice_fixes: practice_fixes.to_string(),
+                }),
+                actions: actions.clone(),
+                format: version.format(),
+                game_version: GameVersion::new(21, 130),
+                frame_counting: FrameCountingMethod::LevelTime,
+            });
+
+            let mut buf = Vec::new();
+            replay.write(&mut buf).unwrap();
+            assert_eq!(EchoVersion::detect(&buf), Some(version));
+
+            let read = EchoReplay::read(&mut Cursor::new(buf)).unwrap();
+            assert_eq!(read.version(), version);
+
+            let read = read.into_replay();
+            let meta = EchoMeta::from_fields(read.meta.fields());
+            assert!(meta.tps.eq(&360.0));
+            assert_eq!(meta.starting_frame, 4);
+            assert!(meta.starting_x.eq(&12.0));
+            assert!(meta.starting_y.eq(&15.0));
+            assert_eq!(meta.practice_fixes, practice_fixes);
+            assert_eq!(read.actions, actions);
+        }
+    }
+
+    #[test]
+    fn test_echo_rejects_unknown_json() {
+        assert!(matches!(
+            EchoReplay::read(&mut Cursor::new(br#"{"macro": []}"#.to_vec())),
+            Err(ReplayError::ReadError(_))
+        ));
+    }
+}
@@ -0,0 +1,675 @@
+use std::io::{Read, Seek, Write};
+
+use serde::{Serialize, Serializer, de::DeserializeOwned};
+use serde_json::{Map, Value, json};
+use siliconv_core::{
+    action::{
+        Action, FrameCountingMethod, PlayerButton, PlayerPosition, Position, TimePoint, TimedAction,
+    },
+    error::ReplayError,
+    format::{Confidence, Format},
+    meta::Meta,
+    replay::{Replay, ReplaySerializable},
+    version::GameVersion,
+};
+use siliconv_macros::Meta;
+
+use crate::{binary, detect};
+
+/// The bot name used when a replay names none.
+const DEFAULT_BOT_NAME: &str = "siliconv";
+
+#[derive(Meta)]
+pub struct GdrMeta {
+    pub author: String,
+    pub description: String,
+    pub duration: f32,
+    pub game_version: f32,
+    #[meta(default = 1.0)]
+    pub version: f32,
+    #[meta(default = 240.0)]
+    pub tps: f64,
+    pub seed: u64,
+    pub coins: i32,
+    pub ldm: bool,
+    #[meta(default = DEFAULT_BOT_NAME.to_string())]
+    pub bot_name: String,
+    #[meta(default = 1)]
+    pub bot_version: i32,
+    pub level_id: u32,
+    pub level_name: String,
+    /// Unknown top-level keys, kept as a JSON object so they can be written back.
+    pub extensions: String,
+}
+
+/// The encoding of a GDR1 replay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GdrEncoding {
+    /// Msgpack-encoded .gdr replay.
+    Binary,
+    /// JSON-encoded .gdr.json replay.
+    Json {
+        /// Whether to indent the written JSON.
+        pretty: bool,
+    },
+}
+
+impl GdrEncoding {
+    /// The replay format corresponding to this encoding.
+    #[must_use]
+    pub const fn format(self) -> Format {
+        match self {
+            Self::Binary => Format::GDR1,
+            Self::Json { .. } => Format::GDR1Json,
+        }
+    }
+
+    /// The GDR encoding of a replay format, if it is a GDR format. JSON is compact.
+    #[must_use]
+    pub const fn from_format(format: Format) -> Option<Self> {
+        match format {
+            Format::GDR1 => Some(Self::Binary),
+            Format::GDR1Json => Some(Self::Json { pretty: false }),
+            _ => None,
+        }
+    }
+}
+
+/// Sniffs a GDR replay in either encoding.
+#[must_use]
+pub fn sniff(data: &[u8]) -> Option<(Format, Confidence)> {
+    match data.iter().find(|b| !b.is_ascii_whitespace())? {
+        b'{' => (detect::json_contains_key(data, "inputs")
+            && detect::json_contains_key(data, "gameVersion"))
+        .then_some((Format::GDR1Json, Confidence::High)),
+        // A msgpack map, whose keys are short enough to be fixstrs.
+        0x80..=0x8f | 0xde | 0xdf => {
+            let contains = |key: &[u8]| data.windows(key.len()).any(|window| window == key);
+            (contains(b"\xa6inputs") && contains(b"\xabgameVersion"))
+                .then_some((Format::GDR1, Confidence::High))
+        }
+        _ => None,
+    }
+}
+
+pub struct GdrReplay {
+    inner: Replay,
+    encoding: GdrEncoding,
+}
+
+/// Serializes a document the way GDR's reference implementation (nlohmann) does:
+/// keys sorted, integers and floats in their most compact representation.
+struct Canonical<'a>(&'a Value);
+
+impl Serialize for Canonical<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Value::Null => serializer.serialize_unit(),
+            Value::Bool(b) => serializer.serialize_bool(*b),
+            Value::Number(n) => {
+                if let Some(n) = n.as_u64() {
+                    serializer.serialize_u64(n)
+                } else if let Some(n) = n.as_i64() {
+                    serializer.serialize_i64(n)
+                } else {
+                    let n = n.as_f64().unwrap_or_default();
+                    #[allow(clippy::cast_possible_truncation)]
+                    let compact = n as f32;
+                    if n.is_finite() && f64::from(compact).eq(&n) {
+                        serializer.serialize_f32(compact)
+                    } else {
+                        serializer.serialize_f64(n)
+                    }
+                }
+            }
+            Value::String(s) => serializer.serialize_str(s),
+            Value::Array(values) => serializer.collect_seq(values.iter().map(Canonical)),
+            Value::Object(map) => {
+                serializer.collect_map(map.iter().map(|(key, value)| (key, Canonical(value))))
+            }
+        }
+    }
+}
+
+fn take<T: DeserializeOwned>(map: &mut Map<String, Value>, key: &str) -> Option<T> {
+    map.remove(key)
+        .and_then(|value| serde_json::from_value(value).ok())
+}
+
+/// Takes a metadata field, which is an error if present with the wrong type. `path` names the
+/// field in errors.
+fn take_field<T: DeserializeOwned>(
+    map: &mut Map<String, Value>,
+    key: &str,
+    path: &str,
+) -> Result<Option<T>, ReplayError> {
+    map.remove(key)
+        .map(|value| {
+            serde_json::from_value(value)
+                .map_err(|e| ReplayError::ReadError(format!("invalid gdr {path}: {e}")))
+        })
+        .transpose()
+}
+
+fn read_player_position(value: Value) -> Option<PlayerPosition> {
+    let Value::Object(mut map) = value else {
+        return None;
+    };
+
+    Some(PlayerPosition {
+        x: take(&mut map, "x").unwrap_or_default(),
+        y: take(&mut map, "y").unwrap_or_default(),
+        rotation: take(&mut map, "rotation"),
+        vel_x: take(&mut map, "xVel"),
+        vel_y: take(&mut map, "yVel"),
+    })
+}
+
+fn write_player_position(position: &PlayerPosition) -> Value {
+    let mut map = Map::new();
+    map.insert("x".to_string(), json!(position.x));
+    map.insert("y".to_string(), json!(position.y));
+    if let Some(rotation) = position.rotation {
+        map.insert("rotation".to_string(), json!(rotation));
+    }
+    if let Some(vel_x) = position.vel_x {
+        map.insert("xVel".to_string(), json!(vel_x));
+    }
+    if let Some(vel_y) = position.vel_y {
+        map.insert("yVel".to_string(), json!(vel_y));
+    }
+
+    Value::Object(map)
+}
+
+fn read_input(value: Value) -> Result<TimedAction, ReplayError> {
+    let Value::Object(mut input) = value else {
+        return Err(ReplayError::ReadError(
+            "gdr input is not an object".to_string(),
+        ));
+    };
+
+    let frame: u64 = take(&mut input, "frame")
+        .ok_or_else(|| ReplayError::ReadError("gdr input is missing its frame".to_string()))?;
+    let button: u8 = match input.remove("btn") {
+        Some(value) => serde_json::from_value(value).map_err(|e| {
+            ReplayError::ReadError(format!("invalid gdr button on frame {frame}: {e}"))
+        })?,
+        None => 1,
+    };
+    let hold = take(&mut input, "down").unwrap_or(false);
+    let player2 = take(&mut input, "2p").unwrap_or(false);
+
+    let button = match button {
+        0 => None,
+        1 => Some(PlayerButton::Jump),
+        2 => Some(PlayerButton::Left),
+        3 => Some(PlayerButton::Right),
+        _ => {
+            return Err(ReplayError::ReadError(format!(
+                "invalid gdr button {button} on frame {frame}"
+            )));
+        }
+    };
+
+    // Frame fix extension: `"frameFix": { "p1": { "x", "y", ... }, "p2": { ... } }`
+    let position = match input.remove("frameFix") {
+        Some(Value::Object(mut frame_fix)) => Some(Position {
+            player1: frame_fix.remove("p1").and_then(read_player_position),
+            player2: frame_fix.remove("p2").and_then(read_player_position),
+        }),
+        _ => None,
+    };
+
+    Ok(TimedAction {
+        time: TimePoint::Frame(frame),
+        action: button.map_or(Action::Empty, |button| Action::Player {
+            button,
+            hold,
+            player2,
+        }),
+        position,
+    })
+}
+
+fn write_input(timed: &TimedAction) -> Result<Value, ReplayError> {
+    let TimePoint::Frame(frame) = timed.time else {
+        return Err(ReplayError::WriteError(
+            "gdr only supports frame time points".to_string(),
+        ));
+    };
+
+    let (button, hold, player2) = match timed.action {
+        Action::Empty => (0, false, false),
+        Action::Player {
+            button,
+            hold,
+            player2,
+        } => {
+            let button = match button {
+                PlayerButton::Jump => 1,
+                PlayerButton::Left => 2,
+                PlayerButton::Right => 3,
+            };
+            (button, hold, player2)
+        }
+        Action::Restart { .. } | Action::TPS { .. } | Action::Bugpoint => {
+            return Err(ReplayError::WriteError(format!(
+                "gdr does not support {:?} actions",
+                timed.action
+            )));
+        }
+    };
+
+    let mut input = Map::new();
+    input.insert("frame".to_string(), json!(frame));
+    input.insert("btn".to_string(), json!(button));
+    input.insert("down".to_string(), json!(hold));
+    input.insert("2p".to_string(), json!(player2));
+
+    if let Some(position) = &timed.position {
+        let mut frame_fix = Map::new();
+        for (key, player) in [("p1", &position.player1), ("p2", &position.player2)] {
+            if let Some(player) = player {
+                frame_fix.insert(key.to_string(), write_player_position(player));
+            }
+        }
+        if !frame_fix.is_empty() {
+            input.insert("frameFix".to_string(), Value::Object(frame_fix));
+        }
+    }
+
+    Ok(Value::Object(input))
+}
+
+impl GdrReplay {
+    /// Set the encoding to write.
+    #[must_use]
+    pub fn with_encoding(mut self, encoding: GdrEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// The encoding this replay will be written as.
+    #[must_use]
+    pub fn encoding(&self) -> GdrEncoding {
+        self.encoding
+    }
+
+    /// Map a parsed GDR document into a generic replay.
+    fn from_document(document: Value, format: Format) -> Result<Replay, ReplayError> {
+        let Value::Object(mut document) = document else {
+            return Err(ReplayError::ReadError(
+                "gdr replay is not an object".to_string(),
+            ));
+        };
+
+        let actions = match document.remove("inputs") {
+            Some(Value::Array(inputs)) => inputs
+                .into_iter()
+                .map(read_input)
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => {
+                return Err(ReplayError::ReadError(
+                    "gdr replay is missing its inputs".to_string(),
+                ));
+            }
+        };
+
+        let mut bot: Map<String, Value> =
+            take_field(&mut document, "bot", "bot")?.unwrap_or_default();
+        let mut level: Map<String, Value> =
+            take_field(&mut document, "level", "level")?.unwrap_or_default();
+
+        let meta = GdrMeta {
+            author: take_field(&mut document, "author", "author")?.unwrap_or_default(),
+            description: take_field(&mut document, "description", "description")?
+                .unwrap_or_default(),
+            duration: take_field(&mut document, "duration", "duration")?.unwrap_or_default(),
+            game_version: take_field(&mut document, "gameVersion", "gameVersion")?
+                .unwrap_or_default(),
+            version: take_field(&mut document, "version", "version")?.unwrap_or(1.0),
+            tps: take_field(&mut document, "framerate", "framerate")?.unwrap_or(240.0),
+            seed: take_field(&mut document, "seed", "seed")?.unwrap_or_default(),
+            coins: take_field(&mut document, "coins", "coins")?.unwrap_or_default(),
+            ldm: take_field(&mut document, "ldm", "ldm")?.unwrap_or_default(),
+            bot_name: take_field(&mut bot, "name", "bot.name")?
+                .unwrap_or_else(|| DEFAULT_BOT_NAME.to_string()),
+            bot_version: take_field(&mut bot, "version", "bot.version")?.unwrap_or(1),
+            level_id: take_field(&mut level, "id", "level.id")?.unwrap_or_default(),
+            level_name: take_field(&mut level, "name", "level.name")?.unwrap_or_default(),
+            extensions: if document.is_empty() {
+                String::new()
+            } else {
+                Value::Object(document).to_string()
+            },
+        };
+
+        let game_version = meta
+            .game_version
+            .to_string()
+            .parse::<GameVersion>()
+            .ok()
+            .filter(|version| version.major >= 22)
+            .unwrap_or(GameVersion::new(22, 0));
+
+        Ok(Replay {
+            meta: Box::new(meta),
+            actions,
+            format,
+            game_version,
+            frame_counting: FrameCountingMethod::CurrentProgress,
+        })
+    }
+
+    /// Build a GDR document from a generic replay.
+    fn to_document(replay: &Replay) -> Result<Value, ReplayError> {
+        let meta = GdrMeta::from_fields(replay.meta.fields());
+
+        let inputs = replay
+            .actions
+            .iter()
+            .map(write_input)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let duration = if meta.duration > 0.0 {
+            meta.duration
+        } else {
+            let last_frame = replay
+                .actions
+                .iter()
+                .fold(0, |last, timed| match timed.time {
+                    TimePoint::Frame(frame) => last.max(frame),
+                    _ => last,
+                });
+            #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+            let duration = (last_frame as f64 / meta.tps) as f32;
+            duration
+        };
+
+        let game_version = if meta.game_version > 0.0 {
+            meta.game_version
+        } else {
+            replay.game_version.to_string().parse().unwrap_or_default()
+        };
+
+        let mut document = match serde_json::from_str(&meta.extensions) {
+            Ok(Value::Object(extensions)) => extensions,
+            _ => Map::new(),
+        };
+
+        document.extend([
+            ("author".to_string(), json!(meta.author)),
+            (
+                "bot".to_string(),
+                json!({ "name": meta.bot_name, "version": meta.bot_version }),
+            ),
+            ("coins".to_string(), json!(meta.coins)),
+            ("description".to_string(), json!(meta.description)),
+            ("duration".to_string(), json!(duration)),
+            ("framerate".to_string(), json!(meta.tps)),
+            ("gameVersion".to_string(), json!(game_version)),
+            ("inputs".to_string(), Value::Array(inputs)),
+            ("ldm".to_string(), json!(meta.ldm)),
+            (
+                "level".to_string(),
+                json!({ "id": meta.level_id, "name": meta.level_name }),
+            ),
+            ("seed".to_string(), json!(meta.seed)),
+            ("version".to_string(), json!(meta.version)),
+        ]);
+
+        Ok(Value::Object(document))
+    }
+
+    /// Write a replay in the given encoding.
+    fn write_encoding<W: Write>(
+        replay: &Replay,
+        encoding: GdrEncoding,
+        writer: &mut W,
+    ) -> Result<(), ReplayError> {
+        let document = Self::to_document(replay)?;
+
+        match encoding {
+            GdrEncoding::Binary => rmp_serde::encode::write(writer, &Canonical(&document))
+                .map_err(|e| ReplayError::WriteError(format!("failed to write gdr replay: {e}"))),
+            GdrEncoding::Json { pretty } => {
+                let result = if pretty {
+                    serde_json::to_writer_pretty(writer, &Canonical(&document))
+                } else {
+                    serde_json::to_writer(writer, &Canonical(&document))
+                };
+                result.map_err(|e| {
+                    ReplayError::WriteError(format!("failed to write gdr json replay: {e}"))
+                })
+            }
+        }
+    }
+}
+
+impl ReplaySerializable for GdrReplay {
+    fn new(replay: Replay) -> Self {
+        let encoding = GdrEncoding::from_format(replay.format).unwrap_or(GdrEncoding::Binary);
+        GdrReplay {
+            inner: replay,
+            encoding,
+        }
+    }
+
+    fn into_replay(self) -> Replay {
+        self.inner
+    }
+
+    fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, ReplayError>
+    where
+        Self: Sized,
+    {
+        // A msgpack map never starts with whitespace or `{`, so this is enough to tell them apart.
+        let header = binary::peek(reader, 64)?;
+        let is_json = header
+            .iter()
+            .find(|b| !b.is_ascii_whitespace())
+            .is_some_and(|b| *b == b'{');
+
+        let (document, encoding) = if is_json {
+            let mut text = Vec::new();
+            reader.read_to_end(&mut text)?;
+            let document: Value = serde_json::from_slice(&text).map_err(|e| {
+                ReplayError::ReadError(format!("failed to read gdr json replay: {e}"))
+            })?;
+
+            // Compact JSON has no line breaks outside of strings, which escape them.
+            let pretty = text.trim_ascii().contains(&b'\n');
+            (document, GdrEncoding::Json { pretty })
+        } else {
+            let document: Value = rmp_serde::from_read(reader)
+                .map_err(|e| ReplayError::ReadError(format!("failed to read gdr replay: {e}")))?;
+            (document, GdrEncoding::Binary)
+        };
+
+        Ok(GdrReplay {
+            inner: Self::from_document(document, encoding.format())?,
+            encoding,
+        })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+        Self::write_encoding(&self.inner, self.encoding, writer)
+    }
+
+    fn write_as<W: Write>(
+        replay: &Replay,
+        format: Format,
+        writer: &mut W,
+    ) -> Result<(), ReplayError>
+    where
+        Self: Sized,
+    {
+        Self::write_encoding(
+            replay,
+            GdrEncoding::from_format(format).unwrap_or(GdrEncoding::Binary),
+            writer,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn canonical_document() -> Value {
+        json!({
+            "author": "maxnut",
+            "bot": { "name": "someBot", "version": 3 },
+            "coins": 2,
+            "description": "test replay",
+            "duration": 1.5,
+            "framerate": 240.0,
+            "gameVersion": 2.206_f32,
+            "inputs": [
+                { "2p": false, "btn": 1, "down": true, "frame": 100 },
+                { "2p": true, "btn": 2, "down": false, "frame": 70000 },
+                {
+                    "2p": false, "btn": 1, "down": false, "frame": 70010,
+                    "frameFix": {
+                        "p1": { "rotation": 90.0, "x": 1024.5, "y": 105.0, "yVel": -3.25 },
+                        "p2": { "x": 1024.5, "y": 255.0 },
+                    },
+                },
+            ],
+            "ldm": true,
+            "level": { "id": 128, "name": "1st level" },
+            "seed": 1234,
+            "someOtherBot": { "practice": [1, 2, 3] },
+            "version": 1.0,
+        })
+    }
+
+    #[test]
+    fn test_gdr_reads_metadata() {
+        let bytes = rmp_serde::to_vec(&Canonical(&canonical_document())).unwrap();
+        let replay = GdrReplay::read(&mut Cursor::new(bytes))
+            .unwrap()
+            .into_replay();
+        let meta = GdrMeta::from_fields(replay.meta.fields());
+
+        assert_eq!(meta.author, "maxnut");
+        assert_eq!(meta.bot_name, "someBot");
+        assert_eq!(meta.level_id, 128);
+        assert!(meta.ldm);
+        assert!(meta.game_version.eq(&2.206));
+        assert_eq!(
+            (replay.game_version.major, replay.game_version.minor),
+            (22, 60)
+        );
+
+        assert_eq!(replay.actions.len(), 3);
+        assert_eq!(
+            replay.actions[1].action,
+            Action::Player {
+                button: PlayerButton::Left,
+                hold: false,
+                player2: true,
+            }
+        );
+
+        let position = replay.actions[2].position.unwrap();
+        assert!(position.player1.unwrap().vel_y.unwrap().eq(&-3.25));
+        assert_eq!(position.player2.unwrap().rotation, None);
+    }
+
+    #[test]
+    fn test_gdr_read_defaults_and_errors() {
+        let mut document = canonical_document();
+        document["bot"] = json!({ "name": "someBot" });
+        let replay = GdrReplay::from_document(document, Format::GDR1).unwrap();
+        assert_eq!(GdrMeta::from_fields(replay.meta.fields()).bot_version, 1);
+
+        let mut document = canonical_document();
+        document.as_object_mut().unwrap().remove("bot");
+        let replay = GdrReplay::from_document(document, Format::GDR1).unwrap();
+        assert_eq!(
+            GdrMeta::from_fields(replay.meta.fields()).bot_name,
+            DEFAULT_BOT_NAME
+        );
+
+        for (key, value) in [("seed", json!("1234")), ("bot", json!({ "version": "3" }))] {
+            let mut document = canonical_document();
+            document[key] = value;
+            assert!(matches!(
+                GdrReplay::from_document(document, Format::GDR1),
+                Err(ReplayError::ReadError(_))
+            ));
+        }
+
+        let mut document = canonical_document();
+        document["inputs"][0]["btn"] = json!("jump");
+        assert!(matches!(
+            GdrReplay::from_document(document, Format::GDR1),
+            Err(ReplayError::ReadError(_))
+        ));
+    }
+
+    #[test]
+    fn test_gdr_roundtrip_is_canonical() {
+        let bytes = rmp_serde::to_vec(&Canonical(&canonical_document())).unwrap();
+        let replay = GdrReplay::read(&mut Cursor::new(bytes.clone())).unwrap();
+
+        let mut written = Vec::new();
+        replay.write(&mut written).unwrap();
+
+        assert_eq!(written, bytes);
+
+        let input = write_input(&TimedAction {
+            time: TimePoint::Frame(10),
+            action: Action::Empty,
+            position: Some(Position {
+                player1: None,
+                player2: None,
+            }),
+        })
+        .unwrap();
+        assert!(input.get("frameFix").is_none());
+    }
+
+    #[test]
+    fn test_gdr_json_roundtrip() {
+        let bytes = rmp_serde::to_vec(&Canonical(&canonical_document())).unwrap();
+        let binary = GdrReplay::read(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(binary.encoding(), GdrEncoding::Binary);
+
+        let mut json = Vec::new();
+        binary
+            .with_encoding(GdrEncoding::Json { pretty: true })
+            .write(&mut json)
+            .unwrap();
+
+        let text = String::from_utf8(json.clone()).unwrap();
+        assert!(text.contains("\n  \"author\": \"maxnut\""));
+        assert!(text.contains("\"gameVersion\": 2.206,"));
+
+        let replay = GdrReplay::read(&mut Cursor::new(json)).unwrap();
+        assert_eq!(replay.encoding(), GdrEncoding::Json { pretty: true });
+
+        let replay = replay.into_replay();
+        assert_eq!(replay.format, Format::GDR1Json);
+
+        let compact = serde_json::to_vec(&canonical_document()).unwrap();
+        let compact_replay = GdrReplay::read(&mut Cursor::new(compact.clone())).unwrap();
+        assert_eq!(
+            compact_replay.encoding(),
+            GdrEncoding::Json { pretty: false }
+        );
+
+        let mut rewritten = Vec::new();
+        compact_replay.write(&mut rewritten).unwrap();
+        assert!(!rewritten.contains(&b'\n'));
+
+        let document: Value = serde_json::from_str(&text).unwrap();
+        let original = GdrReplay::from_document(canonical_document(), Format::GDR1).unwrap();
+        assert_eq!(replay.actions, original.actions);
+        assert_eq!(document["someOtherBot"], json!({ "practice": [1, 2, 3] }));
+    }
+}
@@ -0,0 +1,912 @@
+//! GDR2 replays.
+//!
+//! GDR2 is the binary successor of GDR1. All integers are unsigned LEB128 varints and all
+//! strings and byte buffers are varint length-prefixed:
+//!
+//! ```text
+//! "GDR" version(=2) input_tag author description duration:f32 game_version framerate:f64
+//! seed coins ldm:u8 platformer:u8 bot_name bot_version level_id level_name
+//! extension_count { name data }*
+//! death_count { frame_delta }*
+//! input_count { frame_delta << 4 | button << 2 | player2 << 1 | down, [input_data] }*
+//! ```
+//!
+//! Bots attach their own data through extensions: named replay-level blocks, and one
+//! per-input buffer whose meaning is given by `input_tag`. GDR2 defines no extensions itself,
+//! so siliconv stores what the layout above can't hold in its own, namespaced with
+//! `siliconv.`:
+//!
+//! - `siliconv.tps`: a block of mid-replay TPS changes.
+//! - `siliconv.seeds`: a block of death seeds.
+//! - `siliconv.frameFix`: an input tag for per-input positions. When another bot's input
+//!   extension takes the per-input slot, positions move to a block of the same name.
+//!
+//! Other bots won't read these. Anything else is kept verbatim in [`Gdr2Meta`] so it can be
+//! written back unchanged.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Write as _,
+    io::{Cursor, Read, Seek, Write},
+};
+
+use serde_json::{Value, json};
+use siliconv_core::{
+    action::{
+        Action, FrameCountingMethod, PlayerButton, PlayerPosition, Position, RestartType,
+        TimePoint, TimedAction,
+    },
+    error::ReplayError,
+    format::{Confidence, Format},
+    meta::Meta,
+    replay::{Replay, ReplaySerializable},
+    version::GameVersion,
+};
+use siliconv_macros::Meta;
+
+use crate::binary::{ReadBytes, WriteBytes};
+
+const MAGIC: &[u8; 3] = b"GDR";
+const VERSION: u64 = 2;
+
+/// Replay extension holding mid-replay TPS changes as `count { frame_delta tps:f64 }*`.
+const TPS_EXTENSION: &str = "siliconv.tps";
+/// Replay extension holding death seeds as `count { present:u8 [seed] }*`, in death order.
+const SEEDS_EXTENSION: &str = "siliconv.seeds";
+/// Input tag for per-input frame fix positions. Also the name of the replay extension holding
+/// them as `count { input_data }*`, in input order, when the input slot is taken.
+const FRAME_FIX_TAG: &str = "siliconv.frameFix";
+
+#[derive(Meta)]
+pub struct Gdr2Meta {
+    pub author: String,
+    pub description: String,
+    pub duration: f32,
+    pub game_version: f32,
+    #[meta(default = 240.0)]
+    pub tps: f64,
+    pub seed: u64,
+    pub coins: i32,
+    pub ldm: bool,
+    pub platformer: bool,
+    #[meta(default = "siliconv".to_string())]
+    pub bot_name: String,
+    #[meta(default = 1)]
+    pub bot_version: i32,
+    pub level_id: u32,
+    pub level_name: String,
+    /// Unknown replay extensions, as a JSON array of `{ "name", "data" }` with hex data.
+    pub extension_blocks: String,
+    /// Tag of an unknown per-input extension.
+    pub input_tag: String,
+    /// Per-input data of an unknown input extension, as a JSON array of
+    /// `{ "frame", "input", "data" }` with the input's frame, its state bits and hex data.
+    pub input_extensions: String,
+}
+
+/// Sniffs a GDR2 replay by its header.
+#[must_use]
+pub fn sniff(data: &[u8]) -> Option<(Format, Confidence)> {
+    (data.starts_with(MAGIC) && data.get(MAGIC.len()) == Some(&2))
+        .then_some((Format::GDR2, Confidence::High))
+}
+
+pub struct Gdr2Replay {
+    inner: Replay,
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, b| {
+        let _ = write!(hex, "{b:02x}");
+        hex
+    })
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn invalid(message: &str) -> ReplayError {
+    ReplayError::ReadError(format!("invalid gdr2 replay: {message}"))
+}
+
+/// Mask bit of a player whose position isn't recorded, with no data following it.
+const MISSING_PLAYER: u8 = 0b1000;
+
+fn read_player_position<R: Read>(reader: &mut R) -> Result<Option<PlayerPosition>, ReplayError> {
+    let mask = reader.read_u8()?;
+    if mask & MISSING_PLAYER != 0 {
+        return Ok(None);
+    }
+
+    let x = reader.read_f64()?;
+    let y = reader.read_f64()?;
+
+    let mut optional = |bit: u8| -> Result<Option<f64>, ReplayError> {
+        Ok(if mask & bit != 0 {
+            Some(reader.read_f64()?)
+        } else {
+            None
+        })
+    };
+
+    Ok(Some(PlayerPosition {
+        x,
+        y,
+        rotation: optional(0b001)?,
+        vel_x: optional(0b010)?,
+        vel_y: optional(0b100)?,
+    }))
+}
+
+fn write_player_position<W: Write>(
+    writer: &mut W,
+    position: Option<&PlayerPosition>,
+) -> Result<(), ReplayError> {
+    let Some(position) = position else {
+        writer.write_u8(MISSING_PLAYER)?;
+        return Ok(());
+    };
+
+    let optional = [position.rotation, position.vel_x, position.vel_y];
+    let mask = optional.iter().enumerate().fold(0u8, |mask, (i, value)| {
+        mask | (u8::from(value.is_some()) << i)
+    });
+
+    writer.write_u8(mask)?;
+    writer.write_f64(position.x)?;
+    writer.write_f64(position.y)?;
+    for value in optional.into_iter().flatten() {
+        writer.write_f64(value)?;
+    }
+
+    Ok(())
+}
+
+/// Converts a game version stored as digits (`2206`) to GDR's float form (`2.206`).
+fn game_version_from_digits(digits: u64) -> f32 {
+    let digits = digits.to_string();
+    let (whole, fraction) = digits.split_at(1);
+    format!("{whole}.{fraction}").parse().unwrap_or_default()
+}
+
+/// Converts a game version in GDR's float form (`2.206`) to digits (`2206`).
+fn game_version_to_digits(version: f32) -> u64 {
+    version
+        .to_string()
+        .replace('.', "")
+        .parse()
+        .unwrap_or_default()
+}
+
+/// An input grouped for writing, with its frame.
+type Input<'a> = (u64, &'a TimedAction);
+
+/// Stored per-input data of an unknown input extension, keyed by the frame and state bits of
+/// the input it was read with. Inputs sharing both take their data in order.
+type StoredExtensions = HashMap<(u64, u64), VecDeque<Vec<u8>>>;
+
+/// Bits of an input's state besides its frame delta.
+const INPUT_BITS: u64 = 0b1111;
+
+fn advance(frame: u64, delta: u64) -> Result<u64, ReplayError> {
+    frame
+        .checked_add(delta)
+        .ok_or_else(|| invalid("frame out of range"))
+}
+
+fn frame_delta(frame: u64, previous: &mut u64) -> u64 {
+    let delta = frame - *previous;
+    *previous = frame;
+    delta
+}
+
+fn read_meta<R: Read>(reader: &mut R) -> Result<(String, Gdr2Meta), ReplayError> {
+    let mut magic = [0u8; 3];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("missing GDR header"));
+    }
+
+    let version = reader.read_varint()?;
+    if version != VERSION {
+        return Err(invalid(&format!("unsupported version {version}")));
+    }
+
+    let input_tag = reader.read_string()?;
+    let meta = Gdr2Meta {
+        author: reader.read_string()?,
+        description: reader.read_string()?,
+        duration: reader.read_f32()?,
+        game_version: game_version_from_digits(reader.read_varint()?),
+        tps: reader.read_f64()?,
+        seed: reader.read_varint()?,
+        coins: i32::try_from(reader.read_varint()?).map_err(|_| invalid("coin count"))?,
+        ldm: reader.read_u8()? != 0,
+        platformer: reader.read_u8()? != 0,
+        bot_name: reader.read_string()?,
+        bot_version: i32::try_from(reader.read_varint()?).map_err(|_| invalid("bot version"))?,
+        level_id: u32::try_from(reader.read_varint()?).map_err(|_| invalid("level id"))?,
+        level_name: reader.read_string()?,
+        extension_blocks: String::new(),
+        input_tag: String::new(),
+        input_extensions: String::new(),
+    };
+
+    Ok((input_tag, meta))
+}
+
+fn write_meta<W: Write>(
+    writer: &mut W,
+    meta: &Gdr2Meta,
+    input_tag: &str,
+    game_version: u64,
+    platformer: bool,
+) -> Result<(), ReplayError> {
+    writer.write_all(MAGIC)?;
+    writer.write_varint(VERSION)?;
+    writer.write_string(input_tag)?;
+    writer.write_string(&meta.author)?;
+    writer.write_string(&meta.description)?;
+    writer.write_f32(meta.duration)?;
+    writer.write_varint(game_version)?;
+    writer.write_f64(meta.tps)?;
+    writer.write_varint(meta.seed)?;
+    writer.write_varint(u64::try_from(meta.coins).unwrap_or_default())?;
+    writer.write_u8(u8::from(meta.ldm))?;
+    writer.write_u8(u8::from(platformer))?;
+    writer.write_string(&meta.bot_name)?;
+    writer.write_varint(u64::try_from(meta.bot_version).unwrap_or_default())?;
+    writer.write_varint(u64::from(meta.level_id))?;
+    writer.write_string(&meta.level_name)?;
+
+    Ok(())
+}
+
+/// Known replay extensions that apply to deaths and inputs, read before those are.
+#[derive(Default)]
+struct DeferredExtensions {
+    seeds: Option<Vec<u8>>,
+    frame_fix: Option<Vec<u8>>,
+}
+
+/// Reads replay extensions, returning the unknown ones in their meta representation.
+fn read_extensions<R: Read>(
+    reader: &mut R,
+    actions: &mut Vec<TimedAction>,
+) -> Result<(String, DeferredExtensions), ReplayError> {
+    let mut extension_blocks = Vec::new();
+    let mut deferred = DeferredExtensions::default();
+
+    for _ in 0..reader.read_varint()? {
+        let name = reader.read_string()?;
+        let data = reader.read_bytes()?;
+
+        if name == SEEDS_EXTENSION {
+            deferred.seeds = Some(data);
+        } else if name == FRAME_FIX_TAG {
+            deferred.frame_fix = Some(data);
+        } else if name == TPS_EXTENSION {
+            let mut data = Cursor::new(data);
+            let mut frame = 0u64;
+            for _ in 0..data.read_varint()? {
+                frame = advance(frame, data.read_varint()?)?;
+                actions.push(TimedAction {
+                    time: TimePoint::Frame(frame),
+                    action: Action::TPS {
+                        tps: data.read_f64()?,
+                    },
+                    position: None,
+                });
+            }
+        } else {
+            extension_blocks.push(json!({ "name": name, "data": to_hex(&data) }));
+        }
+    }
+
+    let unknown = if extension_blocks.is_empty() {
+        String::new()
+    } else {
+        Value::Array(extension_blocks).to_string()
+    };
+    Ok((unknown, deferred))
+}
+
+/// Reads a `count { item }*` block, which must have one item per target.
+fn read_block<T>(
+    data: Vec<u8>,
+    name: &str,
+    targets: &mut [T],
+    mut apply: impl FnMut(&mut Cursor<Vec<u8>>, &mut T) -> Result<(), ReplayError>,
+) -> Result<(), ReplayError> {
+    let mut data = Cursor::new(data);
+    if data.read_varint()? != targets.len() as u64 {
+        return Err(invalid(&format!("{name} extension count mismatch")));
+    }
+
+    for target in targets {
+        apply(&mut data, target)?;
+    }
+    Ok(())
+}
+
+/// Reads the frame fix positions of one input, which are empty if it has none.
+fn read_frame_fix(data: Vec<u8>) -> Result<Option<Position>, ReplayError> {
+    if data.is_empty() {
+        return Ok(None);
+    }
+
+    let mut data = Cursor::new(data);
+    Ok(Some(Position {
+        player1: read_player_position(&mut data)?,
+        player2: read_player_position(&mut data)?,
+    }))
+}
+
+fn write_frame_fix(position: Option<&Position>) -> Result<Vec<u8>, ReplayError> {
+    let mut data = Vec::new();
+    if let Some(position) = position {
+        write_player_position(&mut data, position.player1.as_ref())?;
+        write_player_position(&mut data, position.player2.as_ref())?;
+    }
+    Ok(data)
+}
+
+/// Collects the stored unknown replay extensions plus the known ones generated from actions.
+///
+/// `frame_fix` holds the positions of every input when they can't use the input slot.
+fn extension_blocks(
+    meta: &Gdr2Meta,
+    tps_changes: &[(u64, f64)],
+    deaths: &[(u64, Option<u64>)],
+    frame_fix: Option<&[Input<'_>]>,
+) -> Result<Vec<(String, Vec<u8>)>, ReplayError> {
+    let mut blocks: Vec<(String, Vec<u8>)> =
+        serde_json::from_str::<Vec<Value>>(&meta.extension_blocks)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|block| {
+                let name = block["name"].as_str()?.to_string();
+                let data = from_hex(block["data"].as_str()?)?;
+                Some((name, data))
+            })
+            .filter(|(name, _)| ![TPS_EXTENSION, SEEDS_EXTENSION, FRAME_FIX_TAG].contains(&&**name))
+            .collect();
+
+    if !tps_changes.is_empty() {
+        let mut data = Vec::new();
+        data.write_varint(tps_changes.len() as u64)?;
+        let mut previous = 0;
+        for &(frame, tps) in tps_changes {
+            data.write_varint(frame_delta(frame, &mut previous))?;
+            data.write_f64(tps)?;
+        }
+        blocks.push((TPS_EXTENSION.to_string(), data));
+    }
+
+    if deaths.iter().any(|(_, seed)| seed.is_some()) {
+        let mut data = Vec::new();
+        data.write_varint(deaths.len() as u64)?;
+        for &(_, seed) in deaths {
+            data.write_u8(u8::from(seed.is_some()))?;
+            if let Some(seed) = seed {
+                data.write_varint(seed)?;
+            }
+        }
+        blocks.push((SEEDS_EXTENSION.to_string(), data));
+    }
+
+    if let Some(inputs) = frame_fix {
+        let mut data = Vec::new();
+        data.write_varint(inputs.len() as u64)?;
+        for (_, timed) in inputs {
+            data.write_bytes(&write_frame_fix(timed.position.as_ref())?)?;
+        }
+        blocks.push((FRAME_FIX_TAG.to_string(), data));
+    }
+
+    Ok(blocks)
+}
+
+/// Reads inputs, returning the per-input data of an unknown input extension.
+fn read_inputs<R: Read>(
+    reader: &mut R,
+    input_tag: &str,
+    actions: &mut Vec<TimedAction>,
+) -> Result<Vec<Value>, ReplayError> {
+    let mut frame = 0u64;
+    let mut input_extensions = Vec::new();
+
+    for _ in 0..reader.read_varint()? {
+        let state = reader.read_varint()?;
+        frame = advance(frame, state >> 4)?;
+
+        let action = match (state >> 2) & 0b11 {
+            0 => Action::Empty,
+            button => Action::Player {
+                button: match button {
+                    1 => PlayerButton::Jump,
+                    2 => PlayerButton::Left,
+                    _ => PlayerButton::Right,
+                },
+                hold: state & 0b1 != 0,
+                player2: state & 0b10 != 0,
+            },
+        };
+
+        let mut position = None;
+        if input_tag == FRAME_FIX_TAG {
+            position = read_frame_fix(reader.read_bytes()?)?;
+        } else if !input_tag.is_empty() {
+            input_extensions.push(json!({
+                "frame": frame,
+                "input": state & INPUT_BITS,
+                "data": to_hex(&reader.read_bytes()?),
+            }));
+        }
+
+        actions.push(TimedAction {
+            time: TimePoint::Frame(frame),
+            action,
+            position,
+        });
+    }
+
+    Ok(input_extensions)
+}
+
+/// Parses the stored unknown input extension, if there is one.
+fn stored_extensions(meta: &Gdr2Meta) -> Option<StoredExtensions> {
+    if meta.input_tag.is_empty() {
+        return None;
+    }
+
+    let mut stored = StoredExtensions::new();
+    for entry in serde_json::from_str::<Vec<Value>>(&meta.input_extensions).ok()? {
+        let key = (entry["frame"].as_u64()?, entry["input"].as_u64()?);
+        let data = from_hex(entry["data"].as_str()?)?;
+        stored.entry(key).or_default().push_back(data);
+    }
+    Some(stored)
+}
+
+/// Writes inputs, with either the stored unknown input extension or positions in the input
+/// slot. Inputs without stored data get an empty buffer.
+fn write_inputs<W: Write>(
+    writer: &mut W,
+    inputs: &[Input<'_>],
+    frame_fix: bool,
+    mut stored_extensions: Option<StoredExtensions>,
+) -> Result<(), ReplayError> {
+    writer.write_varint(inputs.len() as u64)?;
+
+    let mut previous = 0;
+    for &(frame, timed) in inputs {
+        let (button, hold, player2) = match timed.action {
+            Action::Player {
+                button,
+                hold,
+                player2,
+            } => {
+                let button = match button {
+                    PlayerButton::Jump => 1,
+                    PlayerButton::Left => 2,
+                    PlayerButton::Right => 3,
+                };
+                (button, hold, player2)
+            }
+            _ => (0, false, false),
+        };
+
+        let input = button << 2 | u64::from(player2) << 1 | u64::from(hold);
+        writer.write_varint(frame_delta(frame, &mut previous) << 4 | input)?;
+
+        if let Some(stored) = &mut stored_extensions {
+            let data = stored
+                .get_mut(&(frame, input))
+                .and_then(VecDeque::pop_front)
+                .unwrap_or_default();
+            writer.write_bytes(&data)?;
+        } else if frame_fix {
+            writer.write_bytes(&write_frame_fix(timed.position.as_ref())?)?;
+        }
+    }
+
+    Ok(())
+}
+
+impl ReplaySerializable for Gdr2Replay {
+    fn new(replay: Replay) -> Self {
+        Gdr2Replay { inner: replay }
+    }
+
+    fn into_replay(self) -> Replay {
+        self.inner
+    }
+
+    fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, ReplayError>
+    where
+        Self: Sized,
+    {
+        let (input_tag, mut meta) = read_meta(reader)?;
+
+        let mut actions = Vec::new();
+        let (extension_blocks, deferred) = read_extensions(reader, &mut actions)?;
+        meta.extension_blocks = extension_blocks;
+
+        let deaths_start = actions.len();
+        let mut frame = 0u64;
+        for _ in 0..reader.read_varint()? {
+            frame = advance(frame, reader.read_varint()?)?;
+            actions.push(TimedAction {
+                time: TimePoint::Frame(frame),
+                action: Action::Restart {
+                    restart_type: RestartType::Death,
+                    seed: None,
+                },
+                position: None,
+            });
+        }
+
+        if let Some(seeds) = deferred.seeds {
+            read_block(
+                seeds,
+                SEEDS_EXTENSION,
+                &mut actions[deaths_start..],
+                |data, timed| {
+                    if data.read_u8()? != 0
+                        && let Action::Restart { seed, .. } = &mut timed.action
+                    {
+                        *seed = Some(data.read_varint()?);
+                    }
+                    Ok(())
+                },
+            )?;
+        }
+
+        let inputs_start = actions.len();
+        let input_extensions = read_inputs(reader, &input_tag, &mut actions)?;
+        if let Some(frame_fix) = deferred.frame_fix {
+            read_block(
+                frame_fix,
+                FRAME_FIX_TAG,
+                &mut actions[inputs_start..],
+                |data, timed| {
+                    timed.position = read_frame_fix(data.read_bytes()?)?;
+                    Ok(())
+                },
+            )?;
+        }
+        if !input_tag.is_empty() && input_tag != FRAME_FIX_TAG {
+            meta.input_tag = input_tag;
+            meta.input_extensions = Value::Array(input_extensions).to_string();
+        }
+
+        // TPS changes and deaths were pushed first, so inputs stay after them on equal frames.
+        actions.sort_by_key(|timed| match timed.time {
+            TimePoint::Frame(frame) => frame,
+            _ => 0,
+        });
+
+        let game_version = meta
+            .game_version
+            .to_string()
+            .parse::<GameVersion>()
+            .unwrap_or(GameVersion::new(22, 0));
+
+        Ok(Gdr2Replay {
+            inner: Replay {
+                meta: Box::new(meta),
+                actions,
+                format: Format::GDR2,
+                game_version,
+                frame_counting: FrameCountingMethod::CurrentProgress,
+            },
+        })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+        Self::write_as(&self.inner, Format::GDR2, writer)
+    }
+
+    fn write_as<W: Write>(
+        replay: &Replay,
+        _format: Format,
+        writer: &mut W,
+    ) -> Result<(), ReplayError>
+    where
+        Self: Sized,
+    {
+        let meta = Gdr2Meta::from_fields(replay.meta.fields());
+
+        let mut inputs = Vec::new();
+        let mut deaths = Vec::new();
+        let mut tps_changes = Vec::new();
+
+        for timed in &replay.actions {
+            let TimePoint::Frame(frame) = timed.time else {
+                return Err(ReplayError::WriteError(
+                    "gdr2 only supports frame time points".to_string(),
+                ));
+            };
+
+            match timed.action {
+                Action::Empty | Action::Player { .. } => inputs.push((frame, timed)),
+                Action::Restart {
+                    restart_type: RestartType::Death,
+                    seed,
+                } => deaths.push((frame, seed)),
+                Action::TPS { tps } => tps_changes.push((frame, tps)),
+                Action::Restart { .. } | Action::Bugpoint => {
+                    return Err(ReplayError::WriteError(format!(
+                        "gdr2 does not support {:?} actions",
+                        timed.action
+                    )));
+                }
+            }
+        }
+
+        if !inputs.is_sorted_by_key(|(frame, _)| *frame)
+            || !deaths.is_sorted_by_key(|(frame, _)| *frame)
+            || !tps_changes.is_sorted_by_key(|(frame, _)| *frame)
+        {
+            return Err(ReplayError::WriteError(
+                "gdr2 actions must be sorted by frame".to_string(),
+            ));
+        }
+
+        // An unknown input extension keeps the input slot, and positions move out of it.
+        let has_positions = inputs.iter().any(|(_, timed)| timed.position.is_some());
+        let stored_extensions = stored_extensions(&meta);
+
+        let input_tag = if stored_extensions.is_some() {
+            meta.input_tag.as_str()
+        } else if has_positions {
+            FRAME_FIX_TAG
+        } else {
+            ""
+        };
+        let frame_fix_block = (has_positions && stored_extensions.is_some()).then_some(&*inputs);
+
+        let game_version = if meta.game_version > 0.0 {
+            game_version_to_digits(meta.game_version)
+        } else {
+            replay
+                .game_version
+                .to_string()
+                .replace('.', "")
+                .parse()
+                .unwrap_or_default()
+        };
+
+        let platformer = meta.platformer
+            || inputs.iter().any(|(_, timed)| {
+                matches!(
+                    timed.action,
+                    Action::Player {
+                        button: PlayerButton::Left | PlayerButton::Right,
+                        ..
+                    }
+                )
+            });
+
+        write_meta(writer, &meta, input_tag, game_version, platformer)?;
+
+        let extension_blocks = extension_blocks(&meta, &tps_changes, &deaths, frame_fix_block)?;
+        writer.write_varint(extension_blocks.len() as u64)?;
+        for (name, data) in &extension_blocks {
+            writer.write_string(name)?;
+            writer.write_bytes(data)?;
+        }
+
+        writer.write_varint(deaths.len() as u64)?;
+        let mut previous = 0;
+        for (frame, _) in deaths {
+            writer.write_varint(frame_delta(frame, &mut previous))?;
+        }
+
+        write_inputs(writer, &inputs, has_positions, stored_extensions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timed(frame: u64, action: Action, position: Option<Position>) -> TimedAction {
+        TimedAction {
+            time: TimePoint::Frame(frame),
+            action,
+            position,
+        }
+    }
+
+    fn roundtrip(bytes: Vec<u8>) -> (Replay, Vec<u8>) {
+        let replay = Gdr2Replay::read(&mut Cursor::new(bytes)).unwrap();
+        let mut written = Vec::new();
+        replay.write(&mut written).unwrap();
+        (replay.into_replay(), written)
+    }
+
+    #[test]
+    fn test_gdr2_known_extensions() {
+        let position = Position {
+            player1: Some(PlayerPosition {
+                x: 300.5,
+                y: 105.0,
+                rotation: Some(45.0),
+                vel_x: None,
+                vel_y: Some(-1.5),
+            }),
+            player2: Some(PlayerPosition {
+                x: 300.5,
+                y: 200.0,
+                rotation: None,
+                vel_x: None,
+                vel_y: None,
+            }),
+        };
+        let jump = |hold| Action::Player {
+            button: PlayerButton::Jump,
+            hold,
+            player2: false,
+        };
+        let actions = vec![
+            timed(5, Action::TPS { tps: 480.0 }, None),
+            timed(10, jump(true), Some(position)),
+            timed(
+                12,
+                Action::Restart {
+                    restart_type: RestartType::Death,
+                    seed: None,
+                },
+                None,
+            ),
+            timed(
+                15,
+                Action::Restart {
+                    restart_type: RestartType::Death,
+                    seed: Some(99),
+                },
+                None,
+            ),
+            timed(20, jump(false), None),
+            timed(
+                20,
+                Action::Player {
+                    button: PlayerButton::Right,
+                    hold: true,
+                    player2: true,
+                },
+                None,
+            ),
+        ];
+
+        let replay = Gdr2Replay::new(Replay {
+            meta: Box::new(Gdr2Meta::from_fields(std::collections::HashMap::new())),
+            actions: actions.clone(),
+            format: Format::GDR2,
+            game_version: GameVersion::new(22, 74),
+            frame_counting: FrameCountingMethod::CurrentProgress,
+        });
+
+        let mut bytes = Vec::new();
+        replay.write(&mut bytes).unwrap();
+
+        let (read, written) = roundtrip(bytes.clone());
+        let meta = Gdr2Meta::from_fields(read.meta.fields());
+
+        assert_eq!(read.actions, actions);
+        assert_eq!(written, bytes);
+        assert!(meta.platformer);
+        assert!(meta.game_version.eq(&2.2074));
+        assert_eq!(read.game_version.minor, 74);
+    }
+
+    fn header(input_tag: &str) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.write_varint(VERSION).unwrap();
+        bytes.write_string(input_tag).unwrap();
+        bytes.write_string("author").unwrap();
+        bytes.write_string("").unwrap();
+        bytes.write_f32(2.5).unwrap();
+        bytes.write_varint(2206).unwrap();
+        bytes.write_f64(240.0).unwrap();
+        bytes.write_varint(7).unwrap();
+        bytes.write_varint(0).unwrap();
+        bytes.write_u8(0).unwrap();
+        bytes.write_u8(0).unwrap();
+        bytes.write_string("otherBot").unwrap();
+        bytes.write_varint(2).unwrap();
+        bytes.write_varint(1234).unwrap();
+        bytes.write_string("level").unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_gdr2_keeps_unknown_extensions() {
+        let mut bytes = header("otherBot.inputs");
+        bytes.write_varint(1).unwrap();
+        bytes.write_string("otherBot.checkpoints").unwrap();
+        bytes.write_bytes(&[1, 2, 3, 0xff]).unwrap();
+
+        bytes.write_varint(0).unwrap();
+
+        bytes.write_varint(2).unwrap();
+        bytes.write_varint(100 << 4 | 1 << 2 | 1).unwrap();
+        bytes.write_bytes(&[0xaa]).unwrap();
+        bytes.write_varint(5 << 4 | 1 << 2).unwrap();
+        bytes.write_bytes(&[]).unwrap();
+
+        let (read, written) = roundtrip(bytes.clone());
+        let meta = Gdr2Meta::from_fields(read.meta.fields());
+
+        assert_eq!(meta.input_tag, "otherBot.inputs");
+        assert_eq!(read.actions.len(), 2);
+        assert_eq!(read.actions[1].time, TimePoint::Frame(105));
+        assert_eq!(written, bytes);
+
+        // Positions don't take the slot of the unknown input extension.
+        let mut positioned = read;
+        positioned.actions[1].position = Some(Position {
+            player1: None,
+            player2: Some(PlayerPosition {
+                x: 3.0,
+                y: 4.0,
+                rotation: Some(5.0),
+                vel_x: None,
+                vel_y: None,
+            }),
+        });
+        let mut bytes = Vec::new();
+        Gdr2Replay::write_as(&positioned, Format::GDR2, &mut bytes).unwrap();
+
+        let (read, written) = roundtrip(bytes.clone());
+        let meta = Gdr2Meta::from_fields(read.meta.fields());
+        assert_eq!(meta.input_tag, "otherBot.inputs");
+        assert!(meta.extension_blocks.contains("otherBot.checkpoints"));
+        assert_eq!(read.actions, positioned.actions);
+        assert_eq!(written, bytes);
+
+        // Stored data stays with its input when other inputs are added before it.
+        let mut extended = read;
+        extended.actions.insert(0, timed(50, Action::Empty, None));
+        let mut bytes = Vec::new();
+        Gdr2Replay::write_as(&extended, Format::GDR2, &mut bytes).unwrap();
+
+        let (read, _) = roundtrip(bytes);
+        let meta = Gdr2Meta::from_fields(read.meta.fields());
+        let data = serde_json::from_str::<Vec<Value>>(&meta.input_extensions)
+            .unwrap()
+            .iter()
+            .map(|entry| (entry["frame"].as_u64().unwrap(), entry["data"].clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            data,
+            vec![(50, json!("")), (100, json!("aa")), (105, json!(""))]
+        );
+    }
+
+    #[test]
+    fn test_gdr2_rejects_frame_overflow() {
+        let mut bytes = header("");
+        bytes.write_varint(0).unwrap();
+        bytes.write_varint(2).unwrap();
+        bytes.write_varint(u64::MAX).unwrap();
+        bytes.write_varint(1).unwrap();
+        bytes.write_varint(0).unwrap();
+
+        assert!(matches!(
+            Gdr2Replay::read(&mut Cursor::new(bytes)),
+            Err(ReplayError::ReadError(_))
+        ));
+    }
+}
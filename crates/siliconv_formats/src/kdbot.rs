@@ -0,0 +1,235 @@
+//! ``KDBot`` replays.
+//!
+//! ```text
+//! fps:f32 { frame:i32 hold:u8 player2:u8 }*
+//! ```
+
+use std::io::{Read, Seek, Write};
+
+use siliconv_core::{
+    action::{Action, FrameCountingMethod, PlayerButton, TimePoint, TimedAction},
+    error::ReplayError,
+    format::{Confidence, Format},
+    meta::Meta,
+    replay::{Replay, ReplaySerializable},
+    version::GameVersion,
+};
+use siliconv_macros::Meta;
+
+use crate::{
+    binary::{self, ReadBytes, WriteBytes},
+    detect,
+};
+
+#[derive(Meta)]
+pub struct KdBotMeta {
+    #[meta(default = 60.0)]
+    pub tps: f64,
+}
+
+/// Sniffs a .kd file. ``KDBot`` replays have no header, so only the layout can be checked.
+///
+/// ``OmegaBot`` 1 shares the layout with x positions instead of frames, so the locations
+/// decide between the two.
+#[must_use]
+pub fn sniff(data: &[u8]) -> Option<(Format, Confidence)> {
+    let fps = detect::f32_at(data, 0)?;
+    let records = &data[4..];
+
+    if !(detect::plausible_fps(f64::from(fps))
+        && records.len().is_multiple_of(6)
+        && records
+            .chunks_exact(6)
+            .all(|record| record[4] <= 1 && record[5] <= 1))
+    {
+        return None;
+    }
+
+    let confidence = match detect::locations_are_frames(records, 6) {
+        Some(true) => Confidence::Medium,
+        Some(false) => return None,
+        None => Confidence::Low,
+    };
+    Some((Format::KDBot, confidence))
+}
+
+pub struct KdBotReplay {
+    inner: Replay,
+}
+
+impl ReplaySerializable for KdBotReplay {
+    fn new(replay: Replay) -> Self {
+        KdBotReplay { inner: replay }
+    }
+
+    fn into_replay(self) -> Replay {
+        self.inner
+    }
+
+    fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, ReplayError>
+    where
+        Self: Sized,
+    {
+        let fps = reader.read_f32()?;
+
+        let remaining = binary::remaining(reader)?;
+        if remaining % 6 != 0 {
+            return Err(ReplayError::ReadError(
+                "kdbot replay has a truncated action".to_string(),
+            ));
+        }
+
+        let actions = (0..remaining / 6)
+            .map(|_| {
+                let frame = reader.read_i32()?;
+                let hold = reader.read_u8()? != 0;
+                let player2 = reader.read_u8()? != 0;
+
+                Ok(TimedAction {
+                    time: TimePoint::Frame(u64::try_from(frame).map_err(|_| {
+                        ReplayError::ReadError(format!("invalid kdbot frame {frame}"))
+                    })?),
+                    action: Action::Player {
+                        button: PlayerButton::Jump,
+                        hold,
+                        player2,
+                    },
+                    position: None,
+                })
+            })
+            .collect::<Result<_, ReplayError>>()?;
+
+        Ok(KdBotReplay {
+            inner: Replay {
+                meta: Box::new(KdBotMeta {
+                    tps: f64::from(fps),
+                }),
+                actions,
+                format: Format::KDBot,
+                game_version: GameVersion::new(21, 130),
+                frame_counting: FrameCountingMethod::LevelTime,
+            },
+        })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+        Self::write_as(&self.inner, Format::KDBot, writer)
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_as<W: Write>(
+        replay: &Replay,
+        _format: Format,
+        writer: &mut W,
+    ) -> Result<(), ReplayError>
+    where
+        Self: Sized,
+    {
+        let tps = KdBotMeta::from_fields(replay.meta.fields()).tps;
+        writer.write_f32(tps as f32)?;
+
+        for timed in &replay.actions {
+            let (hold, player2) = match timed.action {
+                Action::Empty => continue,
+                Action::Player {
+                    button: PlayerButton::Jump,
+                    hold,
+                    player2,
+                } => (hold, player2),
+                _ => {
+                    return Err(ReplayError::WriteError(format!(
+                        "kdbot does not support {:?} actions",
+                        timed.action
+                    )));
+                }
+            };
+
+            let TimePoint::Frame(frame) = timed.time else {
+                return Err(ReplayError::WriteError(
+                    "kdbot only supports frame time points".to_string(),
+                ));
+            };
+
+            writer.write_i32(i32::try_from(frame).map_err(|_| {
+                ReplayError::WriteError(format!("frame {frame} is too large for kdbot"))
+            })?)?;
+            writer.write_u8(u8::from(hold))?;
+            writer.write_u8(u8::from(player2))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_kdbot_roundtrip() {
+        let actions = vec![
+            TimedAction {
+                time: TimePoint::Frame(7),
+                action: Action::Player {
+                    button: PlayerButton::Jump,
+                    hold: true,
+                    player2: false,
+                },
+                position: None,
+            },
+            TimedAction {
+                time: TimePoint::Frame(9),
+                action: Action::Player {
+                    button: PlayerButton::Jump,
+                    hold: false,
+                    player2: true,
+                },
+                position: None,
+            },
+        ];
+
+        let replay = KdBotReplay::new(Replay {
+            meta: Box::new(KdBotMeta { tps: 360.0 }),
+            actions: actions.clone(),
+            format: Format::KDBot,
+            game_version: GameVersion::new(21, 130),
+            frame_counting: FrameCountingMethod::LevelTime,
+        });
+
+        let mut buf = Vec::new();
+        replay.write(&mut buf).unwrap();
+        assert_eq!(buf.len(), 4 + 2 * 6);
+
+        let read = KdBotReplay::read(&mut Cursor::new(buf))
+            .unwrap()
+            .into_replay();
+        assert!(KdBotMeta::from_fields(read.meta.fields()).tps.eq(&360.0));
+        assert_eq!(read.actions, actions);
+    }
+
+    #[test]
+    fn test_kdbot_rejects_x_positions() {
+        let replay = KdBotReplay::new(Replay {
+            meta: Box::new(KdBotMeta { tps: 60.0 }),
+            actions: vec![TimedAction {
+                time: TimePoint::XPos(1.0),
+                action: Action::Player {
+                    button: PlayerButton::Jump,
+                    hold: true,
+                    player2: false,
+                },
+                position: None,
+            }],
+            format: Format::KDBot,
+            game_version: GameVersion::new(21, 130),
+            frame_counting: FrameCountingMethod::LevelTime,
+        });
+
+        assert!(matches!(
+            replay.write(&mut Vec::new()),
+            Err(ReplayError::WriteError(_))
+        ));
+    }
+}
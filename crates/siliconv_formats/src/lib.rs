@@ -1,4 +1,25 @@
 #![allow(missing_docs)] // We don't need docs for each and every format, these aren't formats
 // users will edit, just export to.
 
+mod binary;
+
+pub mod detect;
+pub mod echo;
+pub mod gdr;
+pub mod gdr2;
+pub mod kdbot;
+pub mod mhr;
+pub mod omegabot;
+pub mod plaintext;
+pub mod registry;
+pub mod replaybot;
+pub mod replayengine;
+pub mod rush;
 pub mod silicate;
+pub mod tasbot;
+pub mod tcbot;
+pub mod uvbot;
+pub mod xbot;
+pub mod xdbot;
+pub mod ybot;
+pub mod zbot;
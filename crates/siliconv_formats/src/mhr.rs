@@ -0,0 +1,492 @@
+//! Mega Hack Replay replays.
+//!
+//! The JSON flavour is `{ "meta": { "fps" }, "events": [{ "frame", "down", "p2", "x", "y", "a", "r" }] }`,
+//! where `a` is the y velocity and `r` the rotation of the clicking player.
+//! The binary flavour stores the same events as fixed 32-byte little-endian records:
+//!
+//! ```text
+//! "HACKPRO\0" version:u32(=1) fps:u32 count:u32
+//! { frame:u32 down:u8 p2:u8 flags:u8 pad:u8 x:f32 y:f32 a:f64 r:f32 pad:u32 }*
+//! ```
+//!
+//! Bit 0 of `flags` marks events that carry position data. Bits 1 and 2 mark a missing `a` and
+//! `r`, which are then stored as 0.
+
+use std::io::{Read, Seek, Write};
+
+use serde_json::{Map, Value, json};
+use siliconv_core::{
+    action::{
+        Action, FrameCountingMethod, PlayerButton, PlayerPosition, Position, TimePoint, TimedAction,
+    },
+    error::ReplayError,
+    format::{Confidence, Format},
+    meta::Meta,
+    replay::{Replay, ReplaySerializable},
+    version::GameVersion,
+};
+use siliconv_macros::Meta;
+
+use crate::{
+    binary::{self, ReadBytes, WriteBytes},
+    detect,
+};
+
+const MAGIC: &[u8; 8] = b"HACKPRO\0";
+const VERSION: u32 = 1;
+
+/// Binary event flag for events with position data.
+const HAS_POSITION: u8 = 0b001;
+/// Binary event flag for a position without a y velocity.
+const NO_VEL_Y: u8 = 0b010;
+/// Binary event flag for a position without a rotation.
+const NO_ROTATION: u8 = 0b100;
+
+#[derive(Meta)]
+pub struct MhrMeta {
+    #[meta(default = 60.0)]
+    pub tps: f64,
+}
+
+/// The encoding of a Mega Hack replay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MhrEncoding {
+    /// Binary .mhr replay.
+    Binary,
+    /// JSON .mhr.json replay.
+    Json,
+}
+
+impl MhrEncoding {
+    /// The replay format corresponding to this encoding.
+    #[must_use]
+    pub const fn format(self) -> Format {
+        match self {
+            Self::Binary => Format::MHR,
+            Self::Json => Format::MHRJson,
+        }
+    }
+
+    /// The Mega Hack encoding of a replay format, if it is a Mega Hack format.
+    #[must_use]
+    pub const fn from_format(format: Format) -> Option<Self> {
+        match format {
+            Format::MHR => Some(Self::Binary),
+            Format::MHRJson => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// Sniffs a Mega Hack replay in either encoding.
+#[must_use]
+pub fn sniff(data: &[u8]) -> Option<(Format, Confidence)> {
+    if data.starts_with(MAGIC) {
+        Some((Format::MHR, Confidence::High))
+    } else if detect::json_contains_key(data, "events") && detect::json_contains_key(data, "meta") {
+        Some((Format::MHRJson, Confidence::Medium))
+    } else {
+        None
+    }
+}
+
+pub struct MhrReplay {
+    inner: Replay,
+    encoding: MhrEncoding,
+}
+
+/// A single Mega Hack click event.
+struct Event {
+    frame: u32,
+    down: bool,
+    player2: bool,
+    position: Option<PlayerPosition>,
+}
+
+impl Event {
+    fn into_action(self) -> TimedAction {
+        // Mega Hack only records the clicking player.
+        let position = self.position.map(|position| {
+            if self.player2 {
+                Position {
+                    player1: None,
+                    player2: Some(position),
+                }
+            } else {
+                Position {
+                    player1: Some(position),
+                    player2: None,
+                }
+            }
+        });
+
+        TimedAction {
+            time: TimePoint::Frame(u64::from(self.frame)),
+            action: Action::Player {
+                button: PlayerButton::Jump,
+                hold: self.down,
+                player2: self.player2,
+            },
+            position,
+        }
+    }
+
+    fn from_action(timed: &TimedAction) -> Result<Option<Self>, ReplayError> {
+        let (down, player2) = match timed.action {
+            Action::Empty => return Ok(None),
+            Action::Player {
+                button: PlayerButton::Jump,
+                hold,
+                player2,
+            } => (hold, player2),
+            _ => {
+                return Err(ReplayError::WriteError(format!(
+                    "mhr does not support {:?} actions",
+                    timed.action
+                )));
+            }
+        };
+
+        let TimePoint::Frame(frame) = timed.time else {
+            return Err(ReplayError::WriteError(
+                "mhr only supports frame time points".to_string(),
+            ));
+        };
+        let frame = u32::try_from(frame)
+            .map_err(|_| ReplayError::WriteError(format!("frame {frame} is too large for mhr")))?;
+
+        let position = timed.position.and_then(|position| {
+            if player2 {
+                position.player2
+            } else {
+                position.player1
+            }
+        });
+
+        Ok(Some(Event {
+            frame,
+            down,
+            player2,
+            position,
+        }))
+    }
+
+    fn read_binary<R: Read>(reader: &mut R) -> Result<Self, ReplayError> {
+        let frame = reader.read_u32()?;
+        let down = reader.read_u8()? != 0;
+        let player2 = reader.read_u8()? != 0;
+        let flags = reader.read_u8()?;
+        reader.read_u8()?;
+
+        let x = reader.read_f32()?;
+        let y = reader.read_f32()?;
+        let vel_y = reader.read_f64()?;
+        let rotation = reader.read_f32()?;
+        reader.read_u32()?;
+
+        Ok(Event {
+            frame,
+            down,
+            player2,
+            position: (flags & HAS_POSITION != 0).then_some(PlayerPosition {
+                x: f64::from(x),
+                y: f64::from(y),
+                rotation: (flags & NO_ROTATION == 0).then_some(f64::from(rotation)),
+                vel_x: None,
+                vel_y: (flags & NO_VEL_Y == 0).then_some(vel_y),
+            }),
+        })
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_binary<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+        writer.write_u32(self.frame)?;
+        writer.write_u8(u8::from(self.down))?;
+        writer.write_u8(u8::from(self.player2))?;
+
+        let flags = self.position.map_or(0, |position| {
+            HAS_POSITION
+                | if position.vel_y.is_none() {
+                    NO_VEL_Y
+                } else {
+                    0
+                }
+                | if position.rotation.is_none() {
+                    NO_ROTATION
+                } else {
+                    0
+                }
+        });
+        writer.write_u8(flags)?;
+        writer.write_u8(0)?;
+
+        let position = self.position.unwrap_or(PlayerPosition {
+            x: 0.0,
+            y: 0.0,
+            rotation: None,
+            vel_x: None,
+            vel_y: None,
+        });
+        writer.write_f32(position.x as f32)?;
+        writer.write_f32(position.y as f32)?;
+        writer.write_f64(position.vel_y.unwrap_or_default())?;
+        writer.write_f32(position.rotation.unwrap_or_default() as f32)?;
+        writer.write_u32(0)?;
+
+        Ok(())
+    }
+
+    fn read_json(value: &Value) -> Result<Self, ReplayError> {
+        let frame = value["frame"]
+            .as_u64()
+            .and_then(|frame| u32::try_from(frame).ok())
+            .ok_or_else(|| ReplayError::ReadError("mhr event is missing its frame".to_string()))?;
+
+        let position = value["x"].as_f64().map(|x| PlayerPosition {
+            x,
+            y: value["y"].as_f64().unwrap_or_default(),
+            rotation: value["r"].as_f64(),
+            vel_x: None,
+            vel_y: value["a"].as_f64(),
+        });
+
+        Ok(Event {
+            frame,
+            down: value["down"].as_bool().unwrap_or_default(),
+            player2: value["p2"].as_bool().unwrap_or_default(),
+            position,
+        })
+    }
+
+    fn write_json(&self) -> Value {
+        let mut event = Map::new();
+        event.insert("frame".to_string(), json!(self.frame));
+        event.insert("down".to_string(), json!(self.down));
+        if self.player2 {
+            event.insert("p2".to_string(), json!(true));
+        }
+
+        if let Some(position) = &self.position {
+            event.insert("x".to_string(), json!(position.x));
+            event.insert("y".to_string(), json!(position.y));
+            if let Some(vel_y) = position.vel_y {
+                event.insert("a".to_string(), json!(vel_y));
+            }
+            if let Some(rotation) = position.rotation {
+                event.insert("r".to_string(), json!(rotation));
+            }
+        }
+
+        Value::Object(event)
+    }
+}
+
+impl MhrReplay {
+    /// Set the encoding to write.
+    #[must_use]
+    pub fn with_encoding(mut self, encoding: MhrEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// The encoding this replay will be written as.
+    #[must_use]
+    pub fn encoding(&self) -> MhrEncoding {
+        self.encoding
+    }
+
+    fn read_binary<R: Read>(reader: &mut R) -> Result<(f64, Vec<Event>), ReplayError> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(ReplayError::ReadError("missing mhr header".to_string()));
+        }
+
+        let version = reader.read_u32()?;
+        if version != VERSION {
+            return Err(ReplayError::ReadError(format!(
+                "unsupported mhr version {version}"
+            )));
+        }
+
+        let fps = reader.read_u32()?;
+        let count = reader.read_u32()?;
+        let events = (0..count)
+            .map(|_| Event::read_binary(reader))
+            .collect::<Result<_, _>>()?;
+
+        Ok((f64::from(fps), events))
+    }
+
+    fn read_json<R: Read>(reader: &mut R) -> Result<(f64, Vec<Event>), ReplayError> {
+        let document: Value = serde_json::from_reader(reader)
+            .map_err(|e| ReplayError::ReadError(format!("failed to read mhr json replay: {e}")))?;
+
+        let fps = document["meta"]["fps"].as_f64().unwrap_or(60.0);
+        let events = document["events"]
+            .as_array()
+            .ok_or_else(|| ReplayError::ReadError("mhr replay is missing its events".to_string()))?
+            .iter()
+            .map(Event::read_json)
+            .collect::<Result<_, _>>()?;
+
+        Ok((fps, events))
+    }
+
+    /// Write a replay in the given encoding.
+    fn write_encoding<W: Write>(
+        replay: &Replay,
+        encoding: MhrEncoding,
+        writer: &mut W,
+    ) -> Result<(), ReplayError> {
+        let tps = MhrMeta::from_fields(replay.meta.fields()).tps;
+
+        let events = replay
+            .actions
+            .iter()
+            .filter_map(|timed| Event::from_action(timed).transpose())
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match encoding {
+            MhrEncoding::Binary => {
+                #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                let fps = tps.round() as u32;
+                let count = u32::try_from(events.len())
+                    .map_err(|_| ReplayError::WriteError("too many mhr events".to_string()))?;
+
+                writer.write_all(MAGIC)?;
+                writer.write_u32(VERSION)?;
+                writer.write_u32(fps)?;
+                writer.write_u32(count)?;
+                for event in &events {
+                    event.write_binary(writer)?;
+                }
+
+                Ok(())
+            }
+            MhrEncoding::Json => {
+                let document = json!({
+                    "meta": { "fps": tps },
+                    "events": events.iter().map(Event::write_json).collect::<Vec<_>>(),
+                });
+
+                serde_json::to_writer_pretty(writer, &document).map_err(|e| {
+                    ReplayError::WriteError(format!("failed to write mhr json replay: {e}"))
+                })
+            }
+        }
+    }
+}
+
+impl ReplaySerializable for MhrReplay {
+    fn new(replay: Replay) -> Self {
+        let encoding = MhrEncoding::from_format(replay.format).unwrap_or(MhrEncoding::Binary);
+        MhrReplay {
+            inner: replay,
+            encoding,
+        }
+    }
+
+    fn into_replay(self) -> Replay {
+        self.inner
+    }
+
+    fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, ReplayError>
+    where
+        Self: Sized,
+    {
+        let encoding = if binary::peek(reader, MAGIC.len())? == MAGIC {
+            MhrEncoding::Binary
+        } else {
+            MhrEncoding::Json
+        };
+
+        let (tps, events) = match encoding {
+            MhrEncoding::Binary => Self::read_binary(reader)?,
+            MhrEncoding::Json => Self::read_json(reader)?,
+        };
+
+        Ok(MhrReplay {
+            inner: Replay {
+                meta: Box::new(MhrMeta { tps }),
+                actions: events.into_iter().map(Event::into_action).collect(),
+                format: encoding.format(),
+                game_version: GameVersion::new(21, 130),
+                frame_counting: FrameCountingMethod::LevelTime,
+            },
+            encoding,
+        })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+        Self::write_encoding(&self.inner, self.encoding, writer)
+    }
+
+    fn write_as<W: Write>(
+        replay: &Replay,
+        format: Format,
+        writer: &mut W,
+    ) -> Result<(), ReplayError>
+    where
+        Self: Sized,
+    {
+        Self::write_encoding(
+            replay,
+            MhrEncoding::from_format(format).unwrap_or(MhrEncoding::Binary),
+            writer,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_mhr_positions_survive_both_encodings() {
+        let json = br#"{
+            "meta": { "fps": 240 },
+            "events": [
+                { "frame": 100, "down": true, "x": 150.5, "y": 105.0, "a": 11.25, "r": 90.0 },
+                { "frame": 110, "down": false, "p2": true, "x": 170.0, "y": 200.0, "a": -3.5, "r": 0.0 },
+                { "frame": 120, "down": true },
+                { "frame": 130, "down": false, "x": 1.0, "y": 2.0 }
+            ]
+        }"#;
+
+        let replay = MhrReplay::read(&mut Cursor::new(json.to_vec())).unwrap();
+        assert_eq!(replay.encoding(), MhrEncoding::Json);
+
+        let mut binary = Vec::new();
+        replay
+            .with_encoding(MhrEncoding::Binary)
+            .write(&mut binary)
+            .unwrap();
+        assert!(binary.starts_with(MAGIC));
+
+        let read = MhrReplay::read(&mut Cursor::new(binary))
+            .unwrap()
+            .into_replay();
+        assert_eq!(read.format, Format::MHR);
+        assert!(MhrMeta::from_fields(read.meta.fields()).tps.eq(&240.0));
+
+        let first = read.actions[0].position.unwrap();
+        assert_eq!(first.player2, None);
+        let first = first.player1.unwrap();
+        assert!(first.vel_y.unwrap().eq(&11.25));
+        assert!(first.rotation.unwrap().eq(&90.0));
+
+        let second = read.actions[1].position.unwrap();
+        assert_eq!(second.player1, None);
+        let second = second.player2.unwrap();
+        assert!(second.x.eq(&170.0));
+        assert!(second.vel_y.unwrap().eq(&-3.5));
+
+        assert_eq!(read.actions[2].position, None);
+
+        let fourth = read.actions[3].position.unwrap().player1.unwrap();
+        assert_eq!((fourth.rotation, fourth.vel_y), (None, None));
+    }
+}
@@ -0,0 +1,510 @@
+//! ``OmegaBot`` replays.
+//!
+//! All three versions share the .replay extension and store little-endian click lists:
+//!
+//! ```text
+//! v1: fps:f32 { x:f32 hold:u8 player2:u8 }*                             (x position)
+//! v2: type:u32 fps:f32 count:u32 { location:u32 click:u8 }*             (type 0: x position, 1: frame)
+//! v3: "OMEGA3\0\0" tps:f64 count:u32 { frame:u32 click:u8 }*            (frame)
+//! ```
+//!
+//! In v2, x positions are stored as the raw bits of an `f32`. Click types are
+//! 0 (player 1 down), 1 (player 1 up), 2 (player 2 down) and 3 (player 2 up).
+
+use std::io::{Read, Seek, Write};
+
+use siliconv_core::{
+    action::{Action, FrameCountingMethod, PlayerButton, TimePoint, TimedAction},
+    error::ReplayError,
+    format::{Confidence, Format},
+    meta::Meta,
+    replay::{Replay, ReplaySerializable},
+    version::GameVersion,
+};
+use siliconv_macros::Meta;
+
+use crate::{
+    binary::{self, ReadBytes, WriteBytes},
+    detect,
+};
+
+const V3_MAGIC: &[u8; 8] = b"OMEGA3\0\0";
+
+#[derive(Meta)]
+pub struct OmegaBotMeta {
+    #[meta(default = 60.0)]
+    pub tps: f64,
+}
+
+/// An ``OmegaBot`` replay format version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OmegaBotVersion {
+    /// ``OmegaBot`` 1, x position only.
+    V1,
+    /// ``OmegaBot`` 2, x position or frame.
+    V2,
+    /// ``OmegaBot`` 3, frame only.
+    V3,
+}
+
+impl OmegaBotVersion {
+    /// The replay format corresponding to this version.
+    #[must_use]
+    pub const fn format(self) -> Format {
+        match self {
+            Self::V1 => Format::OmegaBot,
+            Self::V2 => Format::OmegaBot2,
+            Self::V3 => Format::OmegaBot3,
+        }
+    }
+
+    /// The ``OmegaBot`` version of a replay format, if it is an ``OmegaBot`` format.
+    #[must_use]
+    pub const fn from_format(format: Format) -> Option<Self> {
+        match format {
+            Format::OmegaBot => Some(Self::V1),
+            Format::OmegaBot2 => Some(Self::V2),
+            Format::OmegaBot3 => Some(Self::V3),
+            _ => None,
+        }
+    }
+
+    /// Picks the version from the first 12 bytes and the length of a file.
+    ///
+    /// v2 starts with its replay type (0 or 1) and has a click count matching its length,
+    /// while v1 starts with its fps as an `f32`. An fps of 0 has the bits of type 0, so the
+    /// type alone isn't enough.
+    #[must_use]
+    pub fn detect(header: &[u8], len: u64) -> Self {
+        if header.starts_with(V3_MAGIC) {
+            return Self::V3;
+        }
+
+        let v2 = matches!(detect::u32_at(header, 0), Some(0 | 1))
+            && detect::u32_at(header, 8).is_some_and(|count| u64::from(count) * 5 + 12 == len);
+        if v2 { Self::V2 } else { Self::V1 }
+    }
+}
+
+/// Sniffs an ``OmegaBot`` replay. Only v3 has a header, older versions are checked by layout.
+#[must_use]
+pub fn sniff(data: &[u8]) -> Option<(Format, Confidence)> {
+    let version = OmegaBotVersion::detect(data, data.len() as u64);
+    let confidence = match version {
+        OmegaBotVersion::V1 => {
+            let fps = detect::f32_at(data, 0)?;
+            let records = &data[4..];
+            if !(detect::plausible_fps(f64::from(fps))
+                && records.len().is_multiple_of(6)
+                && records
+                    .chunks_exact(6)
+                    .all(|record| record[4] <= 1 && record[5] <= 1))
+            {
+                return None;
+            }
+
+            // KDBot shares the layout with frames instead of x positions.
+            match detect::locations_are_frames(records, 6) {
+                Some(false) => Confidence::Medium,
+                Some(true) | None => Confidence::Low,
+            }
+        }
+        OmegaBotVersion::V2 => Confidence::Medium,
+        OmegaBotVersion::V3 => Confidence::High,
+    };
+
+    Some((version.format(), confidence))
+}
+
+pub struct OmegaBotReplay {
+    inner: Replay,
+    version: OmegaBotVersion,
+}
+
+fn click_action(click: u8) -> Result<Action, ReplayError> {
+    if click > 3 {
+        return Err(ReplayError::ReadError(format!(
+            "invalid omegabot click type {click}"
+        )));
+    }
+
+    Ok(Action::Player {
+        button: PlayerButton::Jump,
+        hold: click & 1 == 0,
+        player2: click & 2 != 0,
+    })
+}
+
+impl OmegaBotReplay {
+    /// Set the ``OmegaBot`` version to write.
+    #[must_use]
+    pub fn with_version(mut self, version: OmegaBotVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// The ``OmegaBot`` version this replay will be written as.
+    #[must_use]
+    pub fn version(&self) -> OmegaBotVersion {
+        self.version
+    }
+
+    /// Collect `(time, click type)` pairs of all player actions.
+    fn clicks(replay: &Replay) -> Result<Vec<(TimePoint, u8)>, ReplayError> {
+        replay
+            .actions
+            .iter()
+            .filter(|timed| !matches!(timed.action, Action::Empty))
+            .map(|timed| match timed.action {
+                Action::Player {
+                    button: PlayerButton::Jump,
+                    hold,
+                    player2,
+                } => Ok((timed.time, u8::from(!hold) | u8::from(player2) << 1)),
+                _ => Err(ReplayError::WriteError(format!(
+                    "omegabot does not support {:?} actions",
+                    timed.action
+                ))),
+            })
+            .collect()
+    }
+
+    fn read_v1<R: Read + Seek>(reader: &mut R) -> Result<(f64, Vec<TimedAction>), ReplayError> {
+        let fps = reader.read_f32()?;
+
+        let remaining = binary::remaining(reader)?;
+        if remaining % 6 != 0 {
+            return Err(ReplayError::ReadError(
+                "omegabot replay has a truncated click".to_string(),
+            ));
+        }
+
+        let actions = (0..remaining / 6)
+            .map(|_| {
+                let x = reader.read_f32()?;
+                let hold = reader.read_u8()? != 0;
+                let player2 = reader.read_u8()? != 0;
+
+                Ok(TimedAction {
+                    time: TimePoint::XPos(f64::from(x)),
+                    action: Action::Player {
+                        button: PlayerButton::Jump,
+                        hold,
+                        player2,
+                    },
+                    position: None,
+                })
+            })
+            .collect::<Result<_, ReplayError>>()?;
+
+        Ok((f64::from(fps), actions))
+    }
+
+    fn read_v2<R: Read>(reader: &mut R) -> Result<(f64, Vec<TimedAction>), ReplayError> {
+        let xpos = reader.read_u32()? == 0;
+        let fps = reader.read_f32()?;
+        let count = reader.read_u32()?;
+
+        let actions = (0..count)
+            .map(|_| {
+                let location = reader.read_u32()?;
+                let action = click_action(reader.read_u8()?)?;
+
+                Ok(TimedAction {
+                    time: if xpos {
+                        TimePoint::XPos(f64::from(f32::from_bits(location)))
+                    } else {
+                        TimePoint::Frame(u64::from(location))
+                    },
+                    action,
+                    position: None,
+                })
+            })
+            .collect::<Result<_, ReplayError>>()?;
+
+        Ok((f64::from(fps), actions))
+    }
+
+    fn read_v3<R: Read>(reader: &mut R) -> Result<(f64, Vec<TimedAction>), ReplayError> {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != V3_MAGIC {
+            return Err(ReplayError::ReadError(
+                "missing omegabot 3 header".to_string(),
+            ));
+        }
+
+        let tps = reader.read_f64()?;
+        let count = reader.read_u32()?;
+
+        let actions = (0..count)
+            .map(|_| {
+                let frame = reader.read_u32()?;
+                let action = click_action(reader.read_u8()?)?;
+
+                Ok(TimedAction {
+                    time: TimePoint::Frame(u64::from(frame)),
+                    action,
+                    position: None,
+                })
+            })
+            .collect::<Result<_, ReplayError>>()?;
+
+        Ok((tps, actions))
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_v1<W: Write>(replay: &Replay, writer: &mut W, tps: f64) -> Result<(), ReplayError> {
+        writer.write_f32(tps as f32)?;
+
+        for (time, click) in Self::clicks(replay)? {
+            let TimePoint::XPos(x) = time else {
+                return Err(ReplayError::WriteError(
+                    "omegabot 1 only supports x position time points".to_string(),
+                ));
+            };
+
+            writer.write_f32(x as f32)?;
+            writer.write_u8(u8::from(click & 1 == 0))?;
+            writer.write_u8(click >> 1)?;
+        }
+
+        Ok(())
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_v2<W: Write>(replay: &Replay, writer: &mut W, tps: f64) -> Result<(), ReplayError> {
+        let clicks = Self::clicks(replay)?;
+        let xpos = clicks
+            .first()
+            .is_some_and(|(time, _)| matches!(time, TimePoint::XPos(_)));
+
+        let locations = clicks
+            .iter()
+            .map(|(time, click)| match *time {
+                TimePoint::XPos(x) if xpos => Ok(((x as f32).to_bits(), *click)),
+                TimePoint::Frame(frame) if !xpos => u32::try_from(frame)
+                    .map(|frame| (frame, *click))
+                    .map_err(|_| {
+                        ReplayError::WriteError(format!("frame {frame} is too large for omegabot"))
+                    }),
+                _ => Err(ReplayError::WriteError(
+                    "omegabot 2 replays must use only x position or only frame time points"
+                        .to_string(),
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        writer.write_u32(u32::from(!xpos))?;
+        writer.write_f32(tps as f32)?;
+        writer.write_u32(
+            u32::try_from(locations.len())
+                .map_err(|_| ReplayError::WriteError("too many clicks".to_string()))?,
+        )?;
+        for (location, click) in locations {
+            writer.write_u32(location)?;
+            writer.write_u8(click)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_v3<W: Write>(replay: &Replay, writer: &mut W, tps: f64) -> Result<(), ReplayError> {
+        let clicks = Self::clicks(replay)?
+            .into_iter()
+            .map(|(time, click)| match time {
+                TimePoint::Frame(frame) => u32::try_from(frame)
+                    .map(|frame| (frame, click))
+                    .map_err(|_| {
+                        ReplayError::WriteError(format!("frame {frame} is too large for omegabot"))
+                    }),
+                _ => Err(ReplayError::WriteError(
+                    "omegabot 3 only supports frame time points".to_string(),
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        writer.write_all(V3_MAGIC)?;
+        writer.write_f64(tps)?;
+        writer.write_u32(
+            u32::try_from(clicks.len())
+                .map_err(|_| ReplayError::WriteError("too many clicks".to_string()))?,
+        )?;
+        for (frame, click) in clicks {
+            writer.write_u32(frame)?;
+            writer.write_u8(click)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write a replay as the given version.
+    fn write_version<W: Write>(
+        replay: &Replay,
+        version: OmegaBotVersion,
+        writer: &mut W,
+    ) -> Result<(), ReplayError> {
+        let tps = OmegaBotMeta::from_fields(replay.meta.fields()).tps;
+
+        match version {
+            OmegaBotVersion::V1 => Self::write_v1(replay, writer, tps),
+            OmegaBotVersion::V2 => Self::write_v2(replay, writer, tps),
+            OmegaBotVersion::V3 => Self::write_v3(replay, writer, tps),
+        }
+    }
+}
+
+impl ReplaySerializable for OmegaBotReplay {
+    fn new(replay: Replay) -> Self {
+        let version = OmegaBotVersion::from_format(replay.format).unwrap_or(OmegaBotVersion::V2);
+        OmegaBotReplay {
+            inner: replay,
+            version,
+        }
+    }
+
+    fn into_replay(self) -> Replay {
+        self.inner
+    }
+
+    fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, ReplayError>
+    where
+        Self: Sized,
+    {
+        let len = binary::remaining(reader)?;
+        let version = OmegaBotVersion::detect(&binary::peek(reader, 12)?, len);
+        let (tps, actions) = match version {
+            OmegaBotVersion::V1 => Self::read_v1(reader)?,
+            OmegaBotVersion::V2 => Self::read_v2(reader)?,
+            OmegaBotVersion::V3 => Self::read_v3(reader)?,
+        };
+
+        Ok(OmegaBotReplay {
+            inner: Replay {
+                meta: Box::new(OmegaBotMeta { tps }),
+                actions,
+                format: version.format(),
+                game_version: GameVersion::new(21, 130),
+                frame_counting: FrameCountingMethod::LevelTime,
+            },
+            version,
+        })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+        Self::write_version(&self.inner, self.version, writer)
+    }
+
+    fn write_as<W: Write>(
+        replay: &Replay,
+        format: Format,
+        writer: &mut W,
+    ) -> Result<(), ReplayError>
+    where
+        Self: Sized,
+    {
+        Self::write_version(
+            replay,
+            OmegaBotVersion::from_format(format).unwrap_or(OmegaBotVersion::V2),
+            writer,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn click(time: TimePoint, hold: bool, player2: bool) -> TimedAction {
+        TimedAction {
+            time,
+            action: Action::Player {
+                button: PlayerButton::Jump,
+                hold,
+                player2,
+            },
+            position: None,
+        }
+    }
+
+    fn roundtrip(version: OmegaBotVersion, actions: &[TimedAction]) -> Replay {
+        let replay = OmegaBotReplay::new(Replay {
+            meta: Box::new(OmegaBotMeta { tps: 144.0 }),
+            actions: actions.to_vec(),
+            format: version.format(),
+            game_version: GameVersion::new(21, 130),
+            frame_counting: FrameCountingMethod::LevelTime,
+        });
+
+        let mut buf = Vec::new();
+        replay.write(&mut buf).unwrap();
+        assert_eq!(OmegaBotVersion::detect(&buf, buf.len() as u64), version);
+
+        let read = OmegaBotReplay::read(&mut Cursor::new(buf)).unwrap();
+        assert_eq!(read.version(), version);
+
+        let read = read.into_replay();
+        assert!(OmegaBotMeta::from_fields(read.meta.fields()).tps.eq(&144.0));
+        read
+    }
+
+    #[test]
+    fn test_omegabot_versions_roundtrip() {
+        let xpos = [
+            click(TimePoint::XPos(120.25), true, false),
+            click(TimePoint::XPos(180.5), false, false),
+            click(TimePoint::XPos(180.5), true, true),
+        ];
+        let frames = [
+            click(TimePoint::Frame(60), true, false),
+            click(TimePoint::Frame(75), false, true),
+        ];
+
+        assert_eq!(roundtrip(OmegaBotVersion::V1, &xpos).actions, xpos);
+        assert_eq!(roundtrip(OmegaBotVersion::V2, &xpos).actions, xpos);
+        assert_eq!(roundtrip(OmegaBotVersion::V2, &frames).actions, frames);
+        assert_eq!(roundtrip(OmegaBotVersion::V3, &frames).actions, frames);
+    }
+
+    #[test]
+    fn test_omegabot_rejects_mixed_time_points() {
+        let replay = OmegaBotReplay::new(Replay {
+            meta: Box::new(OmegaBotMeta { tps: 60.0 }),
+            actions: vec![
+                click(TimePoint::XPos(1.0), true, false),
+                click(TimePoint::Frame(2), false, false),
+            ],
+            format: Format::OmegaBot2,
+            game_version: GameVersion::new(21, 130),
+            frame_counting: FrameCountingMethod::LevelTime,
+        });
+
+        assert!(matches!(
+            replay.write(&mut Vec::new()),
+            Err(ReplayError::WriteError(_))
+        ));
+    }
+
+    #[test]
+    fn test_omegabot_zero_fps_is_v1() {
+        let replay = OmegaBotReplay::new(Replay {
+            meta: Box::new(OmegaBotMeta { tps: 0.0 }),
+            actions: vec![
+                click(TimePoint::XPos(120.25), true, false),
+                click(TimePoint::XPos(180.5), false, false),
+            ],
+            format: Format::OmegaBot,
+            game_version: GameVersion::new(21, 130),
+            frame_counting: FrameCountingMethod::LevelTime,
+        });
+
+        let mut buf = Vec::new();
+        replay.write(&mut buf).unwrap();
+        assert_eq!(
+            OmegaBotVersion::detect(&buf, buf.len() as u64),
+            OmegaBotVersion::V1
+        );
+    }
+}
@@ -0,0 +1,485 @@
+//! The plain text intermediate format.
+//!
+//! A line-based format meant to be reviewed in diffs and edited by hand. It can express every
+//! action, time point, position and metadata field, and round-trips exactly:
+//!
+//! ```text
+//! # Lines starting with '#' are comments, blank lines are ignored.
+//! version 1
+//! game 2.206
+//! frames ticks
+//! meta tps "240"
+//! meta author "someone"
+//!
+//! f10 press jump p1
+//! f12 release jump p1 | 30.5 105 90 - -1.5 | 0 0 - - -
+//! x120.5 press left p2
+//! t1.25 tps 480
+//! f300 restart death seed 1234
+//! f301 restart full
+//! f302 bugpoint
+//! f303 empty | 40 105 - - - | 40 225 - - -
+//! f304 press jump p2 | - | 40 225 90 - -
+//! ```
+//!
+//! Time points are `f<frame>`, `x<x position>` or `t<seconds>`. Positions are optional and list
+//! `x y rotation x_velocity y_velocity` for each player, where `-` marks a missing value. A
+//! player whose position isn't recorded is a single `-`.
+//! Metadata values are JSON strings, so they can hold any text. The optional `frames` line names
+//! the frame counting method: `progress`, `level-time` or `ticks`.
+
+use std::io::{BufRead, BufReader, Read, Seek, Write};
+
+use siliconv_core::{
+    action::{
+        Action, FrameCountingMethod, PlayerButton, PlayerPosition, Position, RestartType,
+        TimePoint, TimedAction,
+    },
+    error::ReplayError,
+    format::{Confidence, Format},
+    meta::MetaMap,
+    replay::{Replay, ReplaySerializable},
+    version::GameVersion,
+};
+
+const VERSION: u32 = 1;
+
+/// Sniffs a plain text replay by its version line.
+#[must_use]
+pub fn sniff(data: &[u8]) -> Option<(Format, Confidence)> {
+    let line = data
+        .split(|b| *b == b'\n')
+        .map(<[u8]>::trim_ascii)
+        .find(|line| !line.is_empty() && !line.starts_with(b"#"))?;
+
+    (line == format!("version {VERSION}").as_bytes())
+        .then_some((Format::PlainText, Confidence::High))
+}
+
+pub struct PlainTextReplay {
+    inner: Replay,
+}
+
+fn parse_time(token: &str) -> Option<TimePoint> {
+    let (kind, value) = token.split_at_checked(1)?;
+    match kind {
+        "f" => value.parse().ok().map(TimePoint::Frame),
+        "x" => value.parse().ok().map(TimePoint::XPos),
+        "t" => value.parse().ok().map(TimePoint::Time),
+        _ => None,
+    }
+}
+
+fn parse_action(tokens: &[&str]) -> Option<Action> {
+    Some(match *tokens {
+        ["empty"] => Action::Empty,
+        [edge @ ("press" | "release"), button, player] => Action::Player {
+            button: match button {
+                "jump" => PlayerButton::Jump,
+                "left" => PlayerButton::Left,
+                "right" => PlayerButton::Right,
+                _ => return None,
+            },
+            hold: edge == "press",
+            player2: match player {
+                "p1" => false,
+                "p2" => true,
+                _ => return None,
+            },
+        },
+        ["restart", restart_type, ref seed @ ..] => Action::Restart {
+            restart_type: match restart_type {
+                "restart" => RestartType::Restart,
+                "full" => RestartType::RestartFull,
+                "death" => RestartType::Death,
+                _ => return None,
+            },
+            seed: match *seed {
+                [] => None,
+                ["seed", seed] => Some(seed.parse().ok()?),
+                _ => return None,
+            },
+        },
+        ["tps", tps] => Action::TPS {
+            tps: tps.parse().ok()?,
+        },
+        ["bugpoint"] => Action::Bugpoint,
+        _ => return None,
+    })
+}
+
+fn parse_player(segment: &str) -> Option<PlayerPosition> {
+    let optional = |token: &str| match token {
+        "-" => Some(None),
+        _ => token.parse().ok().map(Some),
+    };
+
+    match *segment.split_whitespace().collect::<Vec<_>>() {
+        [x, y, rotation, vel_x, vel_y] => Some(PlayerPosition {
+            x: x.parse().ok()?,
+            y: y.parse().ok()?,
+            rotation: optional(rotation)?,
+            vel_x: optional(vel_x)?,
+            vel_y: optional(vel_y)?,
+        }),
+        _ => None,
+    }
+}
+
+fn parse_timed_action(line: &str) -> Option<TimedAction> {
+    let mut segments = line.split('|');
+    let tokens = segments.next()?.split_whitespace().collect::<Vec<_>>();
+    let (time, action) = tokens.split_first()?;
+
+    let player = |segment: &str| match segment.trim() {
+        "-" => Some(None),
+        segment => parse_player(segment).map(Some),
+    };
+
+    let position = match segments.collect::<Vec<_>>()[..] {
+        [] => None,
+        [player1, player2] => Some(Position {
+            player1: player(player1)?,
+            player2: player(player2)?,
+        }),
+        _ => return None,
+    };
+
+    Some(TimedAction {
+        time: parse_time(time)?,
+        action: parse_action(action)?,
+        position,
+    })
+}
+
+fn write_time<W: Write>(writer: &mut W, time: TimePoint) -> std::io::Result<()> {
+    match time {
+        TimePoint::Frame(frame) => write!(writer, "f{frame}"),
+        TimePoint::XPos(x) => write!(writer, "x{x}"),
+        TimePoint::Time(time) => write!(writer, "t{time}"),
+    }
+}
+
+fn write_action<W: Write>(writer: &mut W, action: Action) -> std::io::Result<()> {
+    match action {
+        Action::Empty => write!(writer, "empty"),
+        Action::Player {
+            button,
+            hold,
+            player2,
+        } => {
+            let edge = if hold { "press" } else { "release" };
+            let button = match button {
+                PlayerButton::Jump => "jump",
+                PlayerButton::Left => "left",
+                PlayerButton::Right => "right",
+            };
+            let player = if player2 { "p2" } else { "p1" };
+            write!(writer, "{edge} {button} {player}")
+        }
+        Action::Restart { restart_type, seed } => {
+            let restart_type = match restart_type {
+                RestartType::Restart => "restart",
+                RestartType::RestartFull => "full",
+                RestartType::Death => "death",
+            };
+            write!(writer, "restart {restart_type}")?;
+            if let Some(seed) = seed {
+                write!(writer, " seed {seed}")?;
+            }
+            Ok(())
+        }
+        Action::TPS { tps } => write!(writer, "tps {tps}"),
+        Action::Bugpoint => write!(writer, "bugpoint"),
+    }
+}
+
+fn write_player<W: Write>(writer: &mut W, player: Option<&PlayerPosition>) -> std::io::Result<()> {
+    let Some(player) = player else {
+        return write!(writer, " | -");
+    };
+
+    write!(writer, " | {} {}", player.x, player.y)?;
+    for value in [player.rotation, player.vel_x, player.vel_y] {
+        match value {
+            Some(value) => write!(writer, " {value}")?,
+            None => write!(writer, " -")?,
+        }
+    }
+
+    Ok(())
+}
+
+impl ReplaySerializable for PlainTextReplay {
+    fn new(replay: Replay) -> Self {
+        PlainTextReplay { inner: replay }
+    }
+
+    fn into_replay(self) -> Replay {
+        self.inner
+    }
+
+    fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, ReplayError>
+    where
+        Self: Sized,
+    {
+        let mut version = None;
+        let mut game_version = GameVersion::universal();
+        let mut frame_counting = FrameCountingMethod::Unknown;
+        let mut meta = MetaMap::new();
+        let mut actions = Vec::new();
+
+        for (number, line) in BufReader::new(reader).lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let invalid =
+                |reason: &str| ReplayError::ReadError(format!("line {}: {reason}", number + 1));
+            let (directive, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let rest = rest.trim();
+
+            match directive {
+                "version" => {
+                    let found = rest
+                        .parse::<u32>()
+                        .map_err(|_| invalid("invalid version"))?;
+                    if found != VERSION {
+                        return Err(invalid(&format!("unsupported version {found}")));
+                    }
+                    version = Some(found);
+                }
+                _ if version.is_none() => return Err(invalid("expected the version first")),
+                "game" => game_version = rest.parse().map_err(|e: String| invalid(&e))?,
+                "frames" => frame_counting = rest.parse().map_err(|e: String| invalid(&e))?,
+                "meta" => {
+                    let (key, value) = rest
+                        .split_once(char::is_whitespace)
+                        .ok_or_else(|| invalid("expected a meta key and value"))?;
+                    let value = serde_json::from_str::<String>(value.trim())
+                        .map_err(|e| invalid(&format!("invalid meta value: {e}")))?;
+                    meta.insert(key, value);
+                }
+                _ => {
+                    actions
+                        .push(parse_timed_action(line).ok_or_else(|| invalid("invalid action"))?);
+                }
+            }
+        }
+
+        if version.is_none() {
+            return Err(ReplayError::ReadError(
+                "plain text replay is missing its version".to_string(),
+            ));
+        }
+
+        Ok(PlainTextReplay {
+            inner: Replay {
+                meta: Box::new(meta),
+                actions,
+                format: Format::PlainText,
+                game_version,
+                frame_counting,
+            },
+        })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+        Self::write_as(&self.inner, Format::PlainText, writer)
+    }
+
+    fn write_as<W: Write>(
+        replay: &Replay,
+        _format: Format,
+        writer: &mut W,
+    ) -> Result<(), ReplayError>
+    where
+        Self: Sized,
+    {
+        let meta = MetaMap::from_meta(replay.meta.as_ref());
+
+        writeln!(writer, "# siliconv plain text replay")?;
+        writeln!(writer, "version {VERSION}")?;
+        writeln!(writer, "game {}", replay.game_version)?;
+        if replay.frame_counting != FrameCountingMethod::Unknown {
+            writeln!(writer, "frames {}", replay.frame_counting)?;
+        }
+
+        for (key, value) in meta.iter() {
+            if key.is_empty() || key.contains(char::is_whitespace) {
+                return Err(ReplayError::WriteError(format!("invalid meta key {key:?}")));
+            }
+
+            let value = serde_json::to_string(value)
+                .map_err(|e| ReplayError::WriteError(format!("invalid meta value: {e}")))?;
+            writeln!(writer, "meta {key} {value}")?;
+        }
+
+        writeln!(writer)?;
+        for timed in &replay.actions {
+            write_time(writer, timed.time)?;
+            write!(writer, " ")?;
+            write_action(writer, timed.action)?;
+            if let Some(position) = &timed.position {
+                write_player(writer, position.player1.as_ref())?;
+                write_player(writer, position.player2.as_ref())?;
+            }
+            writeln!(writer)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn player(x: f64, y: f64) -> PlayerPosition {
+        PlayerPosition {
+            x,
+            y,
+            rotation: Some(90.0),
+            vel_x: None,
+            vel_y: Some(-1.5),
+        }
+    }
+
+    #[test]
+    fn test_plaintext_roundtrip() {
+        let mut meta = MetaMap::new();
+        meta.insert("tps", "240");
+        meta.insert("author", "line one\nline \"two\"");
+
+        let actions = vec![
+            TimedAction {
+                time: TimePoint::Frame(10),
+                action: Action::Player {
+                    button: PlayerButton::Jump,
+                    hold: true,
+                    player2: false,
+                },
+                position: Some(Position {
+                    player1: Some(player(0.1 + 0.2, 105.0)),
+                    player2: Some(player(-3.0, f64::INFINITY)),
+                }),
+            },
+            TimedAction {
+                time: TimePoint::XPos(120.5),
+                action: Action::Player {
+                    button: PlayerButton::Left,
+                    hold: false,
+                    player2: true,
+                },
+                position: None,
+            },
+            TimedAction {
+                time: TimePoint::Time(1.0 / 3.0),
+                action: Action::TPS { tps: 480.0 },
+                position: None,
+            },
+            TimedAction {
+                time: TimePoint::Frame(300),
+                action: Action::Restart {
+                    restart_type: RestartType::Death,
+                    seed: Some(u64::MAX),
+                },
+                position: None,
+            },
+            TimedAction {
+                time: TimePoint::Frame(301),
+                action: Action::Restart {
+                    restart_type: RestartType::RestartFull,
+                    seed: None,
+                },
+                position: None,
+            },
+            TimedAction {
+                time: TimePoint::Frame(302),
+                action: Action::Bugpoint,
+                position: None,
+            },
+            TimedAction {
+                time: TimePoint::Frame(303),
+                action: Action::Empty,
+                position: Some(Position {
+                    player1: Some(player(40.0, 105.0)),
+                    player2: Some(player(40.0, 225.0)),
+                }),
+            },
+            TimedAction {
+                time: TimePoint::Frame(304),
+                action: Action::Player {
+                    button: PlayerButton::Jump,
+                    hold: true,
+                    player2: true,
+                },
+                position: Some(Position {
+                    player1: None,
+                    player2: Some(player(40.0, 225.0)),
+                }),
+            },
+        ];
+
+        let replay = PlainTextReplay::new(Replay {
+            meta: Box::new(meta.clone()),
+            actions: actions.clone(),
+            format: Format::GDR2,
+            game_version: GameVersion::new(22, 74),
+            frame_counting: FrameCountingMethod::CurrentProgress,
+        });
+
+        let mut buf = Vec::new();
+        replay.write(&mut buf).unwrap();
+
+        let read = PlainTextReplay::read(&mut Cursor::new(buf.clone()))
+            .unwrap()
+            .into_replay();
+        assert_eq!(MetaMap::from_meta(read.meta.as_ref()), meta);
+        assert_eq!(read.actions, actions);
+        assert_eq!((read.game_version.major, read.game_version.minor), (22, 74));
+        assert_eq!(read.frame_counting, FrameCountingMethod::CurrentProgress);
+
+        let mut rewritten = Vec::new();
+        PlainTextReplay::new(read).write(&mut rewritten).unwrap();
+        assert_eq!(rewritten, buf);
+    }
+
+    #[test]
+    fn test_plaintext_reads_hand_written() {
+        let text = "# a comment\n\
+                    version 1\n\
+                    \n\
+                    meta tps \"60\"\n\
+                    f1 press jump p2\n\
+                    # another comment\n\
+                    f2 release jump p2 | 1 2 - - - | 3 4 5 6 7\n";
+
+        let replay = PlainTextReplay::read(&mut Cursor::new(text))
+            .unwrap()
+            .into_replay();
+        assert!(replay.game_version.is_universal());
+        assert_eq!(
+            MetaMap::from_meta(replay.meta.as_ref()).get("tps"),
+            Some("60")
+        );
+        assert_eq!(replay.actions.len(), 2);
+        assert_eq!(
+            replay.actions[1].position.unwrap().player2,
+            Some(PlayerPosition {
+                x: 3.0,
+                y: 4.0,
+                rotation: Some(5.0),
+                vel_x: Some(6.0),
+                vel_y: Some(7.0),
+            })
+        );
+
+        assert!(PlainTextReplay::read(&mut Cursor::new("f1 press jump p1\n")).is_err());
+        assert!(PlainTextReplay::read(&mut Cursor::new("version 1\nf1 jump\n")).is_err());
+    }
+}
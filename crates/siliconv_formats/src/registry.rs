@@ -0,0 +1,274 @@
+//! A format registry with the reader and writer of every format in this crate.
+
+use siliconv_core::{format::Format, registry::Registry};
+
+use crate::{
+    echo::EchoReplay, gdr::GdrReplay, gdr2::Gdr2Replay, kdbot::KdBotReplay, mhr::MhrReplay,
+    omegabot::OmegaBotReplay, plaintext::PlainTextReplay, replaybot::ReplayBotReplay,
+    replayengine::ReplayEngineReplay, rush::RushReplay, silicate::SilicateReplay,
+    tasbot::TasBotReplay, tcbot::TcBotReplay, uvbot::UvBotReplay, xbot::XBotReplay,
+    xdbot::XdBotReplay, ybot::YBotReplay, zbot::ZBotReplay,
+};
+
+/// A registry knowing about every format in this crate.
+#[must_use]
+pub fn registry() -> Registry {
+    Registry::new()
+        .with::<PlainTextReplay>(&[Format::PlainText])
+        .with::<OmegaBotReplay>(&[Format::OmegaBot, Format::OmegaBot2, Format::OmegaBot3])
+        .with::<MhrReplay>(&[Format::MHRJson, Format::MHR])
+        .with::<TasBotReplay>(&[Format::TASBot])
+        .with::<ZBotReplay>(&[Format::ZBotXpos, Format::ZBotFrame])
+        .with::<ReplayBotReplay>(&[Format::ReplayBot])
+        .with::<EchoReplay>(&[Format::EchoOld, Format::EchoNewJson, Format::EchoNewBinary])
+        .with::<YBotReplay>(&[Format::YBot1])
+        .with::<XBotReplay>(&[Format::XBot])
+        .with::<RushReplay>(&[Format::Rush])
+        .with::<KdBotReplay>(&[Format::KDBot])
+        .with::<GdrReplay>(&[Format::GDR1, Format::GDR1Json])
+        .with::<Gdr2Replay>(&[Format::GDR2])
+        .with::<XdBotReplay>(&[Format::XDBot])
+        .with::<ReplayEngineReplay>(&[
+            Format::ReplayEngineV1,
+            Format::ReplayEngineV2,
+            Format::ReplayEngineV3,
+        ])
+        .with::<SilicateReplay>(&[Format::Slc1, Format::Slc2, Format::Slc3])
+        .with::<UvBotReplay>(&[Format::UVBot])
+        .with::<TcBotReplay>(&[Format::TCBot])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use siliconv_core::{
+        action::{Action, FrameCountingMethod, PlayerButton, TimePoint, TimedAction},
+        convert::Loss,
+        error::ReplayError,
+        meta::Meta,
+        replay::Replay,
+        version::GameVersion,
+    };
+
+    use super::*;
+    use crate::detect::detector;
+
+    #[test]
+    fn test_registry_covers_every_format() {
+        let registry = registry();
+        for &format in Format::ALL {
+            assert!(registry.supports(format), "{format:?} is not registered");
+        }
+    }
+
+    #[test]
+    fn test_registry_roundtrip() {
+        #[derive(siliconv_macros::Meta)]
+        struct TpsMeta {
+            tps: f64,
+        }
+
+        let registry = registry();
+        let replay = Replay {
+            meta: Box::new(TpsMeta { tps: 240.0 }),
+            actions: vec![TimedAction {
+                time: TimePoint::Frame(12),
+                action: Action::Player {
+                    button: PlayerButton::Jump,
+                    hold: true,
+                    player2: false,
+                },
+                position: None,
+            }],
+            format: Format::PlainText,
+            game_version: GameVersion::new(22, 74),
+            frame_counting: FrameCountingMethod::Unknown,
+        };
+
+        for format in [Format::GDR2, Format::Slc2, Format::Slc3, Format::EchoOld] {
+            let mut buf = Vec::new();
+            registry.write(format, &replay, &mut buf).unwrap();
+            assert_eq!(detector().best(&buf, None), Some(format));
+
+            let read = registry.read(format, &mut Cursor::new(buf)).unwrap();
+            assert_eq!(read.format, format);
+            assert_eq!(read.frame_counting, format.descriptor().frame_counting);
+            assert_eq!(read.actions, replay.actions);
+            assert!(TpsMeta::from_fields(read.meta.fields()).tps.eq(&240.0));
+        }
+    }
+
+    #[test]
+    fn test_registry_convert_reports_losses() {
+        let source = "version 1\n\
+                      game 2.2074\n\
+                      f10 press jump p1\n\
+                      f20 press left p1\n\
+                      f30 bugpoint\n\
+                      f40 release jump p1\n";
+
+        let mut out = Vec::new();
+        let report = registry()
+            .convert(
+                Format::PlainText,
+                &mut Cursor::new(source),
+                Format::YBot1,
+                &mut out,
+            )
+            .unwrap();
+        assert_eq!(report.count(Loss::PlatformerRemoved), 1);
+        assert_eq!(report.count(Loss::BugpointsRemoved), 1);
+
+        let read = registry()
+            .read(Format::YBot1, &mut Cursor::new(out))
+            .unwrap();
+        assert_eq!(
+            read.actions
+                .iter()
+                .map(|timed| timed.time)
+                .collect::<Vec<_>>(),
+            vec![TimePoint::Frame(10), TimePoint::Frame(40)]
+        );
+    }
+
+    #[test]
+    fn test_registry_convert_retimes_xpos() {
+        let replay = Replay {
+            meta: Box::new(crate::zbot::ZBotMeta {
+                delta: 1.0 / 240.0,
+                speedhack: 1.0,
+                tps: 240.0,
+            }),
+            actions: vec![TimedAction {
+                time: TimePoint::XPos(311.58),
+                action: Action::Player {
+                    button: PlayerButton::Jump,
+                    hold: true,
+                    player2: false,
+                },
+                position: None,
+            }],
+            format: Format::ZBotXpos,
+            game_version: GameVersion::new(21, 130),
+            frame_counting: FrameCountingMethod::LevelTime,
+        };
+
+        let registry = registry();
+        let mut zbot = Vec::new();
+        registry
+            .write(Format::ZBotXpos, &replay, &mut zbot)
+            .unwrap();
+
+        let mut out = Vec::new();
+        let report = registry
+            .convert(
+                Format::ZBotXpos,
+                &mut Cursor::new(zbot),
+                Format::YBot1,
+                &mut out,
+            )
+            .unwrap();
+        assert_eq!(report.count(Loss::XPosRetimed), 1);
+
+        let read = registry.read(Format::YBot1, &mut Cursor::new(out)).unwrap();
+        assert_eq!(read.actions[0].time, TimePoint::Frame(240));
+    }
+
+    #[test]
+    fn test_registry_convert_zbf_keeps_tps() {
+        let mut zbf = Vec::new();
+        zbf.extend((1.0_f32 / 60.0).to_le_bytes());
+        zbf.extend(1.0_f32.to_le_bytes());
+        zbf.extend(30_i32.to_le_bytes());
+        zbf.extend(*b"11");
+
+        let registry = registry();
+        let mut out = Vec::new();
+        let report = registry
+            .convert(
+                Format::ZBotFrame,
+                &mut Cursor::new(zbf),
+                Format::Slc3,
+                &mut out,
+            )
+            .unwrap();
+        assert!(report.is_lossless());
+
+        let read = registry.read(Format::Slc3, &mut Cursor::new(out)).unwrap();
+        let meta = crate::silicate::SilicateMeta::from_fields(read.meta.fields());
+        assert!(meta.tps.eq(&60.0));
+    }
+
+    #[test]
+    fn test_registry_convert_to_xpos_only_formats() {
+        let source = "version 1\n\
+                      game 2.113\n\
+                      meta tps \"240\"\n\
+                      f120 press jump p1\n\
+                      f240 release jump p1\n";
+
+        let registry = registry();
+        for format in [Format::OmegaBot, Format::ZBotXpos] {
+            let mut out = Vec::new();
+            let report = registry
+                .convert(
+                    Format::PlainText,
+                    &mut Cursor::new(source),
+                    format,
+                    &mut out,
+                )
+                .unwrap();
+            assert_eq!(report.count(Loss::FramesToXPos), 2);
+        }
+    }
+
+    #[test]
+    fn test_registry_reads_the_requested_version() {
+        let registry = registry();
+        let replay = Replay {
+            meta: Box::new(crate::zbot::ZBotMeta {
+                delta: 1.0 / 240.0,
+                speedhack: 1.0,
+                tps: 240.0,
+            }),
+            actions: vec![TimedAction {
+                time: TimePoint::XPos(0.0),
+                action: Action::Player {
+                    button: PlayerButton::Jump,
+                    hold: true,
+                    player2: false,
+                },
+                position: None,
+            }],
+            format: Format::ZBotXpos,
+            game_version: GameVersion::new(21, 130),
+            frame_counting: FrameCountingMethod::LevelTime,
+        };
+
+        let mut zbot = Vec::new();
+        registry
+            .write(Format::ZBotXpos, &replay, &mut zbot)
+            .unwrap();
+        let read = registry
+            .read(Format::ZBotXpos, &mut Cursor::new(zbot))
+            .unwrap();
+        assert_eq!(read.actions, replay.actions);
+
+        let mut slc3 = Vec::new();
+        registry
+            .write(
+                Format::Slc3,
+                &Replay {
+                    actions: Vec::new(),
+                    ..replay
+                },
+                &mut slc3,
+            )
+            .unwrap();
+        assert!(matches!(
+            registry.read(Format::Slc2, &mut Cursor::new(slc3)),
+            Err(ReplayError::ReadError(_))
+        ));
+    }
+}
@@ -0,0 +1,259 @@
+//! ``ReplayBot`` replays.
+//!
+//! ```text
+//! "RPLY" version:u8 [type:u8] fps:f32 { location:u32 flags:u8 }*
+//! ```
+//!
+//! The replay type is only present from version 2 onwards: 0 means x position and 1 means frame.
+//! Version 1 replays are always x position. X positions are stored as the raw bits of an `f32`.
+//! Flags are bit 0 (hold) and bit 1 (player 2).
+
+use std::io::{Read, Seek, Write};
+
+use siliconv_core::{
+    action::{Action, FrameCountingMethod, PlayerButton, TimePoint, TimedAction},
+    error::ReplayError,
+    format::{Confidence, Format},
+    meta::Meta,
+    replay::{Replay, ReplaySerializable},
+    version::GameVersion,
+};
+use siliconv_macros::Meta;
+
+use crate::binary::{self, ReadBytes, WriteBytes};
+
+const MAGIC: &[u8; 4] = b"RPLY";
+const VERSION: u8 = 2;
+
+#[derive(Meta)]
+pub struct ReplayBotMeta {
+    #[meta(default = 60.0)]
+    pub tps: f64,
+}
+
+/// Sniffs a ``ReplayBot`` replay by its header.
+#[must_use]
+pub fn sniff(data: &[u8]) -> Option<(Format, Confidence)> {
+    (ReplayBotReplay::detect(data) && matches!(data.get(MAGIC.len()), Some(1 | 2)))
+        .then_some((Format::ReplayBot, Confidence::High))
+}
+
+pub struct ReplayBotReplay {
+    inner: Replay,
+}
+
+impl ReplayBotReplay {
+    /// Whether the first bytes of a file look like a ``ReplayBot`` replay.
+    #[must_use]
+    pub fn detect(header: &[u8]) -> bool {
+        header.starts_with(MAGIC)
+    }
+}
+
+impl ReplaySerializable for ReplayBotReplay {
+    fn new(replay: Replay) -> Self {
+        ReplayBotReplay { inner: replay }
+    }
+
+    fn into_replay(self) -> Replay {
+        self.inner
+    }
+
+    fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, ReplayError>
+    where
+        Self: Sized,
+    {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(ReplayError::ReadError(
+                "missing replaybot header".to_string(),
+            ));
+        }
+
+        let version = reader.read_u8()?;
+        let xpos = match version {
+            1 => true,
+            2 => reader.read_u8()? == 0,
+            _ => {
+                return Err(ReplayError::ReadError(format!(
+                    "unsupported replaybot version {version}"
+                )));
+            }
+        };
+        let fps = reader.read_f32()?;
+
+        let remaining = binary::remaining(reader)?;
+        if remaining % 5 != 0 {
+            return Err(ReplayError::ReadError(
+                "replaybot replay has a truncated action".to_string(),
+            ));
+        }
+
+        let actions = (0..remaining / 5)
+            .map(|_| {
+                let location = reader.read_u32()?;
+                let flags = reader.read_u8()?;
+
+                Ok(TimedAction {
+                    time: if xpos {
+                        TimePoint::XPos(f64::from(f32::from_bits(location)))
+                    } else {
+                        TimePoint::Frame(u64::from(location))
+                    },
+                    action: Action::Player {
+                        button: PlayerButton::Jump,
+                        hold: flags & 1 != 0,
+                        player2: flags & 2 != 0,
+                    },
+                    position: None,
+                })
+            })
+            .collect::<Result<_, ReplayError>>()?;
+
+        Ok(ReplayBotReplay {
+            inner: Replay {
+                meta: Box::new(ReplayBotMeta {
+                    tps: f64::from(fps),
+                }),
+                actions,
+                format: Format::ReplayBot,
+                game_version: GameVersion::new(21, 130),
+                frame_counting: FrameCountingMethod::LevelTime,
+            },
+        })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+        Self::write_as(&self.inner, Format::ReplayBot, writer)
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_as<W: Write>(
+        replay: &Replay,
+        _format: Format,
+        writer: &mut W,
+    ) -> Result<(), ReplayError>
+    where
+        Self: Sized,
+    {
+        let tps = ReplayBotMeta::from_fields(replay.meta.fields()).tps;
+
+        let actions = replay
+            .actions
+            .iter()
+            .filter(|timed| !matches!(timed.action, Action::Empty))
+            .collect::<Vec<_>>();
+        let xpos = actions
+            .first()
+            .is_some_and(|timed| matches!(timed.time, TimePoint::XPos(_)));
+
+        writer.write_all(MAGIC)?;
+        writer.write_u8(VERSION)?;
+        writer.write_u8(u8::from(!xpos))?;
+        writer.write_f32(tps as f32)?;
+
+        for timed in actions {
+            let Action::Player {
+                button: PlayerButton::Jump,
+                hold,
+                player2,
+            } = timed.action
+            else {
+                return Err(ReplayError::WriteError(format!(
+                    "replaybot does not support {:?} actions",
+                    timed.action
+                )));
+            };
+
+            let location = match timed.time {
+                TimePoint::XPos(x) if xpos => (x as f32).to_bits(),
+                TimePoint::Frame(frame) if !xpos => u32::try_from(frame).map_err(|_| {
+                    ReplayError::WriteError(format!("frame {frame} is too large for replaybot"))
+                })?,
+                _ => {
+                    return Err(ReplayError::WriteError(
+                        "replaybot replays must use only x position or only frame time points"
+                            .to_string(),
+                    ));
+                }
+            };
+
+            writer.write_u32(location)?;
+            writer.write_u8(u8::from(hold) | u8::from(player2) << 1)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn click(time: TimePoint, hold: bool, player2: bool) -> TimedAction {
+        TimedAction {
+            time,
+            action: Action::Player {
+                button: PlayerButton::Jump,
+                hold,
+                player2,
+            },
+            position: None,
+        }
+    }
+
+    #[test]
+    fn test_replaybot_modes_roundtrip() {
+        let xpos = vec![
+            click(TimePoint::XPos(64.5), true, false),
+            click(TimePoint::XPos(90.0), false, true),
+        ];
+        let frames = vec![
+            click(TimePoint::Frame(12), true, true),
+            click(TimePoint::Frame(20), false, false),
+        ];
+
+        for actions in [xpos, frames] {
+            let replay = ReplayBotReplay::new(Replay {
+                meta: Box::new(ReplayBotMeta { tps: 120.0 }),
+                actions: actions.clone(),
+                format: Format::ReplayBot,
+                game_version: GameVersion::new(21, 130),
+                frame_counting: FrameCountingMethod::LevelTime,
+            });
+
+            let mut buf = Vec::new();
+            replay.write(&mut buf).unwrap();
+            assert!(ReplayBotReplay::detect(&buf));
+
+            let read = ReplayBotReplay::read(&mut Cursor::new(buf))
+                .unwrap()
+                .into_replay();
+            assert!(
+                ReplayBotMeta::from_fields(read.meta.fields())
+                    .tps
+                    .eq(&120.0)
+            );
+            assert_eq!(read.actions, actions);
+        }
+    }
+
+    #[test]
+    fn test_replaybot_reads_version_1() {
+        let mut buf = b"RPLY\x01".to_vec();
+        buf.extend(60.0_f32.to_le_bytes());
+        buf.extend(32.0_f32.to_bits().to_le_bytes());
+        buf.push(1);
+
+        let read = ReplayBotReplay::read(&mut Cursor::new(buf))
+            .unwrap()
+            .into_replay();
+        assert_eq!(
+            read.actions,
+            vec![click(TimePoint::XPos(32.0), true, false)]
+        );
+    }
+}
@@ -0,0 +1,707 @@
+//! ``ReplayEngine`` replays.
+//!
+//! ``ReplayEngine`` stores clicks and frame fixes (physics) as two separate little-endian streams:
+//!
+//! ```text
+//! v1: fps:f32 physics:u32 inputs:u32 { frame:u32 state player1:u8 }* { frame:u32 hold:u8 player1:u8 }*
+//! v2: "RE2\0" fps:f32 physics:u32 inputs:u32 { frame:u32 state player1:u8 }* { frame:u32 button:u8 hold:u8 player1:u8 }*
+//! v3: "RE3\0" tps:f64 physics:u32 inputs:u32 { frame:u32 state3 state3 }* { frame:u32 flags:u8 }*
+//!
+//! state:  x:f32 y:f32 rotation:f32 y_vel:f64
+//! state3: x:f32 y:f32 rotation:f32 x_vel:f64 y_vel:f64
+//! ```
+//!
+//! Buttons are 1 (jump), 2 (left) and 3 (right). v3 input flags are bit 0 (hold), bit 1 (player 2)
+//! and bits 2-3 (button).
+//!
+//! Physics are attached to the first click on the same frame, or become an [`Action::Empty`] when
+//! there is none. v1 and v2 store one physics entry per player, so a player without an entry on a
+//! frame is missing. v3 stores both players at once and can't write a frame missing either.
+//! Neither can store two different positions of a player on one frame.
+
+use std::{
+    collections::BTreeMap,
+    io::{Read, Seek, Write},
+};
+
+use siliconv_core::{
+    action::{
+        Action, FrameCountingMethod, PlayerButton, PlayerPosition, Position, TimePoint, TimedAction,
+    },
+    error::ReplayError,
+    format::{Confidence, Format},
+    meta::Meta,
+    replay::{Replay, ReplaySerializable},
+    version::GameVersion,
+};
+use siliconv_macros::Meta;
+
+use crate::{
+    binary::{self, ReadBytes, WriteBytes},
+    detect,
+};
+
+const V2_MAGIC: &[u8; 4] = b"RE2\0";
+const V3_MAGIC: &[u8; 4] = b"RE3\0";
+
+#[derive(Meta)]
+pub struct ReplayEngineMeta {
+    #[meta(default = 240.0)]
+    pub tps: f64,
+}
+
+/// A ``ReplayEngine`` replay format version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayEngineVersion {
+    /// ``ReplayEngine`` v1 .re replay.
+    V1,
+    /// ``ReplayEngine`` v2 .re2 replay.
+    V2,
+    /// ``ReplayEngine`` v3 .re3 replay.
+    V3,
+}
+
+impl ReplayEngineVersion {
+    /// The replay format corresponding to this version.
+    #[must_use]
+    pub const fn format(self) -> Format {
+        match self {
+            Self::V1 => Format::ReplayEngineV1,
+            Self::V2 => Format::ReplayEngineV2,
+            Self::V3 => Format::ReplayEngineV3,
+        }
+    }
+
+    /// The ``ReplayEngine`` version of a replay format, if it is a ``ReplayEngine`` format.
+    #[must_use]
+    pub const fn from_format(format: Format) -> Option<Self> {
+        match format {
+            Format::ReplayEngineV1 => Some(Self::V1),
+            Format::ReplayEngineV2 => Some(Self::V2),
+            Format::ReplayEngineV3 => Some(Self::V3),
+            _ => None,
+        }
+    }
+
+    /// Picks the version from the first bytes of a file.
+    ///
+    /// v1 has no header, so anything else is assumed to be v1.
+    #[must_use]
+    pub fn detect(header: &[u8]) -> Self {
+        if header.starts_with(V3_MAGIC) {
+            Self::V3
+        } else if header.starts_with(V2_MAGIC) {
+            Self::V2
+        } else {
+            Self::V1
+        }
+    }
+}
+
+/// The click and physics streams of a replay.
+#[derive(Default)]
+struct Streams {
+    inputs: Vec<(u32, Action)>,
+    physics: BTreeMap<u32, Position>,
+}
+
+impl Streams {
+    fn split(actions: &[TimedAction]) -> Result<Self, ReplayError> {
+        let mut streams = Streams::default();
+
+        for timed in actions {
+            let TimePoint::Frame(frame) = timed.time else {
+                return Err(ReplayError::WriteError(
+                    "replayengine only supports frame time points".to_string(),
+                ));
+            };
+            let frame = u32::try_from(frame).map_err(|_| {
+                ReplayError::WriteError(format!("frame {frame} is too large for replayengine"))
+            })?;
+
+            match timed.action {
+                Action::Empty => {}
+                Action::Player { .. } => streams.inputs.push((frame, timed.action)),
+                _ => {
+                    return Err(ReplayError::WriteError(format!(
+                        "replayengine does not support {:?} actions",
+                        timed.action
+                    )));
+                }
+            }
+
+            if let Some(position) = timed.position {
+                let entry = streams.physics.entry(frame).or_insert(Position {
+                    player1: None,
+                    player2: None,
+                });
+
+                for (player, slot, state) in [
+                    (1, &mut entry.player1, position.player1),
+                    (2, &mut entry.player2, position.player2),
+                ] {
+                    match (*slot, state) {
+                        (Some(existing), Some(state)) if existing != state => {
+                            return Err(ReplayError::WriteError(format!(
+                                "replayengine stores one position of player {player} per frame, \
+                                 but frame {frame} has several"
+                            )));
+                        }
+                        (None, Some(_)) => *slot = state,
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        Ok(streams)
+    }
+
+    fn merge(self) -> Vec<TimedAction> {
+        let mut physics = self.physics;
+        let mut actions = Vec::with_capacity(self.inputs.len() + physics.len());
+
+        let physics_only = |actions: &mut Vec<TimedAction>, frame: u32, position| {
+            actions.push(TimedAction {
+                time: TimePoint::Frame(u64::from(frame)),
+                action: Action::Empty,
+                position: Some(position),
+            });
+        };
+
+        for (frame, action) in self.inputs {
+            while let Some(entry) = physics.first_entry()
+                && *entry.key() < frame
+            {
+                let (frame, position) = entry.remove_entry();
+                physics_only(&mut actions, frame, position);
+            }
+
+            actions.push(TimedAction {
+                time: TimePoint::Frame(u64::from(frame)),
+                action,
+                position: physics.remove(&frame),
+            });
+        }
+
+        for (frame, position) in physics {
+            physics_only(&mut actions, frame, position);
+        }
+
+        actions
+    }
+}
+
+fn button_id(button: PlayerButton) -> u8 {
+    match button {
+        PlayerButton::Jump => 1,
+        PlayerButton::Left => 2,
+        PlayerButton::Right => 3,
+    }
+}
+
+fn button_from_id(id: u8) -> Result<PlayerButton, ReplayError> {
+    match id {
+        1 => Ok(PlayerButton::Jump),
+        2 => Ok(PlayerButton::Left),
+        3 => Ok(PlayerButton::Right),
+        _ => Err(ReplayError::ReadError(format!(
+            "invalid replayengine button {id}"
+        ))),
+    }
+}
+
+fn read_state<R: Read>(reader: &mut R, x_vel: bool) -> Result<PlayerPosition, ReplayError> {
+    Ok(PlayerPosition {
+        x: f64::from(reader.read_f32()?),
+        y: f64::from(reader.read_f32()?),
+        rotation: Some(f64::from(reader.read_f32()?)),
+        vel_x: if x_vel {
+            Some(reader.read_f64()?)
+        } else {
+            None
+        },
+        vel_y: Some(reader.read_f64()?),
+    })
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn write_state<W: Write>(
+    writer: &mut W,
+    state: &PlayerPosition,
+    x_vel: bool,
+) -> Result<(), ReplayError> {
+    writer.write_f32(state.x as f32)?;
+    writer.write_f32(state.y as f32)?;
+    writer.write_f32(state.rotation.unwrap_or_default() as f32)?;
+    if x_vel {
+        writer.write_f64(state.vel_x.unwrap_or_default())?;
+    }
+    writer.write_f64(state.vel_y.unwrap_or_default())?;
+    Ok(())
+}
+
+fn count(len: usize) -> Result<u32, ReplayError> {
+    u32::try_from(len)
+        .map_err(|_| ReplayError::WriteError("too many replayengine entries".to_string()))
+}
+
+/// Sniffs a ``ReplayEngine`` replay. v1 has no header, so it is checked by its stream sizes.
+#[must_use]
+pub fn sniff(data: &[u8]) -> Option<(Format, Confidence)> {
+    let version = ReplayEngineVersion::detect(data);
+    if version != ReplayEngineVersion::V1 {
+        return Some((version.format(), Confidence::High));
+    }
+
+    let fps = detect::f32_at(data, 0)?;
+    let physics = usize::try_from(detect::u32_at(data, 4)?).ok()?;
+    let inputs = usize::try_from(detect::u32_at(data, 8)?).ok()?;
+    let len = physics
+        .checked_mul(25)?
+        .checked_add(inputs.checked_mul(6)?)?
+        .checked_add(12)?;
+
+    (detect::plausible_fps(f64::from(fps)) && len == data.len())
+        .then_some((Format::ReplayEngineV1, Confidence::Medium))
+}
+
+pub struct ReplayEngineReplay {
+    inner: Replay,
+    version: ReplayEngineVersion,
+}
+
+impl ReplayEngineReplay {
+    /// Set the ``ReplayEngine`` version to write.
+    #[must_use]
+    pub fn with_version(mut self, version: ReplayEngineVersion) -> Self {
+        self.version = version;
+        self
+    }
+
+    /// The ``ReplayEngine`` version this replay will be written as.
+    #[must_use]
+    pub fn version(&self) -> ReplayEngineVersion {
+        self.version
+    }
+
+    /// Reads the per-player physics and inputs of v1 and v2.
+    fn read_legacy<R: Read>(
+        reader: &mut R,
+        version: ReplayEngineVersion,
+    ) -> Result<Streams, ReplayError> {
+        let physics_count = reader.read_u32()?;
+        let input_count = reader.read_u32()?;
+        let mut streams = Streams::default();
+
+        for _ in 0..physics_count {
+            let frame = reader.read_u32()?;
+            let state = read_state(reader, false)?;
+            let player1 = reader.read_u8()? != 0;
+
+            let position = streams.physics.entry(frame).or_insert(Position {
+                player1: None,
+                player2: None,
+            });
+            if player1 {
+                position.player1 = Some(state);
+            } else {
+                position.player2 = Some(state);
+            }
+        }
+
+        for _ in 0..input_count {
+            let frame = reader.read_u32()?;
+            let button = if version == ReplayEngineVersion::V2 {
+                button_from_id(reader.read_u8()?)?
+            } else {
+                PlayerButton::Jump
+            };
+            let hold = reader.read_u8()? != 0;
+            let player1 = reader.read_u8()? != 0;
+
+            streams.inputs.push((
+                frame,
+                Action::Player {
+                    button,
+                    hold,
+                    player2: !player1,
+                },
+            ));
+        }
+
+        Ok(streams)
+    }
+
+    fn read_v3<R: Read>(reader: &mut R) -> Result<Streams, ReplayError> {
+        let physics_count = reader.read_u32()?;
+        let input_count = reader.read_u32()?;
+        let mut streams = Streams::default();
+
+        for _ in 0..physics_count {
+            let frame = reader.read_u32()?;
+            let position = Position {
+                player1: Some(read_state(reader, true)?),
+                player2: Some(read_state(reader, true)?),
+            };
+            streams.physics.insert(frame, position);
+        }
+
+        for _ in 0..input_count {
+            let frame = reader.read_u32()?;
+            let flags = reader.read_u8()?;
+
+            streams.inputs.push((
+                frame,
+                Action::Player {
+                    button: button_from_id(flags >> 2 & 3)?,
+                    hold: flags & 1 != 0,
+                    player2: flags & 2 != 0,
+                },
+            ));
+        }
+
+        Ok(streams)
+    }
+
+    fn write_legacy<W: Write>(
+        writer: &mut W,
+        version: ReplayEngineVersion,
+        streams: &Streams,
+    ) -> Result<(), ReplayError> {
+        let physics = streams
+            .physics
+            .iter()
+            .flat_map(|(frame, position)| {
+                [(position.player1, true), (position.player2, false)]
+                    .into_iter()
+                    .filter_map(move |(state, player1)| Some((*frame, state?, player1)))
+            })
+            .collect::<Vec<_>>();
+
+        writer.write_u32(count(physics.len())?)?;
+        writer.write_u32(count(streams.inputs.len())?)?;
+
+        for (frame, state, player1) in &physics {
+            writer.write_u32(*frame)?;
+            write_state(writer, state, false)?;
+            writer.write_u8(u8::from(*player1))?;
+        }
+
+        for (frame, action) in &streams.inputs {
+            let Action::Player {
+                button,
+                hold,
+                player2,
+            } = *action
+            else {
+                continue;
+            };
+
+            writer.write_u32(*frame)?;
+            match version {
+                ReplayEngineVersion::V2 => writer.write_u8(button_id(button))?,
+                _ if button != PlayerButton::Jump => {
+                    return Err(ReplayError::WriteError(
+                        "replayengine v1 only supports jump inputs".to_string(),
+                    ));
+                }
+                _ => {}
+            }
+            writer.write_u8(u8::from(hold))?;
+            writer.write_u8(u8::from(!player2))?;
+        }
+
+        Ok(())
+    }
+
+    fn write_v3<W: Write>(writer: &mut W, streams: &Streams) -> Result<(), ReplayError> {
+        let physics = streams
+            .physics
+            .iter()
+            .filter_map(
+                |(frame, position)| match (position.player1, position.player2) {
+                    (None, None) => None,
+                    (Some(player1), Some(player2)) => Some(Ok((frame, player1, player2))),
+                    _ => Some(Err(ReplayError::WriteError(format!(
+                        "replayengine v3 stores the positions of both players, but frame {frame} \
+                     only has one"
+                    )))),
+                },
+            )
+            .collect::<Result<Vec<_>, _>>()?;
+
+        writer.write_u32(count(physics.len())?)?;
+        writer.write_u32(count(streams.inputs.len())?)?;
+
+        for (frame, player1, player2) in &physics {
+            writer.write_u32(**frame)?;
+            write_state(writer, player1, true)?;
+            write_state(writer, player2, true)?;
+        }
+
+        for (frame, action) in &streams.inputs {
+            let Action::Player {
+                button,
+                hold,
+                player2,
+            } = *action
+            else {
+                continue;
+            };
+
+            writer.write_u32(*frame)?;
+            writer.write_u8(u8::from(hold) | u8::from(player2) << 1 | button_id(button) << 2)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write a replay as the given version.
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_version<W: Write>(
+        replay: &Replay,
+        version: ReplayEngineVersion,
+        writer: &mut W,
+    ) -> Result<(), ReplayError> {
+        let tps = ReplayEngineMeta::from_fields(replay.meta.fields()).tps;
+        let streams = Streams::split(&replay.actions)?;
+
+        match version {
+            ReplayEngineVersion::V1 => {
+                writer.write_f32(tps as f32)?;
+                Self::write_legacy(writer, version, &streams)
+            }
+            ReplayEngineVersion::V2 => {
+                writer.write_all(V2_MAGIC)?;
+                writer.write_f32(tps as f32)?;
+                Self::write_legacy(writer, version, &streams)
+            }
+            ReplayEngineVersion::V3 => {
+                writer.write_all(V3_MAGIC)?;
+                writer.write_f64(tps)?;
+                Self::write_v3(writer, &streams)
+            }
+        }
+    }
+}
+
+impl ReplaySerializable for ReplayEngineReplay {
+    fn new(replay: Replay) -> Self {
+        let version =
+            ReplayEngineVersion::from_format(replay.format).unwrap_or(ReplayEngineVersion::V3);
+        ReplayEngineReplay {
+            inner: replay,
+            version,
+        }
+    }
+
+    fn into_replay(self) -> Replay {
+        self.inner
+    }
+
+    fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, ReplayError>
+    where
+        Self: Sized,
+    {
+        let version = ReplayEngineVersion::detect(&binary::peek(reader, 4)?);
+        let (tps, streams) = match version {
+            ReplayEngineVersion::V1 => {
+                let fps = reader.read_f32()?;
+                (f64::from(fps), Self::read_legacy(reader, version)?)
+            }
+            ReplayEngineVersion::V2 => {
+                reader.read_exact(&mut [0u8; 4])?;
+                let fps = reader.read_f32()?;
+                (f64::from(fps), Self::read_legacy(reader, version)?)
+            }
+            ReplayEngineVersion::V3 => {
+                reader.read_exact(&mut [0u8; 4])?;
+                let tps = reader.read_f64()?;
+                (tps, Self::read_v3(reader)?)
+            }
+        };
+
+        Ok(ReplayEngineReplay {
+            inner: Replay {
+                meta: Box::new(ReplayEngineMeta { tps }),
+                actions: streams.merge(),
+                format: version.format(),
+                game_version: GameVersion::new(22, 0),
+                frame_counting: FrameCountingMethod::CurrentProgress,
+            },
+            version,
+        })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+        Self::write_version(&self.inner, self.version, writer)
+    }
+
+    fn write_as<W: Write>(
+        replay: &Replay,
+        format: Format,
+        writer: &mut W,
+    ) -> Result<(), ReplayError>
+    where
+        Self: Sized,
+    {
+        Self::write_version(
+            replay,
+            ReplayEngineVersion::from_format(format).unwrap_or(ReplayEngineVersion::V3),
+            writer,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn state(x: f64, y: f64, x_vel: bool) -> PlayerPosition {
+        PlayerPosition {
+            x,
+            y,
+            rotation: Some(45.0),
+            vel_x: x_vel.then_some(10.5),
+            vel_y: Some(-2.25),
+        }
+    }
+
+    fn actions(x_vel: bool) -> Vec<TimedAction> {
+        vec![
+            TimedAction {
+                time: TimePoint::Frame(4),
+                action: Action::Empty,
+                position: Some(Position {
+                    player1: Some(state(10.0, 105.0, x_vel)),
+                    // Only v1 and v2 can leave a player out.
+                    player2: x_vel.then(|| state(10.0, 225.0, x_vel)),
+                }),
+            },
+            TimedAction {
+                time: TimePoint::Frame(8),
+                action: Action::Player {
+                    button: PlayerButton::Jump,
+                    hold: true,
+                    player2: false,
+                },
+                position: Some(Position {
+                    player1: Some(state(20.0, 105.0, x_vel)),
+                    player2: Some(state(20.0, 225.0, x_vel)),
+                }),
+            },
+            TimedAction {
+                time: TimePoint::Frame(8),
+                action: Action::Player {
+                    button: PlayerButton::Jump,
+                    hold: true,
+                    player2: true,
+                },
+                position: None,
+            },
+            TimedAction {
+                time: TimePoint::Frame(12),
+                action: Action::Player {
+                    button: PlayerButton::Jump,
+                    hold: false,
+                    player2: false,
+                },
+                position: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_replayengine_versions_roundtrip() {
+        for (version, x_vel) in [
+            (ReplayEngineVersion::V1, false),
+            (ReplayEngineVersion::V2, false),
+            (ReplayEngineVersion::V3, true),
+        ] {
+            let replay = ReplayEngineReplay::new(Replay {
+                meta: Box::new(ReplayEngineMeta { tps: 480.0 }),
+                actions: actions(x_vel),
+                format: version.format(),
+                game_version: GameVersion::new(22, 0),
+                frame_counting: FrameCountingMethod::CurrentProgress,
+            });
+
+            let mut buf = Vec::new();
+            replay.write(&mut buf).unwrap();
+            assert_eq!(ReplayEngineVersion::detect(&buf), version);
+
+            let read = ReplayEngineReplay::read(&mut Cursor::new(buf)).unwrap();
+            assert_eq!(read.version(), version);
+
+            let read = read.into_replay();
+            assert!(
+                ReplayEngineMeta::from_fields(read.meta.fields())
+                    .tps
+                    .eq(&480.0)
+            );
+            assert_eq!(read.actions, actions(x_vel));
+        }
+    }
+
+    #[test]
+    fn test_replayengine_v1_rejects_platformer_inputs() {
+        let replay = ReplayEngineReplay::new(Replay {
+            meta: Box::new(ReplayEngineMeta { tps: 240.0 }),
+            actions: vec![TimedAction {
+                time: TimePoint::Frame(1),
+                action: Action::Player {
+                    button: PlayerButton::Left,
+                    hold: true,
+                    player2: false,
+                },
+                position: None,
+            }],
+            format: Format::ReplayEngineV1,
+            game_version: GameVersion::new(22, 0),
+            frame_counting: FrameCountingMethod::CurrentProgress,
+        });
+
+        assert!(matches!(
+            replay.write(&mut Vec::new()),
+            Err(ReplayError::WriteError(_))
+        ));
+    }
+
+    #[test]
+    fn test_replayengine_rejects_unstorable_positions() {
+        let positioned = |frame, player1, player2| TimedAction {
+            time: TimePoint::Frame(frame),
+            action: Action::Empty,
+            position: Some(Position { player1, player2 }),
+        };
+        let write = |version: ReplayEngineVersion, actions| {
+            let replay = Replay {
+                meta: Box::new(ReplayEngineMeta { tps: 240.0 }),
+                actions,
+                format: version.format(),
+                game_version: GameVersion::new(22, 0),
+                frame_counting: FrameCountingMethod::CurrentProgress,
+            };
+            ReplayEngineReplay::write_as(&replay, version.format(), &mut Vec::new())
+        };
+
+        let one_player = vec![positioned(4, Some(state(10.0, 105.0, true)), None)];
+        assert!(write(ReplayEngineVersion::V2, one_player.clone()).is_ok());
+        assert!(matches!(
+            write(ReplayEngineVersion::V3, one_player),
+            Err(ReplayError::WriteError(_))
+        ));
+
+        let conflicting = vec![
+            positioned(4, Some(state(10.0, 105.0, false)), None),
+            positioned(4, Some(state(11.0, 105.0, false)), None),
+        ];
+        assert!(matches!(
+            write(ReplayEngineVersion::V2, conflicting),
+            Err(ReplayError::WriteError(_))
+        ));
+    }
+}
@@ -0,0 +1,199 @@
+//! Rush replays.
+//!
+//! ```text
+//! fps:i16 { frame:i32 state:u8 }*
+//! ```
+//!
+//! States are bit 0 (hold) and bit 1 (player 2).
+
+use std::io::{Read, Seek, Write};
+
+use siliconv_core::{
+    action::{Action, FrameCountingMethod, PlayerButton, TimePoint, TimedAction},
+    error::ReplayError,
+    format::{Confidence, Format},
+    meta::Meta,
+    replay::{Replay, ReplaySerializable},
+    version::GameVersion,
+};
+use siliconv_macros::Meta;
+
+use crate::{
+    binary::{self, ReadBytes, WriteBytes},
+    detect,
+};
+
+#[derive(Meta)]
+pub struct RushMeta {
+    #[meta(default = 60.0)]
+    pub tps: f64,
+}
+
+/// Sniffs a .rsh file. Rush replays have no header, so only the layout can be checked.
+#[must_use]
+pub fn sniff(data: &[u8]) -> Option<(Format, Confidence)> {
+    let fps = i16::from_le_bytes(data.get(..2)?.try_into().ok()?);
+    let records = &data[2..];
+
+    (detect::plausible_fps(f64::from(fps))
+        && records.len().is_multiple_of(5)
+        && records.chunks_exact(5).all(|record| record[4] <= 3))
+    .then_some((Format::Rush, Confidence::Low))
+}
+
+pub struct RushReplay {
+    inner: Replay,
+}
+
+impl ReplaySerializable for RushReplay {
+    fn new(replay: Replay) -> Self {
+        RushReplay { inner: replay }
+    }
+
+    fn into_replay(self) -> Replay {
+        self.inner
+    }
+
+    fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, ReplayError>
+    where
+        Self: Sized,
+    {
+        let fps = reader.read_i16()?;
+
+        let remaining = binary::remaining(reader)?;
+        if remaining % 5 != 0 {
+            return Err(ReplayError::ReadError(
+                "rush replay has a truncated action".to_string(),
+            ));
+        }
+
+        let actions = (0..remaining / 5)
+            .map(|_| {
+                let frame = reader.read_i32()?;
+                let state = reader.read_u8()?;
+
+                Ok(TimedAction {
+                    time: TimePoint::Frame(u64::try_from(frame).map_err(|_| {
+                        ReplayError::ReadError(format!("invalid rush frame {frame}"))
+                    })?),
+                    action: Action::Player {
+                        button: PlayerButton::Jump,
+                        hold: state & 1 != 0,
+                        player2: state & 2 != 0,
+                    },
+                    position: None,
+                })
+            })
+            .collect::<Result<_, ReplayError>>()?;
+
+        Ok(RushReplay {
+            inner: Replay {
+                meta: Box::new(RushMeta {
+                    tps: f64::from(fps),
+                }),
+                actions,
+                format: Format::Rush,
+                game_version: GameVersion::new(21, 130),
+                frame_counting: FrameCountingMethod::LevelTime,
+            },
+        })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+        Self::write_as(&self.inner, Format::Rush, writer)
+    }
+
+    fn write_as<W: Write>(
+        replay: &Replay,
+        _format: Format,
+        writer: &mut W,
+    ) -> Result<(), ReplayError>
+    where
+        Self: Sized,
+    {
+        let tps = RushMeta::from_fields(replay.meta.fields()).tps;
+
+        #[allow(clippy::cast_possible_truncation)]
+        let fps = tps.round() as i16;
+        writer.write_i16(fps)?;
+
+        for timed in &replay.actions {
+            let (hold, player2) = match timed.action {
+                Action::Empty => continue,
+                Action::Player {
+                    button: PlayerButton::Jump,
+                    hold,
+                    player2,
+                } => (hold, player2),
+                _ => {
+                    return Err(ReplayError::WriteError(format!(
+                        "rush does not support {:?} actions",
+                        timed.action
+                    )));
+                }
+            };
+
+            let TimePoint::Frame(frame) = timed.time else {
+                return Err(ReplayError::WriteError(
+                    "rush only supports frame time points".to_string(),
+                ));
+            };
+
+            writer.write_i32(i32::try_from(frame).map_err(|_| {
+                ReplayError::WriteError(format!("frame {frame} is too large for rush"))
+            })?)?;
+            writer.write_u8(u8::from(hold) | u8::from(player2) << 1)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_rush_roundtrip() {
+        let actions = vec![
+            TimedAction {
+                time: TimePoint::Frame(40),
+                action: Action::Player {
+                    button: PlayerButton::Jump,
+                    hold: true,
+                    player2: false,
+                },
+                position: None,
+            },
+            TimedAction {
+                time: TimePoint::Frame(52),
+                action: Action::Player {
+                    button: PlayerButton::Jump,
+                    hold: false,
+                    player2: true,
+                },
+                position: None,
+            },
+        ];
+
+        let replay = RushReplay::new(Replay {
+            meta: Box::new(RushMeta { tps: 240.0 }),
+            actions: actions.clone(),
+            format: Format::Rush,
+            game_version: GameVersion::new(21, 130),
+            frame_counting: FrameCountingMethod::LevelTime,
+        });
+
+        let mut buf = Vec::new();
+        replay.write(&mut buf).unwrap();
+        assert_eq!(buf.len(), 2 + 2 * 5);
+
+        let read = RushReplay::read(&mut Cursor::new(buf))
+            .unwrap()
+            .into_replay();
+        assert!(RushMeta::from_fields(read.meta.fields()).tps.eq(&240.0));
+        assert_eq!(read.actions, actions);
+    }
+}
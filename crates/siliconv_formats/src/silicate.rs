@@ -1,15 +1,21 @@
 use std::io::{Read, Seek, Write};
 
 use siliconv_core::{
-    action::{Action, PlayerButton, RestartType, TimePoint, TimedAction},
+    action::{Action, FrameCountingMethod, PlayerButton, RestartType, TimePoint, TimedAction},
     error::ReplayError,
-    format::Format,
+    format::{Confidence, Format},
+    meta::Meta,
     replay::{Replay, ReplaySerializable},
     version::GameVersion,
 };
 use siliconv_macros::Meta;
 use slc_oxide::{self as slc, v3::ActionType};
 
+use crate::{
+    binary::{self, ReadBytes, WriteBytes},
+    detect,
+};
+
 #[derive(Meta)]
 pub struct SilicateMeta {
     #[meta(default = 240.0)]
@@ -17,23 +23,245 @@ pub struct SilicateMeta {
     pub seed: u64,
 }
 
+/// A Silicate replay format version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SilicateVersion {
+    /// Silicate v1. Headerless, player inputs only.
+    V1,
+    /// Silicate v2. Blob-packed inputs behind a `SILL` header.
+    V2,
+    /// Silicate v3. Atom-based, behind a `SLC3RPLY` header.
+    V3,
+}
+
+impl SilicateVersion {
+    /// The replay format corresponding to this version.
+    #[must_use]
+    pub const fn format(self) -> Format {
+        match self {
+            Self::V1 => Format::Slc1,
+            Self::V2 => Format::Slc2,
+            Self::V3 => Format::Slc3,
+        }
+    }
+
+    /// The Silicate version of a replay format, if it is a Silicate format.
+    #[must_use]
+    pub const fn from_format(format: Format) -> Option<Self> {
+        match format {
+            Format::Slc1 => Some(Self::V1),
+            Format::Slc2 => Some(Self::V2),
+            Format::Slc3 => Some(Self::V3),
+            _ => None,
+        }
+    }
+
+    /// Picks the version from the first bytes of a file.
+    ///
+    /// v1 replays have no header, so anything unrecognized is treated as v1.
+    #[must_use]
+    pub fn detect(header: &[u8]) -> Self {
+        if header.starts_with(&slc::replay::V3_HEADER) {
+            Self::V3
+        } else if header.starts_with(&slc::replay::V2_HEADER) {
+            Self::V2
+        } else {
+            Self::V1
+        }
+    }
+}
+
+/// Seed metadata used by Silicate v2 replays.
+struct SeedMeta(u64);
+
+impl slc::Meta for SeedMeta {
+    fn size() -> u64 {
+        8
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(&bytes[..8]);
+        SeedMeta(u64::from_le_bytes(buf))
+    }
+
+    fn to_bytes(&self) -> Box<[u8]> {
+        Box::new(self.0.to_le_bytes())
+    }
+}
+
+/// v1 inputs are packed into a `u32` as `frame << 4 | player2 << 3 | button << 1 | hold`.
+const V1_FRAME_SHIFT: u32 = 4;
+
+/// Build number written to slc3 metadata. It is ignored when reading, so the first build is
+/// written rather than pretending to be a particular Silicate release.
+const V3_BUILD: u32 = 1;
+
+/// Sniffs a Silicate replay. v1 has no header, so it is checked by its input count.
+#[must_use]
+pub fn sniff(data: &[u8]) -> Option<(Format, Confidence)> {
+    let version = SilicateVersion::detect(data);
+    if version != SilicateVersion::V1 {
+        return Some((version.format(), Confidence::High));
+    }
+
+    let tps = f64::from_le_bytes(data.get(..8)?.try_into().ok()?);
+    let count = usize::try_from(detect::u32_at(data, 8)?).ok()?;
+
+    (detect::plausible_fps(tps) && count.checked_mul(4)?.checked_add(12)? == data.len())
+        .then_some((Format::Slc1, Confidence::Medium))
+}
+
 pub struct SilicateReplay {
     inner: Replay,
+    version: SilicateVersion,
 }
 
-impl ReplaySerializable for SilicateReplay {
-    fn new(replay: Replay) -> Self {
-        SilicateReplay { inner: replay }
+impl SilicateReplay {
+    /// Set the Silicate version to write.
+    #[must_use]
+    pub fn with_version(mut self, version: SilicateVersion) -> Self {
+        self.version = version;
+        self
     }
 
-    fn into_replay(self) -> Replay {
-        self.inner
+    /// The Silicate version this replay will be written as.
+    #[must_use]
+    pub fn version(&self) -> SilicateVersion {
+        self.version
     }
 
-    fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, ReplayError>
-    where
-        Self: Sized,
-    {
+    /// Iterate over the frames of all actions, ensuring they're sorted.
+    fn frames(replay: &Replay) -> impl Iterator<Item = Result<(u64, &Action), ReplayError>> {
+        let mut previous_frame = 0;
+        replay.actions.iter().map(move |timed| {
+            let TimePoint::Frame(frame) = timed.time else {
+                return Err(ReplayError::WriteError(
+                    "silicate only supports frame time points".to_string(),
+                ));
+            };
+
+            if frame < previous_frame {
+                return Err(ReplayError::WriteError(format!(
+                    "silicate actions must be sorted by frame (frame {frame} follows {previous_frame})"
+                )));
+            }
+            previous_frame = frame;
+
+            Ok((frame, &timed.action))
+        })
+    }
+
+    fn read_v1<R: Read + Seek>(
+        reader: &mut R,
+    ) -> Result<(SilicateMeta, Vec<TimedAction>), ReplayError> {
+        let tps = reader.read_f64()?;
+        let count = reader.read_u32()?;
+
+        if binary::remaining(reader)? != u64::from(count) * 4 {
+            return Err(ReplayError::ReadError(
+                "slc1 input count does not match file size".to_string(),
+            ));
+        }
+
+        let actions = (0..count)
+            .map(|_| {
+                let state = reader.read_u32()?;
+                let button = match (state >> 1) & 0b11 {
+                    1 => PlayerButton::Jump,
+                    2 => PlayerButton::Left,
+                    3 => PlayerButton::Right,
+                    _ => {
+                        return Err(ReplayError::ReadError(format!(
+                            "invalid slc1 button in input {state:#x}"
+                        )));
+                    }
+                };
+
+                Ok(TimedAction {
+                    time: TimePoint::Frame(u64::from(state >> V1_FRAME_SHIFT)),
+                    action: Action::Player {
+                        button,
+                        hold: state & 1 != 0,
+                        player2: state & 0b1000 != 0,
+                    },
+                    position: None,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok((SilicateMeta { tps, seed: 0 }, actions))
+    }
+
+    fn read_v2<R: Read + Seek>(
+        reader: &mut R,
+    ) -> Result<(SilicateMeta, Vec<TimedAction>), ReplayError> {
+        let start = reader.stream_position()?;
+        let (inputs, tps, seed) = match slc::Replay::<SeedMeta>::read(reader) {
+            Ok(replay) => (replay.inputs, replay.tps, replay.meta.0),
+            Err(slc::ReplayError::MetaSizeMismatchError) => {
+                reader.seek(std::io::SeekFrom::Start(start))?;
+                let replay = slc::Replay::<()>::read(reader).map_err(|e| {
+                    ReplayError::ReadError(format!("failed to read slc2 replay: {e}"))
+                })?;
+                (replay.inputs, replay.tps, 0)
+            }
+            Err(e) => {
+                return Err(ReplayError::ReadError(format!(
+                    "failed to read slc2 replay: {e}"
+                )));
+            }
+        };
+
+        let actions = inputs
+            .into_iter()
+            .map(|input| {
+                let action = match input.data {
+                    slc::InputData::Skip => Action::Empty,
+                    slc::InputData::Player(player) => Action::Player {
+                        button: match player.button {
+                            1 => PlayerButton::Jump,
+                            2 => PlayerButton::Left,
+                            3 => PlayerButton::Right,
+                            button => {
+                                return Err(ReplayError::ReadError(format!(
+                                    "invalid slc2 button {button} on frame {}",
+                                    input.frame
+                                )));
+                            }
+                        },
+                        hold: player.hold,
+                        player2: player.player_2,
+                    },
+                    slc::InputData::Restart => Action::Restart {
+                        restart_type: RestartType::Restart,
+                        seed: None,
+                    },
+                    slc::InputData::RestartFull => Action::Restart {
+                        restart_type: RestartType::RestartFull,
+                        seed: None,
+                    },
+                    slc::InputData::Death => Action::Restart {
+                        restart_type: RestartType::Death,
+                        seed: None,
+                    },
+                    slc::InputData::TPS(tps) => Action::TPS { tps },
+                };
+
+                Ok(TimedAction {
+                    time: TimePoint::Frame(input.frame),
+                    action,
+                    position: None,
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok((SilicateMeta { tps, seed }, actions))
+    }
+
+    fn read_v3<R: Read + Seek>(
+        reader: &mut R,
+    ) -> Result<(SilicateMeta, Vec<TimedAction>), ReplayError> {
         use slc::v3 as slc;
 
         let original = slc::Replay::read(reader)
@@ -59,6 +287,9 @@ impl ReplaySerializable for SilicateReplay {
             seed: original.metadata.seed,
         };
 
+        // Every slc3 restart has a seed, with 0 standing for none.
+        let seed = |seed: u64| (seed != 0).then_some(seed);
+
         let actions = action_atom
             .actions
             .iter()
@@ -82,15 +313,15 @@ impl ReplaySerializable for SilicateReplay {
                     },
                     ActionType::Restart => Action::Restart {
                         restart_type: RestartType::Restart,
-                        seed: Some(a.seed),
+                        seed: seed(a.seed),
                     },
                     ActionType::RestartFull => Action::Restart {
                         restart_type: RestartType::RestartFull,
-                        seed: Some(a.seed),
+                        seed: seed(a.seed),
                     },
                     ActionType::Death => Action::Restart {
                         restart_type: RestartType::Death,
-                        seed: Some(a.seed),
+                        seed: seed(a.seed),
                     },
                     ActionType::TPS => Action::TPS { tps: a.tps },
                     ActionType::Reserved => Action::Empty,
@@ -99,17 +330,451 @@ impl ReplaySerializable for SilicateReplay {
             })
             .collect();
 
+        Ok((meta, actions))
+    }
+
+    fn write_v1<W: Write>(replay: &Replay, writer: &mut W) -> Result<(), ReplayError> {
+        let meta = SilicateMeta::from_fields(replay.meta.fields());
+
+        let inputs = Self::frames(replay)
+            .filter(|frame| !matches!(frame, Ok((_, Action::Empty))))
+            .map(|frame| {
+                let (frame, action) = frame?;
+                let Action::Player {
+                    button,
+                    hold,
+                    player2,
+                } = *action
+                else {
+                    return Err(ReplayError::WriteError(
+                        "slc1 only supports player actions".to_string(),
+                    ));
+                };
+
+                let frame = u32::try_from(frame)
+                    .ok()
+                    .filter(|frame| frame.leading_zeros() >= V1_FRAME_SHIFT)
+                    .ok_or_else(|| {
+                        ReplayError::WriteError(format!("frame {frame} is too large for slc1"))
+                    })?;
+
+                let button = match button {
+                    PlayerButton::Jump => 1,
+                    PlayerButton::Left => 2,
+                    PlayerButton::Right => 3,
+                };
+
+                Ok(frame << V1_FRAME_SHIFT
+                    | u32::from(player2) << 3
+                    | button << 1
+                    | u32::from(hold))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let count = u32::try_from(inputs.len())
+            .map_err(|_| ReplayError::WriteError("too many inputs for slc1".to_string()))?;
+
+        writer.write_f64(meta.tps)?;
+        writer.write_u32(count)?;
+        for input in inputs {
+            writer.write_u32(input)?;
+        }
+
+        Ok(())
+    }
+
+    /// slc2 restarts have no seed, so [`Format::Slc2`] doesn't declare seeds and conversions
+    /// strip them beforehand. Restarts still carrying one are rejected.
+    fn write_v2<W: Write>(replay: &Replay, writer: &mut W) -> Result<(), ReplayError> {
+        let meta = SilicateMeta::from_fields(replay.meta.fields());
+        let mut output = slc::Replay::new(meta.tps, SeedMeta(meta.seed));
+
+        for frame in Self::frames(replay) {
+            let (frame, action) = frame?;
+            let data = match *action {
+                Action::Empty => continue,
+                Action::Player {
+                    button,
+                    hold,
+                    player2,
+                } => slc::InputData::Player(slc::PlayerInput {
+                    hold,
+                    player_2: player2,
+                    button: match button {
+                        PlayerButton::Jump => 1,
+                        PlayerButton::Left => 2,
+                        PlayerButton::Right => 3,
+                    },
+                }),
+                Action::Restart {
+                    seed: Some(seed), ..
+                } => {
+                    return Err(ReplayError::WriteError(format!(
+                        "slc2 does not support restart seeds (seed {seed} on frame {frame})"
+                    )));
+                }
+                Action::Restart {
+                    restart_type,
+                    seed: None,
+                } => match restart_type {
+                    RestartType::Restart => slc::InputData::Restart,
+                    RestartType::RestartFull => slc::InputData::RestartFull,
+                    RestartType::Death => slc::InputData::Death,
+                },
+                Action::TPS { tps } => slc::InputData::TPS(tps),
+                Action::Bugpoint => {
+                    return Err(ReplayError::WriteError(
+                        "slc2 does not support bugpoint actions".to_string(),
+                    ));
+                }
+            };
+
+            output.add_input(frame, data);
+        }
+
+        output
+            .write(writer)
+            .map_err(|e| ReplayError::WriteError(format!("failed to write slc2 replay: {e}")))
+    }
+
+    fn write_v3<W: Write>(replay: &Replay, writer: &mut W) -> Result<(), ReplayError> {
+        use slc::v3 as slc;
+
+        let meta = SilicateMeta::from_fields(replay.meta.fields());
+        let mut action_atom = slc::builtin::ActionAtom::new();
+
+        for frame in Self::frames(replay) {
+            let (frame, action) = frame?;
+            let result = match *action {
+                Action::Empty => continue,
+                Action::Player {
+                    button,
+                    hold,
+                    player2,
+                } => {
+                    let action_type = match button {
+                        PlayerButton::Jump => ActionType::Jump,
+                        PlayerButton::Left => ActionType::Left,
+                        PlayerButton::Right => ActionType::Right,
+                    };
+                    action_atom.add_player_action(frame, action_type, hold, player2)
+                }
+                Action::Restart { restart_type, seed } => {
+                    let action_type = match restart_type {
+                        RestartType::Restart => ActionType::Restart,
+                        RestartType::RestartFull => ActionType::RestartFull,
+                        RestartType::Death => ActionType::Death,
+                    };
+                    action_atom.add_death_action(frame, action_type, seed.unwrap_or_default())
+                }
+                Action::TPS { tps } => action_atom.add_tps_action(frame, tps),
+                Action::Bugpoint => {
+                    return Err(ReplayError::WriteError(
+                        "slc3 does not support bugpoint actions".to_string(),
+                    ));
+                }
+            };
+            result
+                .map_err(|e| ReplayError::WriteError(format!("failed to add slc3 action: {e}")))?;
+        }
+
+        let mut output = slc::Replay::new(slc::Metadata::new(meta.tps, meta.seed, V3_BUILD));
+        output.add_atom(slc::atom::AtomVariant::Action(action_atom));
+
+        output
+            .write(writer)
+            .map_err(|e| ReplayError::WriteError(format!("failed to write slc3 replay: {e}")))
+    }
+
+    /// Write a replay as the given version.
+    fn write_version<W: Write>(
+        replay: &Replay,
+        version: SilicateVersion,
+        writer: &mut W,
+    ) -> Result<(), ReplayError> {
+        match version {
+            SilicateVersion::V1 => Self::write_v1(replay, writer),
+            SilicateVersion::V2 => Self::write_v2(replay, writer),
+            SilicateVersion::V3 => Self::write_v3(replay, writer),
+        }
+    }
+}
+
+impl ReplaySerializable for SilicateReplay {
+    fn new(replay: Replay) -> Self {
+        let version = SilicateVersion::from_format(replay.format).unwrap_or(SilicateVersion::V3);
+        SilicateReplay {
+            inner: replay,
+            version,
+        }
+    }
+
+    fn into_replay(self) -> Replay {
+        self.inner
+    }
+
+    fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, ReplayError>
+    where
+        Self: Sized,
+    {
+        let version = SilicateVersion::detect(&binary::peek(reader, 8)?);
+        let (meta, actions) = match version {
+            SilicateVersion::V1 => Self::read_v1(reader)?,
+            SilicateVersion::V2 => Self::read_v2(reader)?,
+            SilicateVersion::V3 => Self::read_v3(reader)?,
+        };
+
+        let game_version = match version {
+            SilicateVersion::V1 | SilicateVersion::V2 => GameVersion::new(22, 0),
+            SilicateVersion::V3 => GameVersion::new(22, 74),
+        };
+
         Ok(SilicateReplay {
             inner: Replay {
                 meta: Box::new(meta),
                 actions,
-                format: Format::Slc3,
-                game_version: GameVersion::new(22, 74),
+                format: version.format(),
+                game_version,
+                frame_counting: FrameCountingMethod::TickCounter,
             },
+            version,
+        })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+        Self::write_version(&self.inner, self.version, writer)
+    }
+
+    fn write_as<W: Write>(
+        replay: &Replay,
+        format: Format,
+        writer: &mut W,
+    ) -> Result<(), ReplayError>
+    where
+        Self: Sized,
+    {
+        Self::write_version(
+            replay,
+            SilicateVersion::from_format(format).unwrap_or(SilicateVersion::V3),
+            writer,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use siliconv_core::convert::{self, Loss};
+
+    use super::*;
+
+    fn timed(frame: u64, action: Action) -> TimedAction {
+        TimedAction {
+            time: TimePoint::Frame(frame),
+            action,
+            position: None,
+        }
+    }
+
+    fn jump(hold: bool) -> Action {
+        Action::Player {
+            button: PlayerButton::Jump,
+            hold,
+            player2: false,
+        }
+    }
+
+    fn replay(version: SilicateVersion, actions: Vec<TimedAction>) -> SilicateReplay {
+        SilicateReplay::new(Replay {
+            meta: Box::new(SilicateMeta {
+                tps: 480.0,
+                seed: 1234,
+            }),
+            actions,
+            format: version.format(),
+            game_version: GameVersion::new(22, 74),
+            frame_counting: FrameCountingMethod::TickCounter,
         })
     }
 
-    fn write<W: Write>(&self, _writer: &mut W) -> Result<(), ReplayError> {
-        todo!()
+    fn roundtrip(replay: &SilicateReplay) -> (SilicateMeta, Replay) {
+        let mut buf = Vec::new();
+        replay.write(&mut buf).unwrap();
+
+        let read = SilicateReplay::read(&mut Cursor::new(buf)).unwrap();
+        assert_eq!(read.version(), replay.version());
+
+        let read = read.into_replay();
+        (SilicateMeta::from_fields(read.meta.fields()), read)
+    }
+
+    fn player_actions() -> Vec<TimedAction> {
+        vec![
+            timed(10, jump(true)),
+            timed(15, jump(false)),
+            timed(
+                15,
+                Action::Player {
+                    button: PlayerButton::Left,
+                    hold: true,
+                    player2: true,
+                },
+            ),
+            timed(100_000, jump(true)),
+        ]
+    }
+
+    #[test]
+    fn test_slc3_roundtrip() {
+        let mut actions = player_actions();
+        actions.insert(3, timed(300, Action::TPS { tps: 240.0 }));
+        actions.insert(
+            4,
+            timed(
+                301,
+                Action::Restart {
+                    restart_type: RestartType::RestartFull,
+                    seed: Some(42),
+                },
+            ),
+        );
+        actions.insert(
+            5,
+            timed(
+                302,
+                Action::Restart {
+                    restart_type: RestartType::Death,
+                    seed: None,
+                },
+            ),
+        );
+
+        let (meta, read) = roundtrip(&replay(SilicateVersion::V3, actions.clone()));
+
+        assert_eq!(read.actions, actions);
+        assert_eq!(read.format, Format::Slc3);
+        assert!(meta.tps.eq(&480.0));
+        assert_eq!(meta.seed, 1234);
+    }
+
+    #[test]
+    fn test_slc2_roundtrip() {
+        let mut actions = player_actions();
+        actions.insert(3, timed(300, Action::TPS { tps: 240.0 }));
+        actions.insert(
+            4,
+            timed(
+                301,
+                Action::Restart {
+                    restart_type: RestartType::Death,
+                    seed: None,
+                },
+            ),
+        );
+
+        let (meta, read) = roundtrip(&replay(SilicateVersion::V2, actions.clone()));
+
+        assert_eq!(read.actions, actions);
+        assert_eq!(read.format, Format::Slc2);
+        assert!(meta.tps.eq(&480.0));
+        assert_eq!(meta.seed, 1234);
+    }
+
+    #[test]
+    fn test_slc2_reports_dropped_seeds() {
+        let restart = |seed| {
+            timed(
+                20,
+                Action::Restart {
+                    restart_type: RestartType::Restart,
+                    seed,
+                },
+            )
+        };
+
+        let mut replay = replay(SilicateVersion::V3, vec![restart(Some(7))]).into_replay();
+        let report = convert::normalize(&mut replay, Format::Slc2);
+        assert_eq!(report.count(Loss::SeedsRemoved), 1);
+
+        let (_, read) = roundtrip(&SilicateReplay::new(replay));
+        assert_eq!(read.actions, vec![restart(None)]);
+    }
+
+    #[test]
+    fn test_slc1_roundtrip() {
+        let actions = player_actions();
+
+        let (meta, read) = roundtrip(&replay(SilicateVersion::V1, actions.clone()));
+
+        assert_eq!(read.actions, actions);
+        assert_eq!(read.format, Format::Slc1);
+        assert!(meta.tps.eq(&480.0));
+    }
+
+    #[test]
+    fn test_version_override() {
+        let replay =
+            replay(SilicateVersion::V3, player_actions()).with_version(SilicateVersion::V1);
+        let (_, read) = roundtrip(&replay);
+
+        assert_eq!(read.format, Format::Slc1);
+    }
+
+    #[test]
+    fn test_rejects_unsupported() {
+        let mut buf = Vec::new();
+
+        let xpos = replay(
+            SilicateVersion::V3,
+            vec![TimedAction {
+                time: TimePoint::XPos(12.5),
+                action: jump(true),
+                position: None,
+            }],
+        );
+        assert!(matches!(
+            xpos.write(&mut buf),
+            Err(ReplayError::WriteError(_))
+        ));
+
+        let bugpoint = replay(SilicateVersion::V3, vec![timed(5, Action::Bugpoint)]);
+        assert!(matches!(
+            bugpoint.write(&mut buf),
+            Err(ReplayError::WriteError(_))
+        ));
+
+        let unsorted = replay(
+            SilicateVersion::V2,
+            vec![timed(5, jump(true)), timed(3, jump(false))],
+        );
+        assert!(matches!(
+            unsorted.write(&mut buf),
+            Err(ReplayError::WriteError(_))
+        ));
+
+        let tps = replay(
+            SilicateVersion::V1,
+            vec![timed(5, Action::TPS { tps: 60.0 })],
+        );
+        assert!(matches!(
+            tps.write(&mut buf),
+            Err(ReplayError::WriteError(_))
+        ));
+
+        let seed = replay(
+            SilicateVersion::V2,
+            vec![timed(
+                5,
+                Action::Restart {
+                    restart_type: RestartType::Restart,
+                    seed: Some(7),
+                },
+            )],
+        );
+        assert!(matches!(
+            seed.write(&mut buf),
+            Err(ReplayError::WriteError(_))
+        ));
     }
 }
@@ -0,0 +1,377 @@
+//! ``TASBot`` replays.
+//!
+//! ``TASBot`` stores the state of both players on every frame rather than click events:
+//!
+//! ```json
+//! { "fps": 60.0, "macro": [{ "frame": 0, "player_1": { "click": 1 }, "player_2": { "click": 0 } }] }
+//! ```
+//!
+//! `click` is 1 while the player is holding and 0 otherwise.
+
+use std::io::{Read, Seek, Write};
+
+use serde::{Serialize, Serializer, ser::SerializeMap};
+use serde_json::{Value, json};
+use siliconv_core::{
+    action::{Action, FrameCountingMethod, PlayerButton, TimePoint, TimedAction},
+    error::ReplayError,
+    format::{Confidence, Format},
+    meta::Meta,
+    replay::{Replay, ReplaySerializable},
+    version::GameVersion,
+};
+use siliconv_macros::Meta;
+
+use crate::detect;
+
+#[derive(Meta)]
+pub struct TasBotMeta {
+    #[meta(default = 60.0)]
+    pub tps: f64,
+}
+
+/// Sniffs a ``TASBot`` replay by its keys.
+#[must_use]
+pub fn sniff(data: &[u8]) -> Option<(Format, Confidence)> {
+    (detect::json_contains_key(data, "macro") && detect::json_contains_key(data, PLAYER_KEYS[0]))
+        .then_some((Format::TASBot, Confidence::High))
+}
+
+pub struct TasBotReplay {
+    inner: Replay,
+}
+
+const PLAYER_KEYS: [&str; 2] = ["player_1", "player_2"];
+
+/// Largest frame written, as every frame up to the last click gets a record.
+const MAX_FRAME: u64 = 10_000_000;
+
+/// The per-frame records of a replay, serialized one at a time instead of collected first.
+struct Records<'a> {
+    edges: &'a [(u64, bool, bool)],
+    last_frame: u64,
+}
+
+impl Serialize for Records<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = [false; 2];
+        let mut edges = self.edges.iter().peekable();
+
+        serializer.collect_seq((0..=self.last_frame).map(|frame| {
+            while let Some((_, hold, player2)) = edges.next_if(|(f, ..)| *f == frame) {
+                state[usize::from(*player2)] = *hold;
+            }
+
+            json!({
+                "frame": frame,
+                "player_1": { "click": u8::from(state[0]) },
+                "player_2": { "click": u8::from(state[1]) },
+            })
+        }))
+    }
+}
+
+/// A replay document, with its records streamed.
+struct Document<'a> {
+    fps: f64,
+    records: Records<'a>,
+}
+
+impl Serialize for Document<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut document = serializer.serialize_map(Some(2))?;
+        document.serialize_entry("fps", &self.fps)?;
+        document.serialize_entry("macro", &self.records)?;
+        document.end()
+    }
+}
+
+fn jump(hold: bool, player2: bool) -> Action {
+    Action::Player {
+        button: PlayerButton::Jump,
+        hold,
+        player2,
+    }
+}
+
+impl TasBotReplay {
+    /// Collect `(frame, hold, player2)` edges from the replay actions.
+    ///
+    /// A state can't hold a press and release on the same frame, so such taps are extended to a
+    /// one frame hold. Edges of a player that still share a frame afterwards can't be stored.
+    fn edges(replay: &Replay, fps: f64) -> Result<Vec<(u64, bool, bool)>, ReplayError> {
+        let mut edges = replay
+            .actions
+            .iter()
+            .filter(|timed| !matches!(timed.action, Action::Empty))
+            .map(|timed| {
+                let Action::Player {
+                    button: PlayerButton::Jump,
+                    hold,
+                    player2,
+                } = timed.action
+                else {
+                    return Err(ReplayError::WriteError(format!(
+                        "tasbot does not support {:?} actions",
+                        timed.action
+                    )));
+                };
+
+                let frame = match timed.time {
+                    TimePoint::Frame(frame) => frame,
+                    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+                    TimePoint::Time(time) if time >= 0.0 => (time * fps).round() as u64,
+                    _ => {
+                        return Err(ReplayError::WriteError(
+                            "tasbot only supports frame and time time points".to_string(),
+                        ));
+                    }
+                };
+
+                Ok((frame, hold, player2))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        edges.sort_by_key(|(frame, ..)| *frame);
+
+        let mut pressed_on = [None; 2];
+        for (frame, hold, player2) in &mut edges {
+            let pressed_on = &mut pressed_on[usize::from(*player2)];
+            if *hold {
+                *pressed_on = Some(*frame);
+            } else if pressed_on.take() == Some(*frame) {
+                *frame = frame.saturating_add(1);
+            }
+        }
+        edges.sort_by_key(|(frame, ..)| *frame);
+
+        let mut previous = [None; 2];
+        for &(frame, hold, player2) in &edges {
+            let previous = &mut previous[usize::from(player2)];
+            if *previous == Some((frame, !hold)) {
+                return Err(ReplayError::WriteError(format!(
+                    "tasbot can't store both a press and a release of player {} on frame {frame}",
+                    if player2 { 2 } else { 1 }
+                )));
+            }
+            *previous = Some((frame, hold));
+        }
+
+        Ok(edges)
+    }
+}
+
+impl ReplaySerializable for TasBotReplay {
+    fn new(replay: Replay) -> Self {
+        TasBotReplay { inner: replay }
+    }
+
+    fn into_replay(self) -> Replay {
+        self.inner
+    }
+
+    fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, ReplayError>
+    where
+        Self: Sized,
+    {
+        let document: Value = serde_json::from_reader(reader)
+            .map_err(|e| ReplayError::ReadError(format!("failed to read tasbot replay: {e}")))?;
+
+        let fps = document["fps"].as_f64().unwrap_or(60.0);
+        let records = document["macro"].as_array().ok_or_else(|| {
+            ReplayError::ReadError("tasbot replay is missing its macro".to_string())
+        })?;
+
+        let mut state = [false; 2];
+        let mut actions = Vec::new();
+
+        for record in records {
+            let frame = record["frame"].as_u64().ok_or_else(|| {
+                ReplayError::ReadError("tasbot record is missing its frame".to_string())
+            })?;
+
+            for (player, key) in PLAYER_KEYS.iter().enumerate() {
+                let hold = record[key]["click"].as_u64().unwrap_or_default() != 0;
+                if hold != state[player] {
+                    state[player] = hold;
+                    actions.push(TimedAction {
+                        time: TimePoint::Frame(frame),
+                        action: jump(hold, player == 1),
+                        position: None,
+                    });
+                }
+            }
+        }
+
+        Ok(TasBotReplay {
+            inner: Replay {
+                meta: Box::new(TasBotMeta { tps: fps }),
+                actions,
+                format: Format::TASBot,
+                game_version: GameVersion::new(21, 130),
+                frame_counting: FrameCountingMethod::LevelTime,
+            },
+        })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+        Self::write_as(&self.inner, Format::TASBot, writer)
+    }
+
+    fn write_as<W: Write>(
+        replay: &Replay,
+        _format: Format,
+        writer: &mut W,
+    ) -> Result<(), ReplayError>
+    where
+        Self: Sized,
+    {
+        let fps = TasBotMeta::from_fields(replay.meta.fields()).tps;
+        let edges = Self::edges(replay, fps)?;
+
+        let last_frame = edges.last().map_or(0, |(frame, ..)| *frame);
+        if last_frame > MAX_FRAME {
+            return Err(ReplayError::WriteError(format!(
+                "frame {last_frame} is too large for tasbot"
+            )));
+        }
+
+        let document = Document {
+            fps,
+            records: Records {
+                edges: &edges,
+                last_frame,
+            },
+        };
+        serde_json::to_writer(writer, &document)
+            .map_err(|e| ReplayError::WriteError(format!("failed to write tasbot replay: {e}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn timed(time: TimePoint, hold: bool, player2: bool) -> TimedAction {
+        TimedAction {
+            time,
+            action: jump(hold, player2),
+            position: None,
+        }
+    }
+
+    #[test]
+    fn test_tasbot_states_to_edges() {
+        let json = br#"{
+            "fps": 120.0,
+            "macro": [
+                { "frame": 0, "player_1": { "click": 0 }, "player_2": { "click": 0 } },
+                { "frame": 1, "player_1": { "click": 1 }, "player_2": { "click": 0 } },
+                { "frame": 2, "player_1": { "click": 1 }, "player_2": { "click": 1 } },
+                { "frame": 3, "player_1": { "click": 0 }, "player_2": { "click": 1 } },
+                { "frame": 4, "player_1": { "click": 0 }, "player_2": { "click": 0 } }
+            ]
+        }"#;
+
+        let replay = TasBotReplay::read(&mut Cursor::new(json.to_vec()))
+            .unwrap()
+            .into_replay();
+
+        assert!(TasBotMeta::from_fields(replay.meta.fields()).tps.eq(&120.0));
+        assert_eq!(
+            replay.actions,
+            vec![
+                timed(TimePoint::Frame(1), true, false),
+                timed(TimePoint::Frame(2), true, true),
+                timed(TimePoint::Frame(3), false, false),
+                timed(TimePoint::Frame(4), false, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tasbot_expands_to_states() {
+        let replay = TasBotReplay::new(Replay {
+            meta: Box::new(TasBotMeta { tps: 120.0 }),
+            actions: vec![
+                timed(TimePoint::Frame(2), true, false),
+                timed(TimePoint::Time(0.05), false, false),
+            ],
+            format: Format::TASBot,
+            game_version: GameVersion::new(21, 130),
+            frame_counting: FrameCountingMethod::LevelTime,
+        });
+
+        let mut buf = Vec::new();
+        replay.write(&mut buf).unwrap();
+
+        let document: Value = serde_json::from_slice(&buf).unwrap();
+        let records = document["macro"].as_array().unwrap();
+        let clicks = records
+            .iter()
+            .map(|record| record["player_1"]["click"].as_u64().unwrap())
+            .collect::<Vec<_>>();
+
+        assert!(document["fps"].as_f64().unwrap().eq(&120.0));
+        assert_eq!(clicks, vec![0, 0, 1, 1, 1, 1, 0]);
+
+        let read = TasBotReplay::read(&mut Cursor::new(buf))
+            .unwrap()
+            .into_replay();
+        assert_eq!(
+            read.actions,
+            vec![
+                timed(TimePoint::Frame(2), true, false),
+                timed(TimePoint::Frame(6), false, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tasbot_keeps_taps_and_rejects_unstorable_edges() {
+        let mut replay = Replay {
+            meta: Box::new(TasBotMeta { tps: 60.0 }),
+            actions: vec![
+                timed(TimePoint::Frame(3), true, true),
+                timed(TimePoint::Frame(3), false, true),
+                timed(TimePoint::Frame(4), true, false),
+            ],
+            format: Format::TASBot,
+            game_version: GameVersion::new(21, 130),
+            frame_counting: FrameCountingMethod::LevelTime,
+        };
+
+        let mut buf = Vec::new();
+        TasBotReplay::write_as(&replay, Format::TASBot, &mut buf).unwrap();
+        let read = TasBotReplay::read(&mut Cursor::new(buf))
+            .unwrap()
+            .into_replay();
+        assert_eq!(
+            read.actions,
+            vec![
+                timed(TimePoint::Frame(3), true, true),
+                timed(TimePoint::Frame(4), true, false),
+                timed(TimePoint::Frame(4), false, true),
+            ]
+        );
+
+        // The release of the tap on frame 3 would land on the press on frame 4.
+        replay.actions = vec![
+            timed(TimePoint::Frame(3), true, false),
+            timed(TimePoint::Frame(3), false, false),
+            timed(TimePoint::Frame(4), true, false),
+        ];
+        assert!(matches!(
+            TasBotReplay::write_as(&replay, Format::TASBot, &mut Vec::new()),
+            Err(ReplayError::WriteError(_))
+        ));
+
+        replay.actions = vec![timed(TimePoint::Frame(u64::MAX), true, false)];
+        assert!(matches!(
+            TasBotReplay::new(replay).write(&mut Vec::new()),
+            Err(ReplayError::WriteError(_))
+        ));
+    }
+}
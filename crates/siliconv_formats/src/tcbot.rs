@@ -0,0 +1,309 @@
+//! ``TCBot`` replays.
+//!
+//! ```text
+//! "TCM" version:u8(=1) tps:f64 seed:u64 count:varint { delta:varint state:u8 [payload] }*
+//! ```
+//!
+//! `delta` is the number of frames since the previous input. The low two bits of `state` pick
+//! the input kind, the remaining bits depend on it:
+//!
+//! ```text
+//! 0 click:    bit 2 hold, bit 3 player 2, bits 4-5 button (1 jump, 2 left, 3 right)
+//! 1 restart:  bits 2-3 type (0 restart, 1 full restart, 2 death), bit 4 seed:u64 follows
+//! 2 tps:      tps:f64 follows
+//! 3 bugpoint
+//! ```
+//!
+//! The header seed is the seed the replay starts with.
+
+use std::io::{Read, Seek, Write};
+
+use siliconv_core::{
+    action::{Action, FrameCountingMethod, PlayerButton, RestartType, TimePoint, TimedAction},
+    error::ReplayError,
+    format::{Confidence, Format},
+    meta::Meta,
+    replay::{Replay, ReplaySerializable},
+    version::GameVersion,
+};
+use siliconv_macros::Meta;
+
+use crate::binary::{ReadBytes, WriteBytes};
+
+const MAGIC: &[u8; 3] = b"TCM";
+const VERSION: u8 = 1;
+
+const KIND_CLICK: u8 = 0;
+const KIND_RESTART: u8 = 1;
+const KIND_TPS: u8 = 2;
+const KIND_BUGPOINT: u8 = 3;
+
+#[derive(Meta)]
+pub struct TcBotMeta {
+    #[meta(default = 240.0)]
+    pub tps: f64,
+    pub seed: u64,
+}
+
+/// Sniffs a ``TCBot`` replay by its header.
+#[must_use]
+pub fn sniff(data: &[u8]) -> Option<(Format, Confidence)> {
+    (data.starts_with(MAGIC) && data.get(MAGIC.len()) == Some(&VERSION))
+        .then_some((Format::TCBot, Confidence::High))
+}
+
+pub struct TcBotReplay {
+    inner: Replay,
+}
+
+fn read_action<R: Read>(reader: &mut R, state: u8) -> Result<Action, ReplayError> {
+    Ok(match state & 3 {
+        KIND_CLICK => Action::Player {
+            button: match state >> 4 & 3 {
+                1 => PlayerButton::Jump,
+                2 => PlayerButton::Left,
+                3 => PlayerButton::Right,
+                _ => {
+                    return Err(ReplayError::ReadError(format!(
+                        "invalid tcbot input state {state:#04x}"
+                    )));
+                }
+            },
+            hold: state & 4 != 0,
+            player2: state & 8 != 0,
+        },
+        KIND_RESTART => Action::Restart {
+            restart_type: match state >> 2 & 3 {
+                0 => RestartType::Restart,
+                1 => RestartType::RestartFull,
+                2 => RestartType::Death,
+                _ => {
+                    return Err(ReplayError::ReadError(format!(
+                        "invalid tcbot input state {state:#04x}"
+                    )));
+                }
+            },
+            seed: if state & 16 != 0 {
+                Some(reader.read_u64()?)
+            } else {
+                None
+            },
+        },
+        KIND_TPS => Action::TPS {
+            tps: reader.read_f64()?,
+        },
+        _ => Action::Bugpoint,
+    })
+}
+
+fn write_action<W: Write>(writer: &mut W, action: &Action) -> Result<(), ReplayError> {
+    match *action {
+        // Empty actions are left out of the count, so they can't be written here.
+        Action::Empty => {
+            return Err(ReplayError::WriteError(
+                "tcbot does not store empty actions".to_string(),
+            ));
+        }
+        Action::Player {
+            button,
+            hold,
+            player2,
+        } => {
+            let button = match button {
+                PlayerButton::Jump => 1,
+                PlayerButton::Left => 2,
+                PlayerButton::Right => 3,
+            };
+            writer.write_u8(
+                KIND_CLICK | u8::from(hold) << 2 | u8::from(player2) << 3 | button << 4,
+            )?;
+        }
+        Action::Restart { restart_type, seed } => {
+            let restart_type = match restart_type {
+                RestartType::Restart => 0,
+                RestartType::RestartFull => 1,
+                RestartType::Death => 2,
+            };
+            writer.write_u8(KIND_RESTART | restart_type << 2 | u8::from(seed.is_some()) << 4)?;
+            if let Some(seed) = seed {
+                writer.write_u64(seed)?;
+            }
+        }
+        Action::TPS { tps } => {
+            writer.write_u8(KIND_TPS)?;
+            writer.write_f64(tps)?;
+        }
+        Action::Bugpoint => writer.write_u8(KIND_BUGPOINT)?,
+    }
+
+    Ok(())
+}
+
+impl ReplaySerializable for TcBotReplay {
+    fn new(replay: Replay) -> Self {
+        TcBotReplay { inner: replay }
+    }
+
+    fn into_replay(self) -> Replay {
+        self.inner
+    }
+
+    fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, ReplayError>
+    where
+        Self: Sized,
+    {
+        let mut magic = [0u8; 3];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(ReplayError::ReadError("missing tcbot header".to_string()));
+        }
+
+        let version = reader.read_u8()?;
+        if version != VERSION {
+            return Err(ReplayError::ReadError(format!(
+                "unsupported tcbot version {version}"
+            )));
+        }
+
+        let tps = reader.read_f64()?;
+        let seed = reader.read_u64()?;
+        let count = reader.read_varint()?;
+
+        let mut frame = 0u64;
+        let actions = (0..count)
+            .map(|_| {
+                frame = frame
+                    .checked_add(reader.read_varint()?)
+                    .ok_or_else(|| ReplayError::ReadError("tcbot frame overflow".to_string()))?;
+                let state = reader.read_u8()?;
+
+                Ok(TimedAction {
+                    time: TimePoint::Frame(frame),
+                    action: read_action(reader, state)?,
+                    position: None,
+                })
+            })
+            .collect::<Result<_, ReplayError>>()?;
+
+        Ok(TcBotReplay {
+            inner: Replay {
+                meta: Box::new(TcBotMeta { tps, seed }),
+                actions,
+                format: Format::TCBot,
+                game_version: GameVersion::new(22, 74),
+                frame_counting: FrameCountingMethod::TickCounter,
+            },
+        })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+        Self::write_as(&self.inner, Format::TCBot, writer)
+    }
+
+    fn write_as<W: Write>(
+        replay: &Replay,
+        _format: Format,
+        writer: &mut W,
+    ) -> Result<(), ReplayError>
+    where
+        Self: Sized,
+    {
+        let meta = TcBotMeta::from_fields(replay.meta.fields());
+
+        let actions = replay
+            .actions
+            .iter()
+            .filter(|timed| !matches!(timed.action, Action::Empty))
+            .map(|timed| match timed.time {
+                TimePoint::Frame(frame) => Ok((frame, &timed.action)),
+                _ => Err(ReplayError::WriteError(
+                    "tcbot only supports frame time points".to_string(),
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if !actions.is_sorted_by_key(|(frame, _)| *frame) {
+            return Err(ReplayError::WriteError(
+                "tcbot actions must be sorted by frame".to_string(),
+            ));
+        }
+
+        writer.write_all(MAGIC)?;
+        writer.write_u8(VERSION)?;
+        writer.write_f64(meta.tps)?;
+        writer.write_u64(meta.seed)?;
+        writer.write_varint(actions.len() as u64)?;
+
+        let mut previous = 0;
+        for (frame, action) in actions {
+            writer.write_varint(frame - previous)?;
+            write_action(writer, action)?;
+            previous = frame;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_tcbot_roundtrip() {
+        let actions = [
+            Action::Player {
+                button: PlayerButton::Jump,
+                hold: true,
+                player2: false,
+            },
+            Action::Player {
+                button: PlayerButton::Left,
+                hold: false,
+                player2: true,
+            },
+            Action::TPS { tps: 480.0 },
+            Action::Restart {
+                restart_type: RestartType::Death,
+                seed: None,
+            },
+            Action::Restart {
+                restart_type: RestartType::RestartFull,
+                seed: Some(0xDEAD_BEEF_CAFE),
+            },
+            Action::Bugpoint,
+        ]
+        .into_iter()
+        .zip([3, 3, 200, 1000, 1000, 1200])
+        .map(|(action, frame)| TimedAction {
+            time: TimePoint::Frame(frame),
+            action,
+            position: None,
+        })
+        .collect::<Vec<_>>();
+
+        let replay = TcBotReplay::new(Replay {
+            meta: Box::new(TcBotMeta {
+                tps: 240.0,
+                seed: 1337,
+            }),
+            actions: actions.clone(),
+            format: Format::TCBot,
+            game_version: GameVersion::new(22, 74),
+            frame_counting: FrameCountingMethod::TickCounter,
+        });
+
+        let mut buf = Vec::new();
+        replay.write(&mut buf).unwrap();
+
+        let read = TcBotReplay::read(&mut Cursor::new(buf))
+            .unwrap()
+            .into_replay();
+        let meta = TcBotMeta::from_fields(read.meta.fields());
+        assert!(meta.tps.eq(&240.0));
+        assert_eq!(meta.seed, 1337);
+        assert_eq!(read.actions, actions);
+    }
+}
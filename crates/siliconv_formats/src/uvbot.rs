@@ -0,0 +1,226 @@
+//! ``UVBot`` replays.
+//!
+//! ```text
+//! "UVBOT" version:u8(=1) fps:f32 count:u32 { frame:u32 button:u8 flags:u8 }*
+//! ```
+//!
+//! Buttons are 1 (jump), 2 (left) and 3 (right). Flags are bit 0 (hold) and bit 1 (player 2).
+
+use std::io::{Read, Seek, Write};
+
+use siliconv_core::{
+    action::{Action, FrameCountingMethod, PlayerButton, TimePoint, TimedAction},
+    error::ReplayError,
+    format::{Confidence, Format},
+    meta::Meta,
+    replay::{Replay, ReplaySerializable},
+    version::GameVersion,
+};
+use siliconv_macros::Meta;
+
+use crate::binary::{ReadBytes, WriteBytes};
+
+const MAGIC: &[u8; 5] = b"UVBOT";
+const VERSION: u8 = 1;
+
+#[derive(Meta)]
+pub struct UvBotMeta {
+    #[meta(default = 240.0)]
+    pub tps: f64,
+}
+
+/// Sniffs a ``UVBot`` replay by its header.
+#[must_use]
+pub fn sniff(data: &[u8]) -> Option<(Format, Confidence)> {
+    data.starts_with(MAGIC)
+        .then_some((Format::UVBot, Confidence::High))
+}
+
+pub struct UvBotReplay {
+    inner: Replay,
+}
+
+impl ReplaySerializable for UvBotReplay {
+    fn new(replay: Replay) -> Self {
+        UvBotReplay { inner: replay }
+    }
+
+    fn into_replay(self) -> Replay {
+        self.inner
+    }
+
+    fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, ReplayError>
+    where
+        Self: Sized,
+    {
+        let mut magic = [0u8; 5];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(ReplayError::ReadError("missing uvbot header".to_string()));
+        }
+
+        let version = reader.read_u8()?;
+        if version != VERSION {
+            return Err(ReplayError::ReadError(format!(
+                "unsupported uvbot version {version}"
+            )));
+        }
+
+        let fps = reader.read_f32()?;
+        let count = reader.read_u32()?;
+
+        let actions = (0..count)
+            .map(|_| {
+                let frame = reader.read_u32()?;
+                let button = match reader.read_u8()? {
+                    1 => PlayerButton::Jump,
+                    2 => PlayerButton::Left,
+                    3 => PlayerButton::Right,
+                    button => {
+                        return Err(ReplayError::ReadError(format!(
+                            "invalid uvbot button {button}"
+                        )));
+                    }
+                };
+                let flags = reader.read_u8()?;
+
+                Ok(TimedAction {
+                    time: TimePoint::Frame(u64::from(frame)),
+                    action: Action::Player {
+                        button,
+                        hold: flags & 1 != 0,
+                        player2: flags & 2 != 0,
+                    },
+                    position: None,
+                })
+            })
+            .collect::<Result<_, ReplayError>>()?;
+
+        Ok(UvBotReplay {
+            inner: Replay {
+                meta: Box::new(UvBotMeta {
+                    tps: f64::from(fps),
+                }),
+                actions,
+                format: Format::UVBot,
+                game_version: GameVersion::new(22, 0),
+                frame_counting: FrameCountingMethod::TickCounter,
+            },
+        })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+        Self::write_as(&self.inner, Format::UVBot, writer)
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_as<W: Write>(
+        replay: &Replay,
+        _format: Format,
+        writer: &mut W,
+    ) -> Result<(), ReplayError>
+    where
+        Self: Sized,
+    {
+        let tps = UvBotMeta::from_fields(replay.meta.fields()).tps;
+
+        let actions = replay
+            .actions
+            .iter()
+            .filter(|timed| !matches!(timed.action, Action::Empty))
+            .map(|timed| {
+                let Action::Player {
+                    button,
+                    hold,
+                    player2,
+                } = timed.action
+                else {
+                    return Err(ReplayError::WriteError(format!(
+                        "uvbot does not support {:?} actions",
+                        timed.action
+                    )));
+                };
+
+                let TimePoint::Frame(frame) = timed.time else {
+                    return Err(ReplayError::WriteError(
+                        "uvbot only supports frame time points".to_string(),
+                    ));
+                };
+                let frame = u32::try_from(frame).map_err(|_| {
+                    ReplayError::WriteError(format!("frame {frame} is too large for uvbot"))
+                })?;
+
+                let button = match button {
+                    PlayerButton::Jump => 1,
+                    PlayerButton::Left => 2,
+                    PlayerButton::Right => 3,
+                };
+
+                Ok((frame, button, u8::from(hold) | u8::from(player2) << 1))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        writer.write_all(MAGIC)?;
+        writer.write_u8(VERSION)?;
+        writer.write_f32(tps as f32)?;
+        writer.write_u32(
+            u32::try_from(actions.len())
+                .map_err(|_| ReplayError::WriteError("too many actions".to_string()))?,
+        )?;
+        for (frame, button, flags) in actions {
+            writer.write_u32(frame)?;
+            writer.write_u8(button)?;
+            writer.write_u8(flags)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_uvbot_roundtrip() {
+        let actions = vec![
+            TimedAction {
+                time: TimePoint::Frame(30),
+                action: Action::Player {
+                    button: PlayerButton::Right,
+                    hold: true,
+                    player2: false,
+                },
+                position: None,
+            },
+            TimedAction {
+                time: TimePoint::Frame(31),
+                action: Action::Player {
+                    button: PlayerButton::Jump,
+                    hold: true,
+                    player2: true,
+                },
+                position: None,
+            },
+        ];
+
+        let replay = UvBotReplay::new(Replay {
+            meta: Box::new(UvBotMeta { tps: 240.0 }),
+            actions: actions.clone(),
+            format: Format::UVBot,
+            game_version: GameVersion::new(22, 0),
+            frame_counting: FrameCountingMethod::TickCounter,
+        });
+
+        let mut buf = Vec::new();
+        replay.write(&mut buf).unwrap();
+
+        let read = UvBotReplay::read(&mut Cursor::new(buf))
+            .unwrap()
+            .into_replay();
+        assert!(UvBotMeta::from_fields(read.meta.fields()).tps.eq(&240.0));
+        assert_eq!(read.actions, actions);
+    }
+}
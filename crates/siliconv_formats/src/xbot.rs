@@ -0,0 +1,259 @@
+//! xBot replays.
+//!
+//! xBot replays are text files with an fps line, a mode line and one action per line:
+//!
+//! ```text
+//! fps: 240
+//! frames
+//! 1 120
+//! 0 134
+//! ```
+//!
+//! The mode is either `frames` or `pos`. In `pos` mode, values are the raw bits of an `f32`
+//! x position. States are 0 (player 1 release), 1 (player 1 press), 2 (player 2 release)
+//! and 3 (player 2 press).
+
+use std::io::{BufRead, BufReader, Read, Seek, Write};
+
+use siliconv_core::{
+    action::{Action, FrameCountingMethod, PlayerButton, TimePoint, TimedAction},
+    error::ReplayError,
+    format::{Confidence, Format},
+    meta::Meta,
+    replay::{Replay, ReplaySerializable},
+    version::GameVersion,
+};
+use siliconv_macros::Meta;
+
+#[derive(Meta)]
+pub struct XBotMeta {
+    #[meta(default = 60.0)]
+    pub tps: f64,
+}
+
+/// Sniffs an xBot replay by its fps and mode lines.
+#[must_use]
+pub fn sniff(data: &[u8]) -> Option<(Format, Confidence)> {
+    let mut lines = data.split(|b| *b == b'\n').map(<[u8]>::trim_ascii);
+    let fps = lines.next()?;
+    let mode = lines.next()?;
+
+    (fps.starts_with(b"fps:") && matches!(mode, b"pos" | b"frames"))
+        .then_some((Format::XBot, Confidence::High))
+}
+
+pub struct XBotReplay {
+    inner: Replay,
+}
+
+fn invalid_line(line: &str) -> ReplayError {
+    ReplayError::ReadError(format!("invalid xbot line {line:?}"))
+}
+
+impl ReplaySerializable for XBotReplay {
+    fn new(replay: Replay) -> Self {
+        XBotReplay { inner: replay }
+    }
+
+    fn into_replay(self) -> Replay {
+        self.inner
+    }
+
+    fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, ReplayError>
+    where
+        Self: Sized,
+    {
+        let mut lines = BufReader::new(reader).lines();
+        let mut next_line = || {
+            lines
+                .next()
+                .transpose()
+                .map_err(ReplayError::from)?
+                .ok_or_else(|| ReplayError::ReadError("xbot replay is truncated".to_string()))
+        };
+
+        let line = next_line()?;
+        let fps = line
+            .trim()
+            .strip_prefix("fps:")
+            .and_then(|fps| fps.trim().parse::<f64>().ok())
+            .ok_or_else(|| invalid_line(&line))?;
+
+        let line = next_line()?;
+        let xpos = match line.trim() {
+            "pos" => true,
+            "frames" => false,
+            _ => return Err(invalid_line(&line)),
+        };
+
+        let mut actions = Vec::new();
+        for line in lines {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let (state, value) = line
+                .trim()
+                .split_once(' ')
+                .and_then(|(state, value)| {
+                    Some((state.parse::<u8>().ok()?, value.trim().parse::<u32>().ok()?))
+                })
+                .filter(|(state, _)| *state <= 3)
+                .ok_or_else(|| invalid_line(&line))?;
+
+            actions.push(TimedAction {
+                time: if xpos {
+                    TimePoint::XPos(f64::from(f32::from_bits(value)))
+                } else {
+                    TimePoint::Frame(u64::from(value))
+                },
+                action: Action::Player {
+                    button: PlayerButton::Jump,
+                    hold: state & 1 != 0,
+                    player2: state & 2 != 0,
+                },
+                position: None,
+            });
+        }
+
+        Ok(XBotReplay {
+            inner: Replay {
+                meta: Box::new(XBotMeta { tps: fps }),
+                actions,
+                format: Format::XBot,
+                game_version: GameVersion::new(21, 130),
+                frame_counting: FrameCountingMethod::LevelTime,
+            },
+        })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+        Self::write_as(&self.inner, Format::XBot, writer)
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_as<W: Write>(
+        replay: &Replay,
+        _format: Format,
+        writer: &mut W,
+    ) -> Result<(), ReplayError>
+    where
+        Self: Sized,
+    {
+        let tps = XBotMeta::from_fields(replay.meta.fields()).tps;
+
+        let actions = replay
+            .actions
+            .iter()
+            .filter(|timed| !matches!(timed.action, Action::Empty))
+            .collect::<Vec<_>>();
+        let xpos = actions
+            .first()
+            .is_some_and(|timed| matches!(timed.time, TimePoint::XPos(_)));
+
+        writeln!(writer, "fps: {}", tps.round())?;
+        writeln!(writer, "{}", if xpos { "pos" } else { "frames" })?;
+
+        for timed in actions {
+            let Action::Player {
+                button: PlayerButton::Jump,
+                hold,
+                player2,
+            } = timed.action
+            else {
+                return Err(ReplayError::WriteError(format!(
+                    "xbot does not support {:?} actions",
+                    timed.action
+                )));
+            };
+
+            let value = match timed.time {
+                TimePoint::XPos(x) if xpos => (x as f32).to_bits(),
+                TimePoint::Frame(frame) if !xpos => u32::try_from(frame).map_err(|_| {
+                    ReplayError::WriteError(format!("frame {frame} is too large for xbot"))
+                })?,
+                _ => {
+                    return Err(ReplayError::WriteError(
+                        "xbot replays must use only x position or only frame time points"
+                            .to_string(),
+                    ));
+                }
+            };
+
+            writeln!(
+                writer,
+                "{} {value}",
+                u8::from(hold) | u8::from(player2) << 1
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn click(time: TimePoint, hold: bool, player2: bool) -> TimedAction {
+        TimedAction {
+            time,
+            action: Action::Player {
+                button: PlayerButton::Jump,
+                hold,
+                player2,
+            },
+            position: None,
+        }
+    }
+
+    #[test]
+    fn test_xbot_reads_both_modes() {
+        let frames = XBotReplay::read(&mut Cursor::new(
+            b"fps: 240\r\nframes\r\n1 120\r\n2 134\r\n",
+        ))
+        .unwrap()
+        .into_replay();
+        assert!(XBotMeta::from_fields(frames.meta.fields()).tps.eq(&240.0));
+        assert_eq!(
+            frames.actions,
+            vec![
+                click(TimePoint::Frame(120), true, false),
+                click(TimePoint::Frame(134), false, true),
+            ]
+        );
+
+        let pos = format!("fps: 60\npos\n3 {}\n", 100.5_f32.to_bits());
+        let pos = XBotReplay::read(&mut Cursor::new(pos))
+            .unwrap()
+            .into_replay();
+        assert_eq!(pos.actions, vec![click(TimePoint::XPos(100.5), true, true)]);
+    }
+
+    #[test]
+    fn test_xbot_roundtrip() {
+        let actions = vec![
+            click(TimePoint::XPos(15.25), true, false),
+            click(TimePoint::XPos(30.0), false, false),
+        ];
+        let replay = XBotReplay::new(Replay {
+            meta: Box::new(XBotMeta { tps: 144.0 }),
+            actions: actions.clone(),
+            format: Format::XBot,
+            game_version: GameVersion::new(21, 130),
+            frame_counting: FrameCountingMethod::LevelTime,
+        });
+
+        let mut buf = Vec::new();
+        replay.write(&mut buf).unwrap();
+
+        let read = XBotReplay::read(&mut Cursor::new(buf))
+            .unwrap()
+            .into_replay();
+        assert!(XBotMeta::from_fields(read.meta.fields()).tps.eq(&144.0));
+        assert_eq!(read.actions, actions);
+    }
+}
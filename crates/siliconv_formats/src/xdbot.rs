@@ -0,0 +1,338 @@
+//! xdBot legacy replays.
+//!
+//! Legacy .xd replays are text files with an fps line followed by one action per line:
+//!
+//! ```text
+//! 240
+//! frame|hold|button|player1|posOnly[|p1x|p1y|p2x|p2y]
+//! ```
+//!
+//! Flags are 0 or 1 and buttons are 1 (jump), 2 (left) and 3 (right). Position-only lines
+//! don't click, they only correct the positions of both players on that frame.
+
+use std::io::{BufRead, BufReader, Read, Seek, Write};
+
+use siliconv_core::{
+    action::{
+        Action, FrameCountingMethod, PlayerButton, PlayerPosition, Position, TimePoint, TimedAction,
+    },
+    error::ReplayError,
+    format::{Confidence, Format},
+    meta::Meta,
+    replay::{Replay, ReplaySerializable},
+    version::GameVersion,
+};
+use siliconv_macros::Meta;
+
+use crate::detect;
+
+#[derive(Meta)]
+pub struct XdBotMeta {
+    #[meta(default = 240.0)]
+    pub tps: f64,
+}
+
+/// Sniffs a legacy .xd replay by its fps line and the shape of its first action.
+#[must_use]
+pub fn sniff(data: &[u8]) -> Option<(Format, Confidence)> {
+    let mut lines = data
+        .split(|b| *b == b'\n')
+        .map(<[u8]>::trim_ascii)
+        .filter(|line| !line.is_empty());
+
+    let fps = std::str::from_utf8(lines.next()?)
+        .ok()?
+        .parse::<f64>()
+        .ok()?;
+    if !detect::plausible_fps(fps) {
+        return None;
+    }
+
+    match lines.next() {
+        None => Some((Format::XDBot, Confidence::Low)),
+        Some(line) => matches!(line.split(|b| *b == b'|').count(), 5 | 9)
+            .then_some((Format::XDBot, Confidence::Medium)),
+    }
+}
+
+pub struct XdBotReplay {
+    inner: Replay,
+}
+
+fn invalid_line(line: &str) -> ReplayError {
+    ReplayError::ReadError(format!("invalid xdbot line {line:?}"))
+}
+
+fn player_position(x: f64, y: f64) -> PlayerPosition {
+    PlayerPosition {
+        x,
+        y,
+        rotation: None,
+        vel_x: None,
+        vel_y: None,
+    }
+}
+
+fn flag(field: &str) -> Option<bool> {
+    match field {
+        "0" => Some(false),
+        "1" => Some(true),
+        _ => None,
+    }
+}
+
+fn parse_line(line: &str) -> Option<TimedAction> {
+    let mut fields = line.split('|');
+    let frame = fields.next()?.parse::<u64>().ok()?;
+    let hold = flag(fields.next()?)?;
+    let button = match fields.next()? {
+        "1" => PlayerButton::Jump,
+        "2" => PlayerButton::Left,
+        "3" => PlayerButton::Right,
+        _ => return None,
+    };
+    let player1 = flag(fields.next()?)?;
+    let position_only = flag(fields.next()?)?;
+
+    let coordinates = fields
+        .map(|field| field.parse::<f64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let position = match coordinates[..] {
+        [] => None,
+        [p1x, p1y, p2x, p2y] => Some(Position {
+            player1: Some(player_position(p1x, p1y)),
+            player2: Some(player_position(p2x, p2y)),
+        }),
+        _ => return None,
+    };
+
+    if position_only && position.is_none() {
+        return None;
+    }
+
+    Some(TimedAction {
+        time: TimePoint::Frame(frame),
+        action: if position_only {
+            Action::Empty
+        } else {
+            Action::Player {
+                button,
+                hold,
+                player2: !player1,
+            }
+        },
+        position,
+    })
+}
+
+impl ReplaySerializable for XdBotReplay {
+    fn new(replay: Replay) -> Self {
+        XdBotReplay { inner: replay }
+    }
+
+    fn into_replay(self) -> Replay {
+        self.inner
+    }
+
+    fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, ReplayError>
+    where
+        Self: Sized,
+    {
+        let mut lines = BufReader::new(reader).lines();
+
+        let line = lines
+            .next()
+            .transpose()?
+            .ok_or_else(|| ReplayError::ReadError("xdbot replay is empty".to_string()))?;
+        let fps = line
+            .trim()
+            .parse::<f64>()
+            .map_err(|_| invalid_line(&line))?;
+
+        let mut actions = Vec::new();
+        for line in lines {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            actions.push(parse_line(line).ok_or_else(|| invalid_line(line))?);
+        }
+
+        Ok(XdBotReplay {
+            inner: Replay {
+                meta: Box::new(XdBotMeta { tps: fps }),
+                actions,
+                format: Format::XDBot,
+                game_version: GameVersion::new(22, 0),
+                frame_counting: FrameCountingMethod::CurrentProgress,
+            },
+        })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+        Self::write_as(&self.inner, Format::XDBot, writer)
+    }
+
+    fn write_as<W: Write>(
+        replay: &Replay,
+        _format: Format,
+        writer: &mut W,
+    ) -> Result<(), ReplayError>
+    where
+        Self: Sized,
+    {
+        let tps = XdBotMeta::from_fields(replay.meta.fields()).tps;
+        writeln!(writer, "{tps}")?;
+
+        for timed in &replay.actions {
+            let TimePoint::Frame(frame) = timed.time else {
+                return Err(ReplayError::WriteError(
+                    "xdbot only supports frame time points".to_string(),
+                ));
+            };
+
+            // xdBot stores the x and y of both players, or nothing.
+            let coordinates = match timed
+                .position
+                .map(|position| (position.player1, position.player2))
+            {
+                None | Some((None, None)) => None,
+                Some((Some(player1), Some(player2))) => Some((player1, player2)),
+                Some(_) => {
+                    return Err(ReplayError::WriteError(format!(
+                        "xdbot stores the positions of both players, but frame {frame} only has one"
+                    )));
+                }
+            };
+
+            let (hold, button, player2, position_only) = match timed.action {
+                Action::Empty if coordinates.is_none() => continue,
+                Action::Empty => (false, PlayerButton::Jump, false, true),
+                Action::Player {
+                    button,
+                    hold,
+                    player2,
+                } => (hold, button, player2, false),
+                _ => {
+                    return Err(ReplayError::WriteError(format!(
+                        "xdbot does not support {:?} actions",
+                        timed.action
+                    )));
+                }
+            };
+
+            let button = match button {
+                PlayerButton::Jump => 1,
+                PlayerButton::Left => 2,
+                PlayerButton::Right => 3,
+            };
+
+            write!(
+                writer,
+                "{frame}|{}|{button}|{}|{}",
+                u8::from(hold),
+                u8::from(!player2),
+                u8::from(position_only)
+            )?;
+            if let Some((player1, player2)) = coordinates {
+                write!(
+                    writer,
+                    "|{}|{}|{}|{}",
+                    player1.x, player1.y, player2.x, player2.y
+                )?;
+            }
+            writeln!(writer)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_xdbot_roundtrip() {
+        let text = "240\n\
+                    10|1|1|1|0\n\
+                    12|0|2|0|0|30.5|105|31.25|225\n\
+                    15|0|1|1|1|40|105|40|225\n";
+
+        let replay = XdBotReplay::read(&mut Cursor::new(text))
+            .unwrap()
+            .into_replay();
+        assert!(XdBotMeta::from_fields(replay.meta.fields()).tps.eq(&240.0));
+        assert_eq!(
+            replay.actions,
+            vec![
+                TimedAction {
+                    time: TimePoint::Frame(10),
+                    action: Action::Player {
+                        button: PlayerButton::Jump,
+                        hold: true,
+                        player2: false,
+                    },
+                    position: None,
+                },
+                TimedAction {
+                    time: TimePoint::Frame(12),
+                    action: Action::Player {
+                        button: PlayerButton::Left,
+                        hold: false,
+                        player2: true,
+                    },
+                    position: Some(Position {
+                        player1: Some(player_position(30.5, 105.0)),
+                        player2: Some(player_position(31.25, 225.0)),
+                    }),
+                },
+                TimedAction {
+                    time: TimePoint::Frame(15),
+                    action: Action::Empty,
+                    position: Some(Position {
+                        player1: Some(player_position(40.0, 105.0)),
+                        player2: Some(player_position(40.0, 225.0)),
+                    }),
+                },
+            ]
+        );
+
+        let mut buf = Vec::new();
+        XdBotReplay::new(replay).write(&mut buf).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), text);
+    }
+
+    #[test]
+    fn test_xdbot_rejects_one_player_positions() {
+        let replay = Replay {
+            meta: Box::new(XdBotMeta { tps: 240.0 }),
+            actions: vec![TimedAction {
+                time: TimePoint::Frame(3),
+                action: Action::Empty,
+                position: Some(Position {
+                    player1: None,
+                    player2: Some(PlayerPosition {
+                        x: 1.0,
+                        y: 2.0,
+                        rotation: None,
+                        vel_x: None,
+                        vel_y: None,
+                    }),
+                }),
+            }],
+            format: Format::XDBot,
+            game_version: GameVersion::new(22, 0),
+            frame_counting: FrameCountingMethod::CurrentProgress,
+        };
+
+        assert!(matches!(
+            XdBotReplay::write_as(&replay, Format::XDBot, &mut Vec::new()),
+            Err(ReplayError::WriteError(_))
+        ));
+    }
+}
@@ -0,0 +1,213 @@
+//! yBot 1 replays.
+//!
+//! yBot 1 saves replays without an extension, so they can only be recognized by their contents:
+//!
+//! ```text
+//! "ybot" fps:f32 count:u32 { frame:u32 state:u8 }*
+//! ```
+//!
+//! States are bit 0 (hold) and bit 1 (player 2).
+
+use std::io::{Read, Seek, Write};
+
+use siliconv_core::{
+    action::{Action, FrameCountingMethod, PlayerButton, TimePoint, TimedAction},
+    error::ReplayError,
+    format::{Confidence, Format},
+    meta::Meta,
+    replay::{Replay, ReplaySerializable},
+    version::GameVersion,
+};
+use siliconv_macros::Meta;
+
+use crate::binary::{ReadBytes, WriteBytes};
+
+const MAGIC: &[u8; 4] = b"ybot";
+
+#[derive(Meta)]
+pub struct YBotMeta {
+    #[meta(default = 60.0)]
+    pub tps: f64,
+}
+
+/// Sniffs a yBot 1 replay by its header.
+#[must_use]
+pub fn sniff(data: &[u8]) -> Option<(Format, Confidence)> {
+    YBotReplay::detect(data).then_some((Format::YBot1, Confidence::High))
+}
+
+pub struct YBotReplay {
+    inner: Replay,
+}
+
+impl YBotReplay {
+    /// Whether the first bytes of a file look like a yBot 1 replay.
+    ///
+    /// As yBot 1 replays have no extension, this is the only way to recognize them.
+    #[must_use]
+    pub fn detect(header: &[u8]) -> bool {
+        header.starts_with(MAGIC)
+    }
+}
+
+impl ReplaySerializable for YBotReplay {
+    fn new(replay: Replay) -> Self {
+        YBotReplay { inner: replay }
+    }
+
+    fn into_replay(self) -> Replay {
+        self.inner
+    }
+
+    fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, ReplayError>
+    where
+        Self: Sized,
+    {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(ReplayError::ReadError("missing ybot header".to_string()));
+        }
+
+        let fps = reader.read_f32()?;
+        let count = reader.read_u32()?;
+
+        let actions = (0..count)
+            .map(|_| {
+                let frame = reader.read_u32()?;
+                let state = reader.read_u8()?;
+
+                Ok(TimedAction {
+                    time: TimePoint::Frame(u64::from(frame)),
+                    action: Action::Player {
+                        button: PlayerButton::Jump,
+                        hold: state & 1 != 0,
+                        player2: state & 2 != 0,
+                    },
+                    position: None,
+                })
+            })
+            .collect::<Result<_, ReplayError>>()?;
+
+        Ok(YBotReplay {
+            inner: Replay {
+                meta: Box::new(YBotMeta {
+                    tps: f64::from(fps),
+                }),
+                actions,
+                format: Format::YBot1,
+                game_version: GameVersion::new(21, 130),
+                frame_counting: FrameCountingMethod::LevelTime,
+            },
+        })
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+        Self::write_as(&self.inner, Format::YBot1, writer)
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_as<W: Write>(
+        replay: &Replay,
+        _format: Format,
+        writer: &mut W,
+    ) -> Result<(), ReplayError>
+    where
+        Self: Sized,
+    {
+        let tps = YBotMeta::from_fields(replay.meta.fields()).tps;
+
+        let actions = replay
+            .actions
+            .iter()
+            .filter(|timed| !matches!(timed.action, Action::Empty))
+            .map(|timed| {
+                let Action::Player {
+                    button: PlayerButton::Jump,
+                    hold,
+                    player2,
+                } = timed.action
+                else {
+                    return Err(ReplayError::WriteError(format!(
+                        "ybot does not support {:?} actions",
+                        timed.action
+                    )));
+                };
+
+                let TimePoint::Frame(frame) = timed.time else {
+                    return Err(ReplayError::WriteError(
+                        "ybot only supports frame time points".to_string(),
+                    ));
+                };
+                let frame = u32::try_from(frame).map_err(|_| {
+                    ReplayError::WriteError(format!("frame {frame} is too large for ybot"))
+                })?;
+
+                Ok((frame, u8::from(hold) | u8::from(player2) << 1))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        writer.write_all(MAGIC)?;
+        writer.write_f32(tps as f32)?;
+        writer.write_u32(
+            u32::try_from(actions.len())
+                .map_err(|_| ReplayError::WriteError("too many actions".to_string()))?,
+        )?;
+        for (frame, state) in actions {
+            writer.write_u32(frame)?;
+            writer.write_u8(state)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_ybot_roundtrip() {
+        let actions = vec![
+            TimedAction {
+                time: TimePoint::Frame(3),
+                action: Action::Player {
+                    button: PlayerButton::Jump,
+                    hold: true,
+                    player2: true,
+                },
+                position: None,
+            },
+            TimedAction {
+                time: TimePoint::Frame(9),
+                action: Action::Player {
+                    button: PlayerButton::Jump,
+                    hold: false,
+                    player2: true,
+                },
+                position: None,
+            },
+        ];
+
+        let replay = YBotReplay::new(Replay {
+            meta: Box::new(YBotMeta { tps: 240.0 }),
+            actions: actions.clone(),
+            format: Format::YBot1,
+            game_version: GameVersion::new(21, 130),
+            frame_counting: FrameCountingMethod::LevelTime,
+        });
+
+        let mut buf = Vec::new();
+        replay.write(&mut buf).unwrap();
+        assert!(YBotReplay::detect(&buf));
+        assert!(!YBotReplay::detect(b"RPLY"));
+
+        let read = YBotReplay::read(&mut Cursor::new(buf))
+            .unwrap()
+            .into_replay();
+        assert!(YBotMeta::from_fields(read.meta.fields()).tps.eq(&240.0));
+        assert_eq!(read.actions, actions);
+    }
+}
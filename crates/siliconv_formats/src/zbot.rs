@@ -0,0 +1,419 @@
+//! zBot replays.
+//!
+//! Both variants share a header and only differ in how clicks are timed:
+//!
+//! ```text
+//! .zbot: delta:f32 speedhack:f32 { x:f32 hold:u8 player1:u8 }*      (x position)
+//! .zbf:  delta:f32 speedhack:f32 { frame:i32 hold:u8 player1:u8 }*  (frame)
+//! ```
+//!
+//! `delta` is the game's frame delta (`1 / fps`), read into the `tps` meta as well. The
+//! speedhack only scales how fast the game runs in real time, not the tick rate. Booleans are
+//! stored as the ASCII characters `'1'` and `'0'`.
+//!
+//! The two variants are told apart by the requested format, or by the click records when
+//! none is given.
+
+use std::io::{Read, Seek, Write};
+
+use siliconv_core::{
+    action::{Action, FrameCountingMethod, PlayerButton, TimePoint, TimedAction},
+    error::ReplayError,
+    format::{Confidence, Format},
+    meta::Meta,
+    replay::{Replay, ReplaySerializable},
+    version::GameVersion,
+};
+use siliconv_macros::Meta;
+
+use crate::{
+    binary::{ReadBytes, WriteBytes},
+    detect,
+};
+
+const TRUE: u8 = b'1';
+const FALSE: u8 = b'0';
+
+#[derive(Meta)]
+pub struct ZBotMeta {
+    #[meta(default = 1.0 / 60.0)]
+    pub delta: f32,
+    #[meta(default = 1.0)]
+    pub speedhack: f32,
+    /// Ticks per second, `1 / delta`.
+    #[meta(default = 60.0)]
+    pub tps: f64,
+}
+
+/// The tick rate of a frame delta. `delta` is an `f32`, so rates within rounding error of a
+/// whole number are snapped to it.
+fn delta_tps(delta: f32) -> f64 {
+    let tps = 1.0 / f64::from(delta);
+    if (tps - tps.round()).abs() < 1e-3 {
+        tps.round()
+    } else {
+        tps
+    }
+}
+
+/// How the clicks of a zBot replay are timed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZBotTiming {
+    /// .zbot, x position.
+    XPos,
+    /// .zbf, frame.
+    Frame,
+}
+
+impl ZBotTiming {
+    /// The replay format corresponding to this timing.
+    #[must_use]
+    pub const fn format(self) -> Format {
+        match self {
+            Self::XPos => Format::ZBotXpos,
+            Self::Frame => Format::ZBotFrame,
+        }
+    }
+
+    /// The zBot timing of a replay format, if it is a zBot format.
+    #[must_use]
+    pub const fn from_format(format: Format) -> Option<Self> {
+        match format {
+            Format::ZBotXpos => Some(Self::XPos),
+            Format::ZBotFrame => Some(Self::Frame),
+            _ => None,
+        }
+    }
+
+    /// Picks the timing from the click records following the header, see
+    /// [`detect::locations_are_frames`].
+    #[must_use]
+    pub fn detect(records: &[u8]) -> Self {
+        if detect::locations_are_frames(records, 6) == Some(true) {
+            Self::Frame
+        } else {
+            Self::XPos
+        }
+    }
+}
+
+/// Sniffs a zBot replay. zBot replays have no header, so this checks the layout and booleans.
+#[must_use]
+pub fn sniff(data: &[u8]) -> Option<(Format, Confidence)> {
+    let delta = detect::f32_at(data, 0)?;
+    let speedhack = detect::f32_at(data, 4)?;
+    let records = &data[8..];
+    let is_bool = |byte: u8| matches!(byte, TRUE | FALSE | 0 | 1);
+
+    (delta > 0.0
+        && delta <= 1.0
+        && speedhack > 0.0
+        && speedhack <= 100.0
+        && records.len().is_multiple_of(6)
+        && records
+            .chunks_exact(6)
+            .all(|record| is_bool(record[4]) && is_bool(record[5])))
+    .then(|| (ZBotTiming::detect(records).format(), Confidence::Medium))
+}
+
+pub struct ZBotReplay {
+    inner: Replay,
+    timing: ZBotTiming,
+}
+
+fn read_bool(byte: u8) -> Result<bool, ReplayError> {
+    match byte {
+        TRUE | 1 => Ok(true),
+        FALSE | 0 => Ok(false),
+        _ => Err(ReplayError::ReadError(format!(
+            "invalid zbot boolean {byte:#04x}"
+        ))),
+    }
+}
+
+fn write_bool(value: bool) -> u8 {
+    if value { TRUE } else { FALSE }
+}
+
+impl ZBotReplay {
+    /// Set the zBot timing to write.
+    #[must_use]
+    pub fn with_timing(mut self, timing: ZBotTiming) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// The zBot timing this replay will be written with.
+    #[must_use]
+    pub fn timing(&self) -> ZBotTiming {
+        self.timing
+    }
+
+    /// Read a replay with the given timing, detecting it from the clicks when it is `None`.
+    fn read_timing<R: Read + Seek>(
+        reader: &mut R,
+        timing: Option<ZBotTiming>,
+    ) -> Result<Self, ReplayError> {
+        let delta = reader.read_f32()?;
+        let speedhack = reader.read_f32()?;
+
+        let mut records = Vec::new();
+        reader.read_to_end(&mut records)?;
+        if records.len() % 6 != 0 {
+            return Err(ReplayError::ReadError(
+                "zbot replay has a truncated click".to_string(),
+            ));
+        }
+
+        let timing = timing.unwrap_or_else(|| ZBotTiming::detect(&records));
+        let actions = records
+            .chunks_exact(6)
+            .map(|mut record| {
+                let time = match timing {
+                    ZBotTiming::XPos => TimePoint::XPos(f64::from(record.read_f32()?)),
+                    ZBotTiming::Frame => {
+                        let frame = record.read_i32()?;
+                        TimePoint::Frame(u64::try_from(frame).map_err(|_| {
+                            ReplayError::ReadError(format!("invalid zbot frame {frame}"))
+                        })?)
+                    }
+                };
+                let hold = read_bool(record.read_u8()?)?;
+                let player1 = read_bool(record.read_u8()?)?;
+
+                Ok(TimedAction {
+                    time,
+                    action: Action::Player {
+                        button: PlayerButton::Jump,
+                        hold,
+                        player2: !player1,
+                    },
+                    position: None,
+                })
+            })
+            .collect::<Result<_, ReplayError>>()?;
+
+        Ok(ZBotReplay {
+            inner: Replay {
+                meta: Box::new(ZBotMeta {
+                    delta,
+                    speedhack,
+                    tps: delta_tps(delta),
+                }),
+                actions,
+                format: timing.format(),
+                game_version: GameVersion::new(21, 130),
+                frame_counting: FrameCountingMethod::LevelTime,
+            },
+            timing,
+        })
+    }
+
+    /// Write a replay with the given timing.
+    #[allow(clippy::cast_possible_truncation)]
+    fn write_timing<W: Write>(
+        replay: &Replay,
+        timing: ZBotTiming,
+        writer: &mut W,
+    ) -> Result<(), ReplayError> {
+        let fields = replay.meta.fields();
+        let mut meta = ZBotMeta::from_fields(replay.meta.fields());
+
+        // Replays from other formats only carry a tick rate.
+        if !fields.contains_key("delta")
+            && let Some(tps) = fields.get("tps").and_then(|f| f.decode::<f64>(f.encode()))
+        {
+            meta.delta = (1.0 / tps) as f32;
+        }
+
+        writer.write_f32(meta.delta)?;
+        writer.write_f32(meta.speedhack)?;
+
+        for timed in &replay.actions {
+            let Action::Player {
+                button: PlayerButton::Jump,
+                hold,
+                player2,
+            } = timed.action
+            else {
+                if matches!(timed.action, Action::Empty) {
+                    continue;
+                }
+
+                return Err(ReplayError::WriteError(format!(
+                    "zbot does not support {:?} actions",
+                    timed.action
+                )));
+            };
+
+            match (timing, timed.time) {
+                (ZBotTiming::XPos, TimePoint::XPos(x)) => writer.write_f32(x as f32)?,
+                (ZBotTiming::Frame, TimePoint::Frame(frame)) => {
+                    writer.write_i32(i32::try_from(frame).map_err(|_| {
+                        ReplayError::WriteError(format!("frame {frame} is too large for zbot"))
+                    })?)?;
+                }
+                (ZBotTiming::XPos, _) => {
+                    return Err(ReplayError::WriteError(
+                        ".zbot only supports x position time points".to_string(),
+                    ));
+                }
+                (ZBotTiming::Frame, _) => {
+                    return Err(ReplayError::WriteError(
+                        ".zbf only supports frame time points".to_string(),
+                    ));
+                }
+            }
+
+            writer.write_u8(write_bool(hold))?;
+            writer.write_u8(write_bool(!player2))?;
+        }
+
+        Ok(())
+    }
+}
+
+impl ReplaySerializable for ZBotReplay {
+    fn new(replay: Replay) -> Self {
+        let timing = ZBotTiming::from_format(replay.format).unwrap_or(ZBotTiming::Frame);
+        ZBotReplay {
+            inner: replay,
+            timing,
+        }
+    }
+
+    fn into_replay(self) -> Replay {
+        self.inner
+    }
+
+    fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, ReplayError>
+    where
+        Self: Sized,
+    {
+        Self::read_timing(reader, None)
+    }
+
+    fn read_as<R: Read + Seek>(reader: &mut R, format: Format) -> Result<Self, ReplayError>
+    where
+        Self: Sized,
+    {
+        Self::read_timing(reader, ZBotTiming::from_format(format))
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), ReplayError> {
+        Self::write_timing(&self.inner, self.timing, writer)
+    }
+
+    fn write_as<W: Write>(
+        replay: &Replay,
+        format: Format,
+        writer: &mut W,
+    ) -> Result<(), ReplayError>
+    where
+        Self: Sized,
+    {
+        let timing = ZBotTiming::from_format(format).unwrap_or(ZBotTiming::Frame);
+        Self::write_timing(replay, timing, writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn click(time: TimePoint, hold: bool, player2: bool) -> TimedAction {
+        TimedAction {
+            time,
+            action: Action::Player {
+                button: PlayerButton::Jump,
+                hold,
+                player2,
+            },
+            position: None,
+        }
+    }
+
+    fn roundtrip(timing: ZBotTiming, actions: &[TimedAction]) -> Replay {
+        let replay = ZBotReplay::new(Replay {
+            meta: Box::new(ZBotMeta {
+                delta: 1.0 / 240.0,
+                speedhack: 0.5,
+                tps: 240.0,
+            }),
+            actions: actions.to_vec(),
+            format: timing.format(),
+            game_version: GameVersion::new(21, 130),
+            frame_counting: FrameCountingMethod::LevelTime,
+        });
+
+        let mut buf = Vec::new();
+        replay.write(&mut buf).unwrap();
+
+        let read = ZBotReplay::read(&mut Cursor::new(buf)).unwrap();
+        assert_eq!(read.timing(), timing);
+
+        let read = read.into_replay();
+        let meta = ZBotMeta::from_fields(read.meta.fields());
+        assert!(meta.delta.eq(&(1.0 / 240.0)));
+        assert!(meta.speedhack.eq(&0.5));
+        assert!(meta.tps.eq(&240.0));
+        read
+    }
+
+    #[test]
+    fn test_zbot_timings_roundtrip() {
+        let xpos = [
+            click(TimePoint::XPos(120.25), true, false),
+            click(TimePoint::XPos(180.5), false, true),
+        ];
+        let frames = [
+            click(TimePoint::Frame(0), true, false),
+            click(TimePoint::Frame(75), false, true),
+        ];
+
+        assert_eq!(roundtrip(ZBotTiming::XPos, &xpos).actions, xpos);
+        assert_eq!(roundtrip(ZBotTiming::Frame, &frames).actions, frames);
+    }
+
+    #[test]
+    fn test_zbot_delta_from_tps() {
+        #[derive(Meta)]
+        struct TpsMeta {
+            tps: f64,
+        }
+
+        let replay = ZBotReplay::new(Replay {
+            meta: Box::new(TpsMeta { tps: 240.0 }),
+            actions: Vec::new(),
+            format: Format::ZBotFrame,
+            game_version: GameVersion::new(21, 130),
+            frame_counting: FrameCountingMethod::LevelTime,
+        });
+
+        let mut buf = Vec::new();
+        replay.write(&mut buf).unwrap();
+        assert_eq!(buf[..4], (1.0_f32 / 240.0).to_le_bytes());
+    }
+
+    #[test]
+    fn test_zbot_reads_requested_timing() {
+        let mut data = Vec::new();
+        data.extend((1.0_f32 / 240.0).to_le_bytes());
+        data.extend(1.0_f32.to_le_bytes());
+        data.extend(0.0_f32.to_le_bytes());
+        data.extend(*b"11");
+
+        // An x position of 0 looks like frame 0, so only the format tells them apart.
+        let detected = ZBotReplay::read(&mut Cursor::new(&data)).unwrap();
+        assert_eq!(detected.timing(), ZBotTiming::Frame);
+
+        let read = ZBotReplay::read_as(&mut Cursor::new(&data), Format::ZBotXpos).unwrap();
+        assert_eq!(read.timing(), ZBotTiming::XPos);
+        assert_eq!(
+            read.into_replay().actions,
+            [click(TimePoint::XPos(0.0), true, false)]
+        );
+    }
+}
@@ -2,14 +2,22 @@ use darling::FromAttributes;
 use proc_macro::TokenStream;
 use quote::quote;
 
-#[derive(FromAttributes)]
-#[darling(attributes(meta))]
-struct MetaOptions {
-    #[darling(rename = "rename")]
-    renamed: Option<String>,
-    default: Option<syn::Expr>,
+mod options {
+    #![allow(clippy::needless_continue)] // Emitted by the `FromAttributes` derive.
+
+    use darling::FromAttributes;
+
+    #[derive(FromAttributes)]
+    #[darling(attributes(meta))]
+    pub(super) struct MetaOptions {
+        #[darling(rename = "rename")]
+        pub(super) renamed: Option<String>,
+        pub(super) default: Option<syn::Expr>,
+    }
 }
 
+use options::MetaOptions;
+
 fn from_each_named_field(
     transform: impl Fn(&syn::Field) -> proc_macro2::TokenStream,
     data_struct: &syn::DataStruct,